@@ -0,0 +1,21 @@
+use std::fs;
+
+/// Expose the version of the nmstate dependency as `NMSTATE_VERSION` since the library does not provide it.
+fn main() {
+    println!("cargo:rerun-if-changed=Cargo.lock");
+
+    let version = fs::read_to_string("Cargo.lock")
+        .ok()
+        .and_then(|lock| {
+            let mut lines = lock.lines();
+            lines.find(|line| *line == "name = \"nmstate\"")?;
+            lines
+                .next()?
+                .strip_prefix("version = \"")?
+                .strip_suffix('"')
+                .map(str::to_owned)
+        })
+        .unwrap_or_else(|| "unknown".to_owned());
+
+    println!("cargo:rustc-env=NMSTATE_VERSION={version}");
+}
@@ -1,437 +1,3959 @@
-use std::collections::HashMap;
+use std::collections::{BTreeSet, HashMap, HashSet};
 use std::fs;
+use std::io;
 use std::io::Write;
-use std::os::unix::fs::OpenOptionsExt;
+use std::os::unix::fs::{symlink, OpenOptionsExt, PermissionsExt};
 use std::path::{Path, PathBuf};
+use std::process::Command;
+use std::thread;
+use std::time::{Duration, Instant};
 
 use anyhow::{anyhow, Context};
-use log::{debug, info};
+use log::{debug, info, warn};
 use network_interface::{NetworkInterface, NetworkInterfaceConfig};
 use nmstate::InterfaceType;
+use serde::{Deserialize, Serialize};
 
-use crate::types::Host;
-use crate::HOST_MAPPING_FILE;
+use crate::error::ConfigError;
+use crate::fetch::SourceBundle;
+use crate::keyfile::Keyfile;
+use crate::matcher::{
+    find_host, HostMatcher, HostnameMatcher, MacMatcher, MatchStrategy, SerialMatcher,
+};
+use crate::remote::Remote;
+use crate::types::{Host, Interface, Metadata};
+use crate::{HOST_MAPPING_FILE, METADATA_FILE, NMSTATE_VERSION, PER_HOST_MAPPING_FILE};
 
 /// Destination directory to store the *.nmconnection files for NetworkManager.
-const STATIC_SYSTEM_CONNECTIONS_DIR: &str = "/etc/NetworkManager/system-connections";
+pub(crate) const STATIC_SYSTEM_CONNECTIONS_DIR: &str = "/etc/NetworkManager/system-connections";
 const RUNTIME_SYSTEM_CONNECTIONS_DIR: &str = "/var/run/NetworkManager/system-connections";
 /// Configuration directory for NetworkManager options.
 const CONFIG_DIR: &str = "/etc/NetworkManager/conf.d";
-const CONNECTION_FILE_EXT: &str = "nmconnection";
+pub(crate) const CONNECTION_FILE_EXT: &str = "nmconnection";
+/// File in the destination dir listing the connection files stored by `nmc apply --delete-missing`.
+/// NetworkManager ignores hidden files.
+const MANIFEST_FILE: &str = ".nmc-manifest";
+/// Start of the provenance header prepended to the keyfiles by `nmc generate --annotate`.
+pub(crate) const PROVENANCE_HEADER_PREFIX: &str = "# Generated by nmc ";
+/// NetworkManager config disabling the automatically created default wired connections.
+const NO_AUTO_DEFAULT_FILE: &str = "no-auto-default.conf";
+const NO_AUTO_DEFAULT_CONFIG: &str = "[main]\nno-auto-default=*\n";
 const HOSTNAME_FILE: &str = "/etc/hostname";
+/// Current hostname of the host which might differ from the configured one e.g. if set via DHCP.
+pub(crate) const KERNEL_HOSTNAME_FILE: &str = "/proc/sys/kernel/hostname";
+/// Serial number of the system as reported by the firmware. Only readable by root.
+pub(crate) const DMI_PRODUCT_SERIAL_FILE: &str = "/sys/class/dmi/id/product_serial";
+/// Kernel command line which may point to the config dir e.g. in initramfs-based provisioning.
+pub(crate) const KERNEL_CMDLINE_FILE: &str = "/proc/cmdline";
+/// Kernel command line parameter overriding the config dir e.g. `nmc.source=/run/initramfs/nmc`.
+const SOURCE_CMDLINE_PARAM: &str = "nmc.source";
+/// Config dirs probed in the given order when none is provided e.g. where they are mounted during early boot.
+pub(crate) const WELL_KNOWN_SOURCE_DIRS: [&str; 2] = ["/run/nmc", "/etc/nmc"];
+/// Config dir used if none of the well-known ones exists.
+const DEFAULT_SOURCE_DIR: &str = "config";
+/// Suffixes of the names of the connection files generated by nmstate for OVS.
+const OVS_BRIDGE_SUFFIX: &str = "-br";
+const OVS_INTERFACE_SUFFIX: &str = "-if";
+const OVS_PORT_SUFFIX: &str = "-port";
+/// Placeholder of a secret in the connection files e.g. `${SECRET:wifi_psk}`.
+const SECRET_PLACEHOLDER_PREFIX: &str = "${SECRET:";
+/// Prefix of the environment variables providing the secrets missing from the secrets file.
+const SECRET_ENV_PREFIX: &str = "NMC_SECRET_";
+/// Lists the network interfaces including their alternative names in JSON format.
+pub(crate) const LIST_ALTNAMES_CMD: &str = "ip -j link show";
 
-pub(crate) fn apply(source_dir: &str) -> Result<(), anyhow::Error> {
-    let hosts = parse_config(source_dir).context("Parsing config")?;
-    debug!("Loaded hosts config: {hosts:?}");
-
-    let network_interfaces = NetworkInterface::show()?;
-    debug!("Retrieved network interfaces: {network_interfaces:?}");
+/// Options adjusting the behaviour of [`apply`].
+#[derive(Default)]
+pub(crate) struct ApplyOptions {
+    /// Only copy the connection files of the listed interfaces
+    /// (either by their preconfigured or local name) if not empty.
+    pub(crate) interface_filter: Vec<String>,
+    /// Print the source and destination paths of the connection files without writing anything.
+    pub(crate) print_target: bool,
+    /// Print a JSON record of the host identification without applying anything.
+    pub(crate) explain: bool,
+    /// Apply the configuration to a remote host over SSH (e.g. `user@host`) instead of the local one.
+    pub(crate) remote: Option<String>,
+    /// Reload the NetworkManager connections on the remote host after applying the configuration.
+    pub(crate) remote_reload: bool,
+    /// Strategies identifying the host tried in the given order. Defaults to matching the MAC addresses.
+    pub(crate) match_strategies: Vec<MatchStrategy>,
+    /// Number of additional attempts to identify the host in case none of the NICs match.
+    pub(crate) retries: u32,
+    /// Delay before the first retry which is doubled after every subsequent attempt.
+    pub(crate) retry_interval: Duration,
+    /// Check the host mapping for semantic issues (e.g. duplicate hostnames) before applying.
+    pub(crate) validate_mapping: bool,
+    /// Reject multicast MAC addresses and warn about locally-administered ones in the host mapping.
+    pub(crate) check_mac_addresses: bool,
+    /// Overlay the generated sections and keys onto the existing connection files
+    /// instead of overwriting them.
+    pub(crate) merge: bool,
+    /// Minimum number of the host's MAC addresses which must be present among the local NICs.
+    pub(crate) required_interfaces: Option<usize>,
+    /// Require the MAC addresses of all physical interfaces of the host to be present among the local NICs.
+    pub(crate) exact_match: bool,
+    /// Minimum share of the host's physical interfaces which must be present among the local NICs
+    /// in order for the host to be matched by its MAC addresses.
+    pub(crate) min_match_ratio: Option<f64>,
+    /// Apply the configurations of all hosts matching the local NICs by their MAC addresses
+    /// (e.g. of network namespaces or containers sharing a machine) instead of only the best one,
+    /// storing each in its own `destination_dir/<hostname>` dir.
+    pub(crate) all_matches: bool,
+    /// Fail instead of logging warnings e.g. about unexpected entries in the host config dir.
+    pub(crate) strict: bool,
+    /// Download the config dir from a URL instead of reading it from the `source_dir`.
+    pub(crate) source: Option<SourceBundle>,
+    /// Path to a YAML file of `MAC address -> interface name` overrides taking precedence
+    /// over the names of the local NICs.
+    pub(crate) rename_map: Option<String>,
+    /// Keep the preconfigured interface names instead of adjusting them to the local ones.
+    pub(crate) no_rename: bool,
+    /// Link to the resolved targets of symlinked connection files instead of copying them.
+    pub(crate) preserve_symlinks: bool,
+    /// Re-read the stored connection files and check their contents and permissions.
+    pub(crate) verify: bool,
+    /// Write the connection files even if they already have the expected contents.
+    pub(crate) force: bool,
+    /// Remove the connection files stored by NMC which are no longer part of the host's config.
+    pub(crate) delete_missing: bool,
+    /// Name of the host mapping file in the config dir. Defaults to [`HOST_MAPPING_FILE`].
+    pub(crate) mapping_file: Option<String>,
+    /// Write the connection files of the ports before the ones of their controllers.
+    pub(crate) parents_first: bool,
+    /// Write the connection files to a staging dir and verify them before moving them
+    /// to the destination dir so that they only appear once complete.
+    pub(crate) staged: bool,
+    /// Name the destination files after the preconfigured interfaces even if the references
+    /// in their contents are adjusted to the local interface names.
+    pub(crate) keep_filenames: bool,
+    /// Path to a YAML file of `name -> value` secrets substituted for the `${SECRET:name}`
+    /// placeholders of the connection files.
+    pub(crate) secrets_file: Option<String>,
+    /// Path to a YAML file of recorded NICs used instead of the local ones. Only the connection
+    /// files are stored while the rest of the host is left untouched.
+    pub(crate) simulate_interfaces: Option<String>,
+    /// Point in time (see `--timeout`) after which no further changes are made to the host.
+    pub(crate) deadline: Option<Instant>,
+}
 
-    let host = identify_host(hosts, &network_interfaces)
-        .ok_or_else(|| anyhow!("None of the preconfigured hosts match local NICs"))?;
-    info!("Identified host: {}", host.hostname);
+/// Summary of an [`apply`] run.
+#[derive(Serialize, Debug, Default)]
+pub(crate) struct ApplySummary {
+    /// Hostname of the identified host.
+    pub(crate) hostname: String,
+    /// MAC addresses of the host which are present among the local NICs.
+    pub(crate) mac_addresses: Vec<String>,
+    /// Source and destination paths of the applied connection files.
+    pub(crate) files: Vec<AppliedFile>,
+    /// Interfaces whose names were adjusted to the local ones.
+    pub(crate) renames: Vec<Rename>,
+    /// Connection files removed since they are no longer part of the config.
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub(crate) deleted: Vec<PathBuf>,
+}
 
-    fs::write(HOSTNAME_FILE, &host.hostname).context("Setting hostname")?;
-    info!("Set hostname: {}", host.hostname);
+#[derive(Serialize, Debug, PartialEq)]
+pub(crate) struct AppliedFile {
+    pub(crate) source: PathBuf,
+    pub(crate) destination: PathBuf,
+}
 
-    let local_interfaces = detect_local_interfaces(&host, network_interfaces);
-    copy_connection_files(
-        host,
-        local_interfaces,
-        source_dir,
-        STATIC_SYSTEM_CONNECTIONS_DIR,
-    )
-    .context("Copying connection files")?;
+/// Preconfigured interface name which was replaced by the local one.
+#[derive(Serialize, Debug, PartialEq)]
+pub(crate) struct Rename {
+    pub(crate) from: String,
+    pub(crate) to: String,
+    /// MAC address of the interface, if any (e.g. not for VLANs).
+    pub(crate) mac_address: Option<String>,
+}
 
-    disable_wired_connections(CONFIG_DIR, RUNTIME_SYSTEM_CONNECTIONS_DIR)
-        .context("Disabling wired connections")
+/// Record of the host identification for automated troubleshooting.
+#[derive(Serialize, Debug)]
+pub(crate) struct Explanation {
+    /// MAC addresses of the local NICs.
+    pub(crate) local_mac_addresses: Vec<String>,
+    pub(crate) hosts: Vec<HostExplanation>,
+    /// Hostname of the identified host, if any.
+    pub(crate) selected: Option<String>,
+    /// Strategy which identified the host.
+    pub(crate) matched_by: Option<String>,
+    /// Why no host was identified.
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub(crate) reasons: Vec<String>,
 }
 
-fn parse_config(source_dir: &str) -> Result<Vec<Host>, anyhow::Error> {
-    let config_file = Path::new(source_dir).join(HOST_MAPPING_FILE);
+#[derive(Serialize, Debug)]
+pub(crate) struct HostExplanation {
+    pub(crate) hostname: String,
+    /// MAC addresses of the host's interfaces including the additional ones.
+    pub(crate) mac_addresses: Vec<String>,
+    /// MAC addresses taken into account by the matching which are present among the local NICs.
+    pub(crate) matched: Vec<String>,
+    /// Share of the host's physical interfaces present among the local NICs.
+    pub(crate) match_ratio: f64,
+}
 
-    let file = fs::File::open(config_file)?;
-    let mut hosts: Vec<Host> = serde_yaml::from_reader(file)?;
+/// Determine the config dir in case none was provided: the one given by the `nmc.source=`
+/// parameter of the kernel command line takes precedence over the first existing well-known dir,
+/// falling back to [`DEFAULT_SOURCE_DIR`].
+pub(crate) fn probe_source_dir(cmdline_file: &Path, well_known_dirs: &[&str]) -> String {
+    match fs::read_to_string(cmdline_file) {
+        Ok(cmdline) => {
+            if let Some(source_dir) = cmdline_source_dir(&cmdline) {
+                info!("Using config dir {source_dir} from the kernel command line");
+                return source_dir;
+            }
+        }
+        Err(err) => debug!("Reading {cmdline_file:?} failed: {err}"),
+    }
 
-    // Ensure lower case formatting.
-    hosts.iter_mut().for_each(|h| {
-        h.interfaces.iter_mut().for_each(|i| match &i.mac_address {
-            None => {}
-            Some(addr) => i.mac_address = Some(addr.to_lowercase()),
-        });
-    });
+    if let Some(source_dir) = well_known_dirs.iter().find(|dir| Path::new(dir).is_dir()) {
+        info!("Using well-known config dir {source_dir}");
+        return source_dir.to_string();
+    }
 
-    Ok(hosts)
+    DEFAULT_SOURCE_DIR.to_string()
 }
 
-/// Identify the preconfigured static host by matching the MAC address of at least one of the local network interfaces.
-fn identify_host(hosts: Vec<Host>, network_interfaces: &[NetworkInterface]) -> Option<Host> {
-    hosts.into_iter().find(|h| {
-        h.interfaces.iter().any(|interface| {
-            network_interfaces
-                .iter()
-                .filter(|nic| nic.mac_addr.is_some())
-                .any(|nic| nic.mac_addr == interface.mac_address)
-        })
-    })
+/// Value of the last `nmc.source=` parameter of the kernel command line, if any.
+fn cmdline_source_dir(cmdline: &str) -> Option<String> {
+    cmdline
+        .split_whitespace()
+        .filter_map(|param| param.split_once('='))
+        .filter(|(key, _)| *key == SOURCE_CMDLINE_PARAM)
+        .map(|(_, value)| value.trim_matches('"'))
+        .rev()
+        .find(|value| !value.is_empty())
+        .map(str::to_owned)
 }
 
-/// Detect and return the differences between the preconfigured interfaces and their local representations.
+/// Apply the network configurations of the identified host storing
+/// the *.nmconnection files under `destination_dir`.
 ///
-/// Examples:
-///     Desired Ethernet "eth0" -> Local "ens1f0"
-///     Desired VLAN "eth0.1365" -> Local "ens1f0.1365"
-fn detect_local_interfaces(
-    host: &Host,
-    network_interfaces: Vec<NetworkInterface>,
-) -> HashMap<String, String> {
-    let mut local_interfaces = HashMap::new();
+/// With [`ApplyOptions::all_matches`], the configurations of all hosts matching the local NICs are
+/// applied instead, storing the ones of each host in a `destination_dir/<hostname>` dir.
+/// Returns the summaries of the applied hosts.
+pub(crate) fn apply(
+    source_dir: &str,
+    destination_dir: &str,
+    options: &ApplyOptions,
+) -> Result<Vec<ApplySummary>, anyhow::Error> {
+    let mapping_file = options.mapping_file.as_deref().unwrap_or(HOST_MAPPING_FILE);
 
-    host.interfaces
-        .iter()
-        .filter(|interface| interface.interface_type == InterfaceType::Ethernet.to_string())
-        .for_each(|interface| {
-            let detected_interface = network_interfaces.iter().find(|nic| {
-                nic.mac_addr == interface.mac_address
-                    && !host.interfaces.iter().any(|i| i.logical_name == nic.name)
-            });
-            match detected_interface {
-                None => {}
-                Some(detected) => {
-                    local_interfaces.insert(interface.logical_name.clone(), detected.name.clone());
+    let fetched = options
+        .source
+        .as_ref()
+        .map(|source| source.fetch(mapping_file))
+        .transpose()
+        .context("Fetching config bundle")?;
+
+    let source_dir = match &fetched {
+        Some(config) => config
+            .path()
+            .to_str()
+            .ok_or_else(|| anyhow!("Determining fetched config path"))?,
+        None => source_dir,
+    };
+
+    let hosts = parse_config(source_dir, mapping_file, options.strict).context("Parsing config")?;
+    debug!("Loaded hosts config: {hosts:?}");
+
+    if options.validate_mapping {
+        validate_mapping(&hosts)?;
+    }
+
+    if options.check_mac_addresses {
+        for host in &hosts {
+            let (errors, warnings) = check_mac_address_bits(&host.interfaces);
+            if !errors.is_empty() {
+                return Err(ConfigError::InvalidMapping(format!(
+                    "Invalid MAC addresses of host {}: {}",
+                    host.hostname,
+                    errors.join("; ")
+                ))
+                .into());
+            }
+
+            for warning in warnings {
+                if options.strict {
+                    return Err(anyhow!("Host {}: {warning}", host.hostname));
                 }
-            };
-        });
+                warn!(host = host.hostname.as_str(); "{warning}");
+            }
+        }
+    }
 
-    // Look for non-Ethernet interfaces containing references to Ethernet ones differing from their preconfigured names.
-    local_interfaces.clone().iter().for_each(|(key, value)| {
-        host.interfaces
-            .iter()
-            .filter(|interface| {
-                interface.logical_name.contains(key) && !interface.logical_name.eq(key)
-            })
-            .for_each(|interface| {
-                let name = &interface.logical_name;
-                local_interfaces.insert(name.clone(), name.replace(key, value));
-            })
-    });
+    if let Some(warning) = check_metadata(source_dir)? {
+        if options.strict {
+            return Err(anyhow!(warning));
+        }
+        warn!("{warning}");
+    }
 
-    local_interfaces
-}
+    let remote = options.remote.as_deref().map(Remote::new);
 
-/// Copy all *.nmconnection files from the preconfigured host dir to the
-/// appropriate NetworkManager dir (default `/etc/NetworkManager/system-connections`).
-fn copy_connection_files(
-    host: Host,
-    local_interfaces: HashMap<String, String>,
-    source_dir: &str,
-    destination_dir: &str,
-) -> Result<(), anyhow::Error> {
-    fs::create_dir_all(destination_dir).context("Creating destination dir")?;
+    let recorded = options
+        .simulate_interfaces
+        .as_deref()
+        .map(RecordedInterfaces::load)
+        .transpose()
+        .context("Loading simulated interfaces")?;
 
-    let host_config_dir = Path::new(source_dir).join(&host.hostname);
-    let host_config_dir = host_config_dir
-        .to_str()
-        .ok_or_else(|| anyhow!("Determining host config path"))?;
+    let interface_source: &dyn InterfaceSource = match (&remote, &recorded) {
+        (Some(remote), _) => remote,
+        (None, Some(recorded)) => recorded,
+        (None, None) => &LocalInterfaces,
+    };
+    let retrieve_interfaces = || interface_source.network_interfaces();
 
-    for interface in &host.interfaces {
-        info!("Processing interface '{}'...", &interface.logical_name);
+    let matchers = host_matchers(
+        &options.match_strategies,
+        options.min_match_ratio,
+        remote.as_ref(),
+    )?;
+
+    if options.explain {
+        let network_interfaces = retrieve_interfaces()?;
+        let explanation = explain(
+            &hosts,
+            &matchers,
+            &network_interfaces,
+            options.min_match_ratio,
+        );
+        println!("{}", serde_json::to_string(&explanation)?);
 
-        let mut filename = &interface.logical_name;
+        return Ok(Vec::new());
+    }
 
-        let filepath = keyfile_path(host_config_dir, filename)
-            .ok_or_else(|| anyhow!("Determining source keyfile path"))?;
+    let (host, network_interfaces) = identify_host_with_retries(
+        &hosts,
+        &matchers,
+        retrieve_interfaces,
+        options.retries,
+        options.retry_interval,
+    )?;
 
-        let mut contents = fs::read_to_string(filepath).context("Reading file")?;
+    let matched = if options.all_matches {
+        let matched = match_all_hosts(
+            &hosts,
+            &local_mac_addresses(&network_interfaces),
+            options.min_match_ratio,
+        );
+        let hostnames: Vec<&str> = matched.iter().map(|h| h.hostname.as_str()).collect();
+        info!("Identified hosts: {}", hostnames.join(", "));
 
-        // Update the name and all references of the host NIC in the settings file if there is a difference from the static config.
-        match local_interfaces.get(&interface.logical_name) {
-            None => {}
-            Some(local_name) => {
-                info!(
-                    "Using interface name '{}' instead of the preconfigured '{}'",
-                    local_name, interface.logical_name
-                );
+        matched.into_iter().cloned().collect()
+    } else {
+        info!(host = host.hostname.as_str(); "Identified host: {}", host.hostname);
+        vec![host]
+    };
+
+    let mut targets = Vec::new();
+    for host in matched {
+        // Each of the matched hosts is stored in its own dir e.g. for the NetworkManager of a container.
+        let destination_dir = if options.all_matches {
+            Path::new(destination_dir)
+                .join(&host.hostname)
+                .to_str()
+                .ok_or_else(|| anyhow!("Determining destination dir of {}", host.hostname))?
+                .to_owned()
+        } else {
+            destination_dir.to_owned()
+        };
+
+        if let Some(required) = options.required_interfaces {
+            check_required_interfaces(&host, &network_interfaces, required)?;
+        }
+
+        if options.exact_match {
+            check_exact_match(&host, &network_interfaces)?;
+        }
 
-                contents = contents.replace(&interface.logical_name, local_name);
-                filename = local_name;
+        for entry in find_unexpected_entries(&host, source_dir)? {
+            if options.strict {
+                return Err(anyhow!("Unexpected entry: {entry:?}"));
             }
+            warn!("Ignoring unexpected entry: {entry:?}");
         }
 
-        let destination = keyfile_path(destination_dir, filename)
-            .ok_or_else(|| anyhow!("Determining destination keyfile path"))?;
+        let mac_addresses = matched_mac_addresses(&host, &local_mac_addresses(&network_interfaces));
+        let local_interfaces =
+            local_interface_names(&host, network_interfaces.clone(), interface_source, options)?;
+        // The files of the interfaces excluded via `--interface` are still part of the config.
+        let managed_files: Vec<PathBuf> = if options.delete_missing {
+            connection_targets(
+                &host,
+                &local_interfaces,
+                source_dir,
+                &destination_dir,
+                options.keep_filenames,
+            )?
+            .into_iter()
+            .map(|(_, destination)| destination)
+            .collect()
+        } else {
+            Vec::new()
+        };
+        let mut host = filter_interfaces(host, &local_interfaces, &options.interface_filter)?;
+        if options.parents_first {
+            order_parents_first(&mut host);
+        }
 
-        fs::OpenOptions::new()
-            .create(true)
-            .truncate(true)
-            .write(true)
-            .mode(0o600)
-            .open(&destination)
-            .context("Creating file")?
-            .write_all(contents.as_bytes())
-            .context("Writing file")?;
+        let summary = ApplySummary {
+            hostname: host.hostname.clone(),
+            mac_addresses,
+            files: resolve_targets(
+                &host,
+                &local_interfaces,
+                source_dir,
+                &destination_dir,
+                options.keep_filenames,
+            )?
+            .into_iter()
+            .map(|(source, destination)| AppliedFile {
+                source,
+                destination,
+            })
+            .collect(),
+            renames: renamed_interfaces(&host, &local_interfaces),
+            deleted: Vec::new(),
+        };
+
+        targets.push((
+            host,
+            local_interfaces,
+            destination_dir,
+            managed_files,
+            summary,
+        ));
     }
 
-    Ok(())
-}
+    if options.print_target {
+        for (_, _, _, _, summary) in &targets {
+            for file in &summary.files {
+                println!(
+                    "{} -> {}",
+                    file.source.display(),
+                    file.destination.display()
+                );
+            }
+        }
 
-fn keyfile_path(dir: &str, filename: &str) -> Option<PathBuf> {
-    if dir.is_empty() || filename.is_empty() {
-        return None;
+        return Ok(targets
+            .into_iter()
+            .map(|(_, _, _, _, summary)| summary)
+            .collect());
     }
 
-    let mut destination = Path::new(dir).join(filename).into_os_string();
+    let secrets = match &options.secrets_file {
+        Some(path) => load_secrets(path, options.strict).context("Loading secrets")?,
+        None => HashMap::new(),
+    };
 
-    // Manually append the extension since Path::with_extension() would overwrite a portion of the
-    // filename (i.e. interface name) in the cases where the interface name contains one or more dots
-    destination.push(".");
-    destination.push(CONNECTION_FILE_EXT);
+    let mut summaries = Vec::new();
+    for (host, local_interfaces, destination_dir, managed_files, mut summary) in targets {
+        if let Some(remote) = &remote {
+            apply_remote(
+                remote,
+                host,
+                local_interfaces,
+                source_dir,
+                &destination_dir,
+                &secrets,
+                options,
+            )?;
 
-    Some(destination.into())
-}
+            summaries.push(summary);
+            continue;
+        }
 
-fn disable_wired_connections(config_dir: &str, conn_dir: &str) -> Result<(), anyhow::Error> {
-    let _ = fs::remove_dir_all(conn_dir);
-    fs::create_dir_all(conn_dir).context(format!("Recreating {} directory", conn_dir))?;
+        check_writable(&destination_dir, "--destination-dir")?;
 
-    fs::create_dir_all(config_dir).context(format!("Creating {} directory", config_dir))?;
+        // The simulated host is not the one NMC is running on and neither is any of multiple matched ones,
+        // so only the connection files are stored.
+        if recorded.is_none() && !options.all_matches {
+            check_deadline(options, "setting the hostname")?;
+            fs::write(HOSTNAME_FILE, &host.hostname).context("Setting hostname")?;
+            info!(host = host.hostname.as_str(); "Set hostname: {}", host.hostname);
+        }
 
-    let config_path = Path::new(config_dir).join("no-auto-default.conf");
-    let config_contents = "[main]\nno-auto-default=*\n";
+        let stored = copy_connection_files(
+            host,
+            local_interfaces,
+            source_dir,
+            &destination_dir,
+            &secrets,
+            options,
+        )
+        .context("Copying connection files")?;
 
-    fs::OpenOptions::new()
-        .create(true)
-        .truncate(true)
-        .write(true)
-        .open(config_path)
-        .context("Creating config file")?
-        .write_all(config_contents.as_bytes())
-        .context("Writing config file")
+        if options.verify {
+            verify_connection_files(&stored).context("Verifying connection files")?;
+        }
+
+        if options.delete_missing {
+            check_deadline(options, "deleting the missing connection files")?;
+            summary.deleted = delete_missing_files(Path::new(&destination_dir), &managed_files)
+                .context("Deleting missing connection files")?;
+        }
+
+        summaries.push(summary);
+    }
+
+    // The NetworkManager instances of multiple matched hosts don't use the machine-wide config.
+    if remote.is_none() && recorded.is_none() && !options.all_matches {
+        check_deadline(options, "disabling the wired connections")?;
+        disable_wired_connections(CONFIG_DIR, RUNTIME_SYSTEM_CONNECTIONS_DIR)
+            .context("Disabling wired connections")?;
+    }
+
+    Ok(summaries)
 }
 
-#[cfg(test)]
-mod tests {
-    use std::collections::HashMap;
-    use std::path::{Path, PathBuf};
-    use std::{fs, io};
+/// Abort with a timeout instead of starting the given step once the deadline has passed
+/// so that a timed out apply leaves the host as it was before that step.
+fn check_deadline(options: &ApplyOptions, step: &str) -> Result<(), anyhow::Error> {
+    match options.deadline {
+        Some(deadline) if Instant::now() >= deadline => {
+            Err(io::Error::new(io::ErrorKind::TimedOut, format!("Timed out before {step}")).into())
+        }
+        _ => Ok(()),
+    }
+}
 
-    use network_interface::NetworkInterface;
+/// Source of the NICs of the host the configurations are applied to.
+pub(crate) trait InterfaceSource {
+    fn network_interfaces(&self) -> Result<Vec<NetworkInterface>, anyhow::Error>;
 
-    use crate::apply_conf::{
-        copy_connection_files, detect_local_interfaces, disable_wired_connections, identify_host,
-        keyfile_path, parse_config,
-    };
-    use crate::types::{Host, Interface};
+    /// Alternative names of the NICs keyed by their primary names.
+    fn altnames(&self) -> Result<HashMap<String, Vec<String>>, anyhow::Error>;
+}
 
-    #[test]
-    fn disable_wired_conn() {
-        assert!(disable_wired_connections("config", "connections").is_ok());
+/// NICs of the host NMC is running on.
+pub(crate) struct LocalInterfaces;
 
-        assert!(Path::new("config").exists());
-        assert!(Path::new("connections").exists());
+impl InterfaceSource for LocalInterfaces {
+    fn network_interfaces(&self) -> Result<Vec<NetworkInterface>, anyhow::Error> {
+        NetworkInterface::show().context("Retrieving network interfaces")
+    }
 
-        let config_contents = fs::read_to_string("config/no-auto-default.conf").unwrap();
-        assert_eq!(config_contents, "[main]\nno-auto-default=*\n");
+    fn altnames(&self) -> Result<HashMap<String, Vec<String>>, anyhow::Error> {
+        local_altnames()
+    }
+}
 
-        // cleanup
-        assert!(fs::remove_dir_all("config").is_ok());
-        assert!(fs::remove_dir_all("connections").is_ok());
+/// NICs recorded in a YAML file (e.g. of a target machine) which are used instead of the local ones
+/// in order to simulate applying the configurations offline:
+///
+/// ```yaml
+/// - name: ens1f0
+///   mac_address: 00:11:22:33:44:55
+///   altnames: [enp1s0f0]
+/// ```
+pub(crate) struct RecordedInterfaces {
+    interfaces: Vec<RecordedInterface>,
+}
+
+#[derive(Deserialize, Debug)]
+struct RecordedInterface {
+    name: String,
+    mac_address: String,
+    #[serde(default)]
+    altnames: Vec<String>,
+}
+
+impl RecordedInterfaces {
+    pub(crate) fn load(path: &str) -> Result<Self, anyhow::Error> {
+        let contents = fs::read_to_string(path).context(format!("Reading {path}"))?;
+        let mut interfaces: Vec<RecordedInterface> =
+            serde_yaml::from_str(&contents).context(format!("Parsing {path}"))?;
+
+        for interface in &mut interfaces {
+            if !is_valid_mac_address(&interface.mac_address) {
+                return Err(anyhow!(
+                    "Invalid MAC address '{}' of interface '{}' in {path}",
+                    interface.mac_address,
+                    interface.name
+                ));
+            }
+            interface.mac_address = interface.mac_address.to_lowercase();
+        }
+
+        Ok(RecordedInterfaces { interfaces })
     }
+}
 
-    #[test]
-    fn identify_host_successfully() {
-        let hosts = vec![
-            Host {
-                hostname: "h1".to_string(),
-                interfaces: vec![Interface {
-                    logical_name: "eth0".to_string(),
-                    mac_address: Option::from("00:11:22:33:44:55".to_string()),
-                    interface_type: "ethernet".to_string(),
-                }],
-            },
-            Host {
-                hostname: "h2".to_string(),
-                interfaces: vec![Interface {
-                    logical_name: "".to_string(),
-                    mac_address: Option::from("10:10:10:10:10:10".to_string()),
-                    interface_type: "".to_string(),
-                }],
-            },
-        ];
-        let interfaces = [
-            NetworkInterface {
-                name: "eth0".to_string(),
-                mac_addr: Some("00:11:22:33:44:55".to_string()),
-                addr: vec![],
-                index: 0,
-            },
-            NetworkInterface {
-                name: "eth0".to_string(),
-                mac_addr: Some("00:10:20:30:40:50".to_string()),
+impl InterfaceSource for RecordedInterfaces {
+    fn network_interfaces(&self) -> Result<Vec<NetworkInterface>, anyhow::Error> {
+        Ok(self
+            .interfaces
+            .iter()
+            .map(|interface| NetworkInterface {
+                name: interface.name.clone(),
+                mac_addr: Some(interface.mac_address.clone()),
                 addr: vec![],
                 index: 0,
-            },
-        ];
+            })
+            .collect())
+    }
 
-        let host = identify_host(hosts, &interfaces).unwrap();
-        assert_eq!(host.hostname, "h1");
-        assert_eq!(
-            host.interfaces,
-            vec![Interface {
-                logical_name: "eth0".to_string(),
-                mac_address: Option::from("00:11:22:33:44:55".to_string()),
-                interface_type: "ethernet".to_string(),
-            }]
-        );
+    fn altnames(&self) -> Result<HashMap<String, Vec<String>>, anyhow::Error> {
+        Ok(self
+            .interfaces
+            .iter()
+            .filter(|interface| !interface.altnames.is_empty())
+            .map(|interface| (interface.name.clone(), interface.altnames.clone()))
+            .collect())
+    }
+}
+
+/// Determine the local names of the host interfaces which differ from the preconfigured ones
+/// unless renaming is disabled.
+fn local_interface_names(
+    host: &Host,
+    network_interfaces: Vec<NetworkInterface>,
+    interface_source: &dyn InterfaceSource,
+    options: &ApplyOptions,
+) -> Result<HashMap<String, String>, anyhow::Error> {
+    if options.no_rename {
+        debug!("Keeping the preconfigured interface names");
+        return Ok(HashMap::new());
     }
 
-    #[test]
-    fn identify_host_fails() {
-        let hosts = vec![
-            Host {
-                hostname: "h1".to_string(),
-                interfaces: vec![Interface {
-                    logical_name: "eth0".to_string(),
-                    mac_address: Option::from("10:20:30:40:50:60".to_string()),
-                    interface_type: "ethernet".to_string(),
-                }],
-            },
-            Host {
-                hostname: "h2".to_string(),
-                interfaces: vec![Interface {
-                    logical_name: "".to_string(),
-                    mac_address: Option::from("00:10:20:30:40:50".to_string()),
-                    interface_type: "".to_string(),
-                }],
-            },
-        ];
-        let interfaces = [NetworkInterface {
-            name: "eth0".to_string(),
-            mac_addr: Some("00:11:22:33:44:55".to_string()),
-            addr: vec![],
-            index: 0,
-        }];
+    let rename_map = match &options.rename_map {
+        Some(path) => load_rename_map(path).context("Loading rename map")?,
+        None => HashMap::new(),
+    };
+    for mac in rename_map.keys() {
+        if !network_interfaces
+            .iter()
+            .any(|nic| nic.mac_addr.as_ref() == Some(mac))
+        {
+            warn!("MAC address {mac} of the rename map is not present locally");
+        }
+    }
+
+    let altnames = interface_source.altnames().unwrap_or_else(|err| {
+        debug!("Retrieving alternative interface names failed: {err:#}");
+        HashMap::new()
+    });
+
+    for interface in &host.interfaces {
+        let Some(name) = &interface.predictable_name else {
+            continue;
+        };
+        let mismatched = network_interfaces.iter().find(|nic| {
+            nic.mac_addr.is_some()
+                && nic.mac_addr == interface.mac_address
+                && nic.name != *name
+                && !altnames.get(&nic.name).is_some_and(|a| a.contains(name))
+        });
+        if let Some(nic) = mismatched {
+            warn!(
+                "NIC {} of interface '{}' is not named after its predictable name '{name}'",
+                nic.name, interface.logical_name
+            );
+        }
+    }
+
+    Ok(detect_local_interfaces(
+        host,
+        network_interfaces,
+        &rename_map,
+        &altnames,
+    ))
+}
+
+/// Ensure that the destination dir can be written to before making any changes
+/// to the host, e.g. in case the root filesystem is mounted as read-only.
+///
+/// Nothing is left behind: a dir which does not exist yet is not created, and
+/// the nearest existing one it would be created in is probed instead.
+///
+/// The `option` selecting the dir is suggested as an alternative in the errors.
+pub(crate) fn check_writable(destination_dir: &str, option: &str) -> Result<(), anyhow::Error> {
+    let existing = Path::new(destination_dir)
+        .ancestors()
+        .find(|dir| dir.as_os_str().is_empty() || dir.exists())
+        .filter(|dir| !dir.as_os_str().is_empty())
+        .unwrap_or(Path::new("."));
+
+    let probe = existing.join(".nmc-write-check");
+    let result = fs::File::create(&probe).and_then(|_| fs::remove_file(probe));
+
+    match result {
+        Ok(_) => Ok(()),
+        Err(err) if err.kind() == io::ErrorKind::ReadOnlyFilesystem => Err(err).context(format!(
+            "Destination {destination_dir} is read-only; remount or use {option}"
+        )),
+        Err(err) if err.kind() == io::ErrorKind::PermissionDenied => Err(err).context(format!(
+            "Destination {destination_dir} is not writable; check permissions or use {option}"
+        )),
+        Err(err) => Err(err).context(format!("Checking destination {destination_dir}")),
+    }
+}
+
+/// Perform the same steps as a local apply on the remote host.
+fn apply_remote(
+    remote: &Remote,
+    host: Host,
+    local_interfaces: HashMap<String, String>,
+    source_dir: &str,
+    destination_dir: &str,
+    secrets: &HashMap<String, String>,
+    options: &ApplyOptions,
+) -> Result<(), anyhow::Error> {
+    check_deadline(options, "setting the hostname")?;
+    remote
+        .write_file(Path::new(HOSTNAME_FILE), &host.hostname, 0o644)
+        .context("Setting hostname")?;
+    info!(host = host.hostname.as_str(); "Set hostname: {}", host.hostname);
+
+    for (filename, contents) in
+        read_connection_files(&host, &local_interfaces, source_dir, options.keep_filenames)?
+    {
+        check_deadline(options, "copying the connection files")?;
+        let destination = keyfile_path(destination_dir, &filename)
+            .ok_or_else(|| anyhow!("Determining destination keyfile path"))?;
+        let contents = resolve_secrets(&contents, secrets)
+            .with_context(|| format!("Resolving secrets of {filename}"))?;
+
+        remote
+            .write_file(&destination, &contents, 0o600)
+            .context("Copying connection files")?;
+    }
+
+    check_deadline(options, "disabling the wired connections")?;
+    remote
+        .run(
+            &format!("rm -rf {RUNTIME_SYSTEM_CONNECTIONS_DIR} && mkdir -p {RUNTIME_SYSTEM_CONNECTIONS_DIR}"),
+            None,
+        )
+        .context("Disabling wired connections")?;
+    remote
+        .write_file(
+            &Path::new(CONFIG_DIR).join(NO_AUTO_DEFAULT_FILE),
+            NO_AUTO_DEFAULT_CONFIG,
+            0o644,
+        )
+        .context("Disabling wired connections")?;
+
+    if options.remote_reload {
+        check_deadline(options, "reloading the connections")?;
+        remote
+            .run("nmcli connection reload", None)
+            .context("Reloading connections")?;
+        info!("Reloaded NetworkManager connections");
+    }
+
+    Ok(())
+}
+
+/// Compare the nmstate version recorded in the metadata file (if any) with the expected one
+/// and return a warning if their major or minor versions differ.
+fn check_metadata(source_dir: &str) -> Result<Option<String>, anyhow::Error> {
+    let metadata_path = Path::new(source_dir).join(METADATA_FILE);
+
+    let contents = match fs::read_to_string(&metadata_path) {
+        Ok(contents) => contents,
+        Err(err) if err.kind() == io::ErrorKind::NotFound => {
+            debug!("No metadata file found in {source_dir}");
+            return Ok(None);
+        }
+        Err(err) => return Err(err).context("Reading metadata file"),
+    };
+
+    let metadata: Metadata = serde_yaml::from_str(&contents).context("Parsing metadata file")?;
+    debug!("Loaded metadata: {metadata:?}");
+
+    let minor_version = |version: &str| version.split('.').take(2).collect::<Vec<_>>().join(".");
+
+    if minor_version(&metadata.nmstate_version) == minor_version(NMSTATE_VERSION) {
+        return Ok(None);
+    }
+
+    Ok(Some(format!(
+        "Config was generated with nmstate {} (nmc {}) while nmstate {NMSTATE_VERSION} is expected",
+        metadata.nmstate_version, metadata.nmc_version
+    )))
+}
+
+/// Parse the host mapping. Hosts without interfaces are reported as warnings
+/// (or errors in `strict` mode) since they can never be identified.
+pub(crate) fn parse_config(
+    source_dir: &str,
+    mapping_file: &str,
+    strict: bool,
+) -> Result<Vec<Host>, anyhow::Error> {
+    let config_file = Path::new(source_dir).join(mapping_file);
+
+    let mut hosts = match fs::File::open(config_file) {
+        Ok(file) => read_mapping(file)?,
+        // Fall back to the mappings stored in the host dirs by `generate --mapping-scope per-host`.
+        Err(err) if err.kind() == io::ErrorKind::NotFound => {
+            match read_per_host_mappings(source_dir) {
+                Ok(hosts) if !hosts.is_empty() => hosts,
+                _ => return Err(err.into()),
+            }
+        }
+        Err(err) => return Err(err.into()),
+    };
+
+    // Ensure lower case formatting.
+    hosts.iter_mut().for_each(|h| {
+        h.interfaces.iter_mut().for_each(|i| {
+            if let Some(addr) = &i.mac_address {
+                i.mac_address = Some(addr.to_lowercase());
+            }
+            i.additional_macs
+                .iter_mut()
+                .for_each(|addr| *addr = addr.to_lowercase());
+        });
+    });
+
+    for host in &hosts {
+        for interface in &host.interfaces {
+            let addresses = interface
+                .mac_address
+                .iter()
+                .chain(&interface.additional_macs);
+
+            for addr in addresses {
+                if !is_valid_mac_address(addr) {
+                    return Err(ConfigError::InvalidMapping(format!(
+                        "Invalid MAC address '{}' for host {} interface {}",
+                        addr, host.hostname, interface.logical_name
+                    ))
+                    .into());
+                }
+            }
+        }
+    }
+
+    for host in hosts.iter().filter(|h| h.interfaces.is_empty()) {
+        let message = format!(
+            "Host {} has no interfaces and can never be identified",
+            host.hostname
+        );
+        if strict {
+            return Err(ConfigError::InvalidMapping(message).into());
+        }
+        warn!("{message}");
+    }
+
+    Ok(hosts)
+}
+
+fn read_mapping(file: fs::File) -> Result<Vec<Host>, anyhow::Error> {
+    serde_yaml::from_reader(file)
+        .map_err(|err| ConfigError::InvalidMapping(format!("Invalid host mapping: {err}")).into())
+}
+
+/// Collect the mappings of the hosts stored in their dirs in the `source_dir`.
+fn read_per_host_mappings(source_dir: &str) -> Result<Vec<Host>, anyhow::Error> {
+    let mut paths = Vec::new();
+    for entry in fs::read_dir(source_dir)? {
+        let path = entry?.path().join(PER_HOST_MAPPING_FILE);
+        if path.is_file() {
+            paths.push(path);
+        }
+    }
+    paths.sort();
+
+    let mut hosts = Vec::new();
+    for path in paths {
+        let file = fs::File::open(&path).context(format!("Reading {path:?}"))?;
+        hosts.extend(read_mapping(file).context(format!("Parsing {path:?}"))?);
+    }
+
+    Ok(hosts)
+}
+
+/// Detect issues which are not caught by deserializing the host mapping e.g. blank or
+/// duplicate hostnames and hosts without interfaces.
+fn validate_mapping(hosts: &[Host]) -> anyhow::Result<()> {
+    let mut issues = Vec::new();
+    let mut hostnames = HashSet::new();
+
+    for (index, host) in hosts.iter().enumerate() {
+        let name = if host.hostname.trim().is_empty() {
+            issues.push(format!("host #{}: blank hostname", index + 1));
+            format!("#{}", index + 1)
+        } else {
+            if !hostnames.insert(host.hostname.as_str()) {
+                issues.push(format!("host {}: duplicate hostname", host.hostname));
+            }
+            host.hostname.clone()
+        };
+
+        if host.interfaces.is_empty() {
+            issues.push(format!("host {name}: no interfaces"));
+        }
+
+        let unnamed = host
+            .interfaces
+            .iter()
+            .filter(|i| i.logical_name.trim().is_empty())
+            .count();
+        if unnamed > 0 {
+            issues.push(format!(
+                "host {name}: {unnamed} interface(s) with empty logical name"
+            ));
+        }
+    }
+
+    if issues.is_empty() {
+        return Ok(());
+    }
+
+    Err(ConfigError::InvalidMapping(format!("Invalid host mapping: {}", issues.join("; "))).into())
+}
+
+/// Load the explicit `MAC address -> interface name` overrides from a YAML file e.g.
+/// `"00:11:22:33:44:55": eth0`.
+fn load_rename_map(path: &str) -> Result<HashMap<String, String>, anyhow::Error> {
+    let contents = fs::read_to_string(path).context(format!("Reading {path}"))?;
+    let rename_map: HashMap<String, String> =
+        serde_yaml::from_str(&contents).context(format!("Parsing {path}"))?;
+
+    rename_map
+        .into_iter()
+        .map(|(mac, name)| {
+            if !is_valid_mac_address(&mac) {
+                return Err(anyhow!("Invalid MAC address '{mac}' in {path}"));
+            }
+            if name.trim().is_empty() {
+                return Err(anyhow!(
+                    "Empty interface name for MAC address '{mac}' in {path}"
+                ));
+            }
+            Ok((mac.to_lowercase(), name))
+        })
+        .collect()
+}
+
+/// Validate that the address consists of six colon separated hex octets e.g. `00:11:22:33:44:55`.
+fn is_valid_mac_address(address: &str) -> bool {
+    let octets: Vec<&str> = address.split(':').collect();
+
+    octets.len() == 6
+        && octets
+            .iter()
+            .all(|octet| octet.len() == 2 && octet.chars().all(|c| c.is_ascii_hexdigit()))
+}
+
+/// Check the bits of the first octet of the interfaces' MAC addresses. Multicast addresses never belong
+/// to a NIC and are reported as errors while locally-administered addresses are usually virtual ones
+/// (or typos) which are unlikely to match a physical NIC and are reported as warnings.
+///
+/// Returns the errors and the warnings.
+pub(crate) fn check_mac_address_bits(interfaces: &[Interface]) -> (Vec<String>, Vec<String>) {
+    let mut errors = Vec::new();
+    let mut warnings = Vec::new();
+
+    for interface in interfaces {
+        for mac_address in interface
+            .mac_address
+            .iter()
+            .chain(&interface.additional_macs)
+        {
+            let Some(first_octet) = mac_address
+                .get(..2)
+                .and_then(|octet| u8::from_str_radix(octet, 16).ok())
+            else {
+                continue;
+            };
+
+            if first_octet & 0x01 != 0 {
+                errors.push(format!(
+                    "{} has the multicast MAC address {mac_address}",
+                    interface.logical_name
+                ));
+            } else if first_octet & 0x02 != 0 {
+                warnings.push(format!(
+                    "{} has the locally-administered MAC address {mac_address}",
+                    interface.logical_name
+                ));
+            }
+        }
+    }
+
+    (errors, warnings)
+}
+
+/// Build the matchers of the given strategies, matching the MAC addresses if none are given.
+fn host_matchers(
+    strategies: &[MatchStrategy],
+    min_match_ratio: Option<f64>,
+    remote: Option<&Remote>,
+) -> Result<Vec<Box<dyn HostMatcher>>, anyhow::Error> {
+    if strategies.is_empty() {
+        return Ok(vec![Box::new(MacMatcher::with_min_match_ratio(
+            min_match_ratio,
+        ))]);
+    }
+
+    strategies
+        .iter()
+        .map(|strategy| -> Result<Box<dyn HostMatcher>, anyhow::Error> {
+            match strategy {
+                MatchStrategy::Mac => {
+                    Ok(Box::new(MacMatcher::with_min_match_ratio(min_match_ratio)))
+                }
+                MatchStrategy::Hostname => {
+                    let hostname = match remote {
+                        Some(remote) => remote.hostname(),
+                        None => fs::read_to_string(KERNEL_HOSTNAME_FILE).map_err(Into::into),
+                    }
+                    .context("Retrieving hostname")?;
+                    debug!("Current hostname: {}", hostname.trim());
+
+                    Ok(Box::new(HostnameMatcher::new(&hostname)))
+                }
+                MatchStrategy::Serial => {
+                    let serial = match remote {
+                        Some(remote) => remote.serial(),
+                        None => fs::read_to_string(DMI_PRODUCT_SERIAL_FILE).map_err(Into::into),
+                    }
+                    .context("Retrieving serial number")?;
+                    debug!("Current serial number: {}", serial.trim());
+
+                    Ok(Box::new(SerialMatcher::new(&serial)))
+                }
+            }
+        })
+        .collect()
+}
+
+/// Retrieve the network interfaces and identify the host, re-attempting both with an
+/// exponential backoff since NICs might still be initializing (e.g. right after boot).
+fn identify_host_with_retries<F>(
+    hosts: &[Host],
+    matchers: &[Box<dyn HostMatcher>],
+    retrieve_interfaces: F,
+    retries: u32,
+    retry_interval: Duration,
+) -> Result<(Host, Vec<NetworkInterface>), anyhow::Error>
+where
+    F: Fn() -> Result<Vec<NetworkInterface>, anyhow::Error>,
+{
+    let mut interval = retry_interval;
+
+    for attempt in 0..=retries {
+        if attempt > 0 {
+            info!("Retrying host identification in {interval:?} (attempt {attempt}/{retries})...");
+            thread::sleep(interval);
+            interval *= 2;
+        }
+
+        let network_interfaces = retrieve_interfaces()?;
+        debug!("Retrieved network interfaces: {network_interfaces:?}");
+
+        if let Some(host) = find_host(matchers, hosts, &network_interfaces) {
+            return Ok((host, network_interfaces));
+        }
+
+        warn!("None of the preconfigured hosts match local NICs");
+    }
+
+    Err(ConfigError::NoMatchingHost.into())
+}
+
+/// Record how the host is identified among the given NICs the same way as when applying the configurations.
+fn explain(
+    hosts: &[Host],
+    matchers: &[Box<dyn HostMatcher>],
+    network_interfaces: &[NetworkInterface],
+    min_match_ratio: Option<f64>,
+) -> Explanation {
+    let local_mac_addresses = local_mac_addresses(network_interfaces);
+
+    let host_explanations: Vec<HostExplanation> = hosts
+        .iter()
+        .map(|host| HostExplanation {
+            hostname: host.hostname.clone(),
+            mac_addresses: host
+                .interfaces
+                .iter()
+                .flat_map(|i| i.mac_address.iter().chain(&i.additional_macs))
+                .cloned()
+                .collect(),
+            matched: matched_mac_addresses(host, &local_mac_addresses),
+            match_ratio: match_ratio(host, &local_mac_addresses),
+        })
+        .collect();
+
+    let selected = matchers.iter().find_map(|matcher| {
+        let host = matcher.find(hosts, network_interfaces)?;
+        Some((host.hostname.clone(), matcher.name().to_owned()))
+    });
+
+    let mut reasons = Vec::new();
+    if selected.is_none() {
+        if host_explanations.iter().all(|host| host.matched.is_empty()) {
+            reasons.push(
+                "None of the hosts' MAC addresses are present among the local NICs".to_owned(),
+            );
+        }
+
+        if let Some(min_match_ratio) = min_match_ratio {
+            for host in host_explanations
+                .iter()
+                .filter(|host| !host.matched.is_empty() && host.match_ratio < min_match_ratio)
+            {
+                reasons.push(format!(
+                    "Host {} only has {:.0}% of its physical interfaces present, below the minimum of {:.0}%",
+                    host.hostname,
+                    host.match_ratio * 100.0,
+                    min_match_ratio * 100.0
+                ));
+            }
+        }
+
+        for matcher in matchers {
+            reasons.push(format!("No confident match by {}", matcher.name()));
+        }
+    }
+
+    let (selected, matched_by) = selected.unzip();
+
+    Explanation {
+        local_mac_addresses,
+        hosts: host_explanations,
+        selected,
+        matched_by,
+        reasons,
+    }
+}
+
+/// Identify the preconfigured static host by matching the MAC address of at least one of the local network interfaces.
+///
+/// Only physical interfaces are taken into account since the MAC addresses of virtual ones
+/// (e.g. VLANs, bonds and bridges) are usually cloned from their physical counterparts.
+/// The additional MAC addresses of all interfaces (e.g. the ones of the ports of a bond) are matched as well.
+///
+/// In case multiple hosts match (e.g. cloned VMs sharing a MAC address), the one with the most
+/// matching MAC addresses is preferred, falling back to the first one listed in the mapping.
+pub(crate) fn identify_host(
+    hosts: Vec<Host>,
+    network_interfaces: &[NetworkInterface],
+) -> Option<Host> {
+    MacMatcher::default()
+        .find(&hosts, network_interfaces)
+        .cloned()
+}
+
+/// Find the host whose MAC addresses match the given ones the same way as when applying
+/// the configurations, e.g. in order to predict which host a machine will be identified as.
+///
+/// The host with the most matching MAC addresses is preferred, ties are resolved
+/// in favour of the first listed host.
+pub fn match_host<'a>(hosts: &'a [Host], mac_addresses: &[String]) -> Option<&'a Host> {
+    best_match(hosts.iter(), mac_addresses)
+}
+
+/// Find the host like [`match_host`] among the ones which have at least `min_match_ratio`
+/// of their physical interfaces present so that a single coincidentally matching MAC address
+/// (e.g. in a fleet of cloned VMs) does not select a host with many NICs.
+///
+/// The best candidate below the threshold is logged in case no host reaches it.
+pub(crate) fn match_host_with_ratio<'a>(
+    hosts: &'a [Host],
+    mac_addresses: &[String],
+    min_match_ratio: f64,
+) -> Option<&'a Host> {
+    let (eligible, rejected): (Vec<_>, Vec<_>) = hosts
+        .iter()
+        .map(|host| (host, match_ratio(host, mac_addresses)))
+        .filter(|(_, ratio)| *ratio > 0.0)
+        .partition(|(_, ratio)| *ratio >= min_match_ratio);
+
+    if eligible.is_empty() {
+        let best = rejected.into_iter().reduce(|best, candidate| {
+            if candidate.1 > best.1 {
+                candidate
+            } else {
+                best
+            }
+        });
+
+        if let Some((host, ratio)) = best {
+            warn!(
+                "Best matching host {} only has {:.0}% of its physical interfaces present, \
+                below the minimum of {:.0}%",
+                host.hostname,
+                ratio * 100.0,
+                min_match_ratio * 100.0
+            );
+        }
+        return None;
+    }
+
+    best_match(eligible.into_iter().map(|(host, _)| host), mac_addresses)
+}
+
+/// Find all hosts whose MAC addresses match the given ones, optionally only the ones with at least
+/// `min_match_ratio` of their physical interfaces present, in the order of the mapping.
+pub(crate) fn match_all_hosts<'a>(
+    hosts: &'a [Host],
+    mac_addresses: &[String],
+    min_match_ratio: Option<f64>,
+) -> Vec<&'a Host> {
+    hosts
+        .iter()
+        .filter(|host| !matched_mac_addresses(host, mac_addresses).is_empty())
+        .filter(|host| {
+            min_match_ratio.is_none_or(|ratio| match_ratio(host, mac_addresses) >= ratio)
+        })
+        .collect()
+}
+
+/// Share of the host's physical interfaces whose MAC addresses are present among the given ones.
+fn match_ratio(host: &Host, mac_addresses: &[String]) -> f64 {
+    let (present, total) = host
+        .interfaces
+        .iter()
+        .filter(|interface| is_physical_interface(interface))
+        .filter_map(|interface| interface.mac_address.as_ref())
+        .fold((0, 0), |(present, total), mac_address| {
+            let is_present = mac_addresses
+                .iter()
+                .any(|mac| mac.eq_ignore_ascii_case(mac_address));
+            (present + usize::from(is_present), total + 1)
+        });
+
+    if total == 0 {
+        return 0.0;
+    }
+
+    present as f64 / total as f64
+}
+
+fn best_match<'a>(
+    hosts: impl Iterator<Item = &'a Host>,
+    mac_addresses: &[String],
+) -> Option<&'a Host> {
+    let candidates: Vec<(&Host, usize)> = hosts
+        .map(|host| {
+            let score = matched_mac_addresses(host, mac_addresses).len();
+            (host, score)
+        })
+        .filter(|(_, score)| *score > 0)
+        .collect();
+
+    if candidates.len() > 1 {
+        let scores: Vec<String> = candidates
+            .iter()
+            .map(|(host, score)| format!("{} ({score})", host.hostname))
+            .collect();
+        warn!(
+            "Multiple hosts match the local NICs, preferring the one with the most matching MAC addresses: {}",
+            scores.join(", ")
+        );
+    }
+
+    candidates
+        .into_iter()
+        .reduce(|best, candidate| {
+            if candidate.1 > best.1 {
+                candidate
+            } else {
+                best
+            }
+        })
+        .map(|(host, _)| host)
+}
+
+pub(crate) fn local_mac_addresses(network_interfaces: &[NetworkInterface]) -> Vec<String> {
+    network_interfaces
+        .iter()
+        .filter_map(|nic| nic.mac_addr.clone())
+        .collect()
+}
+
+/// MAC addresses of the host's physical interfaces as well as the additional ones of all interfaces
+/// which are present among the given ones.
+fn matched_mac_addresses(host: &Host, mac_addresses: &[String]) -> Vec<String> {
+    host.interfaces
+        .iter()
+        .flat_map(|interface| {
+            interface
+                .mac_address
+                .iter()
+                .filter(|_| is_physical_interface(interface))
+                .chain(&interface.additional_macs)
+        })
+        .filter(|mac_address| {
+            mac_addresses
+                .iter()
+                .any(|mac| mac.eq_ignore_ascii_case(mac_address))
+        })
+        .cloned()
+        .collect()
+}
+
+pub(crate) fn is_physical_interface(interface: &Interface) -> bool {
+    interface.interface_type == InterfaceType::Ethernet.to_string()
+        || interface.interface_type == InterfaceType::InfiniBand.to_string()
+}
+
+/// Split the host's physical interfaces with MAC addresses into the ones present among the NICs and the missing ones.
+fn partition_physical_interfaces<'a>(
+    host: &'a Host,
+    network_interfaces: &[NetworkInterface],
+) -> (Vec<&'a Interface>, Vec<&'a Interface>) {
+    host.interfaces
+        .iter()
+        .filter(|i| is_physical_interface(i))
+        .filter(|i| i.mac_address.is_some())
+        .partition(|i| {
+            let mac_address = i.mac_address.as_deref().unwrap_or_default();
+            network_interfaces
+                .iter()
+                .filter_map(|nic| nic.mac_addr.as_ref())
+                .any(|mac| mac.eq_ignore_ascii_case(mac_address))
+        })
+}
+
+fn describe_interfaces(interfaces: &[&Interface]) -> String {
+    interfaces
+        .iter()
+        .map(|i| {
+            format!(
+                "{} ({})",
+                i.logical_name,
+                i.mac_address.as_deref().unwrap_or_default()
+            )
+        })
+        .collect::<Vec<_>>()
+        .join(", ")
+}
+
+/// Ensure that the MAC addresses of all physical interfaces of the host are present among the NICs
+/// so that the config is not applied on a different machine which shares some of them (e.g. a recycled NIC).
+fn check_exact_match(
+    host: &Host,
+    network_interfaces: &[NetworkInterface],
+) -> Result<(), anyhow::Error> {
+    let (_, missing) = partition_physical_interfaces(host, network_interfaces);
+
+    if missing.is_empty() {
+        return Ok(());
+    }
+
+    Err(ConfigError::MissingInterfaces(format!(
+        "Refusing to apply the config of host '{}' since not all of its MAC addresses are present, missing: {}",
+        host.hostname,
+        describe_interfaces(&missing)
+    ))
+    .into())
+}
+
+/// Ensure that at least `required` of the MAC addresses of the host's physical interfaces are present
+/// among the NICs so that a multi-NIC configuration is not applied on a host with a failed card.
+fn check_required_interfaces(
+    host: &Host,
+    network_interfaces: &[NetworkInterface],
+    required: usize,
+) -> Result<(), anyhow::Error> {
+    let (present, missing) = partition_physical_interfaces(host, network_interfaces);
+
+    if present.len() >= required {
+        return Ok(());
+    }
+
+    Err(ConfigError::MissingInterfaces(format!(
+        "Only {} of the required {required} interfaces of host '{}' are present, missing: {}",
+        present.len(),
+        host.hostname,
+        describe_interfaces(&missing)
+    ))
+    .into())
+}
+
+/// Find the entries in the host config dir which do not belong to any of the host's interfaces.
+fn find_unexpected_entries(host: &Host, source_dir: &str) -> Result<Vec<PathBuf>, anyhow::Error> {
+    let host_config_dir = Path::new(source_dir).join(&host.hostname);
+    let host_config_dir_str = host_config_dir
+        .to_str()
+        .ok_or_else(|| anyhow!("Determining host config path"))?;
+
+    let expected: HashSet<PathBuf> = host
+        .interfaces
+        .iter()
+        .flat_map(|interface| {
+            connection_file_suffixes(host_config_dir_str, interface)
+                .into_iter()
+                .filter_map(|suffix| {
+                    keyfile_path(
+                        host_config_dir_str,
+                        &format!("{}{suffix}", interface.logical_name),
+                    )
+                })
+        })
+        .collect();
+
+    let mut unexpected = Vec::new();
+    let host_mapping = host_config_dir.join(PER_HOST_MAPPING_FILE);
+
+    for entry in fs::read_dir(&host_config_dir).context("Reading host config dir")? {
+        let path = entry?.path();
+        if !expected.contains(&path) && path != host_mapping {
+            unexpected.push(path);
+        }
+    }
+
+    unexpected.sort();
+
+    Ok(unexpected)
+}
+
+/// Detect and return the differences between the preconfigured interfaces and their local representations.
+/// Ethernet interfaces whose MAC addresses are present in the `rename_map` use the names listed there instead.
+/// Otherwise, the predictable names recorded on generate (see `--naming-scheme`) are used as long as the NIC
+/// with the same MAC address is known under them, be it as its primary or alternative name.
+///
+/// The alternative names of the NICs (keyed by their primary names) are considered as well: no rename
+/// is needed if the preconfigured name is one of them, and they are used in case the primary name
+/// is already taken by another preconfigured interface.
+///
+/// Examples:
+///     Desired Ethernet "eth0" -> Local "ens1f0"
+///     Desired VLAN "eth0.1365" -> Local "ens1f0.1365"
+pub(crate) fn detect_local_interfaces(
+    host: &Host,
+    network_interfaces: Vec<NetworkInterface>,
+    rename_map: &HashMap<String, String>,
+    altnames: &HashMap<String, Vec<String>>,
+) -> HashMap<String, String> {
+    let mut local_interfaces = HashMap::new();
+
+    host.interfaces
+        .iter()
+        .filter(|interface| interface.interface_type == InterfaceType::Ethernet.to_string())
+        .for_each(|interface| {
+            let renamed = interface
+                .mac_address
+                .as_ref()
+                .and_then(|mac| rename_map.get(mac));
+            if let Some(name) = renamed {
+                if *name != interface.logical_name {
+                    local_interfaces.insert(interface.logical_name.clone(), name.clone());
+                }
+                return;
+            }
+
+            let predictable = interface.predictable_name.as_ref().filter(|name| {
+                network_interfaces.iter().any(|nic| {
+                    nic.mac_addr.is_some()
+                        && nic.mac_addr == interface.mac_address
+                        && (nic.name == **name
+                            || altnames.get(&nic.name).is_some_and(|a| a.contains(name)))
+                })
+            });
+            if let Some(name) = predictable {
+                if *name != interface.logical_name {
+                    local_interfaces.insert(interface.logical_name.clone(), name.clone());
+                }
+                return;
+            }
+
+            let detected_name = network_interfaces
+                .iter()
+                .filter(|nic| nic.mac_addr == interface.mac_address)
+                .find_map(|nic| {
+                    let nic_altnames = altnames
+                        .get(&nic.name)
+                        .map(Vec::as_slice)
+                        .unwrap_or_default();
+                    if nic.name == interface.logical_name
+                        || nic_altnames.contains(&interface.logical_name)
+                    {
+                        return Some(None);
+                    }
+
+                    std::iter::once(&nic.name)
+                        .chain(nic_altnames)
+                        .find(|name| !host.interfaces.iter().any(|i| &i.logical_name == *name))
+                        .map(Some)
+                });
+            if let Some(Some(name)) = detected_name {
+                local_interfaces.insert(interface.logical_name.clone(), name.clone());
+            }
+        });
+
+    // Look for non-Ethernet interfaces containing references to Ethernet ones differing from their preconfigured names.
+    local_interfaces.clone().iter().for_each(|(key, value)| {
+        host.interfaces
+            .iter()
+            .filter(|interface| {
+                interface.logical_name.contains(key) && !interface.logical_name.eq(key)
+            })
+            .for_each(|interface| {
+                let name = &interface.logical_name;
+                local_interfaces.insert(name.clone(), name.replace(key, value));
+            })
+    });
+
+    local_interfaces
+}
+
+/// Retrieve the alternative names of the local NICs keyed by their primary names.
+/// These are not exposed by `network_interface` and are read via iproute2 instead.
+pub(crate) fn local_altnames() -> Result<HashMap<String, Vec<String>>, anyhow::Error> {
+    let output = Command::new("sh")
+        .args(["-c", LIST_ALTNAMES_CMD])
+        .output()
+        .context("Running ip")?;
+
+    if !output.status.success() {
+        return Err(anyhow!(
+            "Listing links failed: {}",
+            String::from_utf8_lossy(&output.stderr).trim()
+        ));
+    }
+
+    parse_altnames(&String::from_utf8_lossy(&output.stdout))
+}
+
+/// Parse the output of [`LIST_ALTNAMES_CMD`] omitting the NICs without alternative names.
+pub(crate) fn parse_altnames(output: &str) -> Result<HashMap<String, Vec<String>>, anyhow::Error> {
+    #[derive(Deserialize)]
+    struct Link {
+        ifname: String,
+        #[serde(default)]
+        altnames: Vec<String>,
+    }
+
+    let links: Vec<Link> = serde_json::from_str(output).context("Parsing links")?;
+
+    Ok(links
+        .into_iter()
+        .filter(|link| !link.altnames.is_empty())
+        .map(|link| (link.ifname, link.altnames))
+        .collect())
+}
+
+/// List the host interfaces whose names differ from the local ones sorted by their preconfigured names.
+fn renamed_interfaces(host: &Host, local_interfaces: &HashMap<String, String>) -> Vec<Rename> {
+    let mut renames: Vec<Rename> = host
+        .interfaces
+        .iter()
+        .filter_map(|interface| {
+            let local_name = local_interfaces.get(&interface.logical_name)?;
+
+            Some(Rename {
+                from: interface.logical_name.clone(),
+                to: local_name.clone(),
+                mac_address: interface.mac_address.clone(),
+            })
+        })
+        .collect();
+
+    renames.sort_by(|a, b| a.from.cmp(&b.from));
+    renames
+}
+
+/// Retain only the host interfaces whose preconfigured or local name is present in the `interface_filter`.
+/// An empty filter retains all interfaces.
+fn filter_interfaces(
+    mut host: Host,
+    local_interfaces: &HashMap<String, String>,
+    interface_filter: &[String],
+) -> Result<Host, anyhow::Error> {
+    if interface_filter.is_empty() {
+        return Ok(host);
+    }
+
+    host.interfaces.retain(|interface| {
+        let name = &interface.logical_name;
+        let local_name = local_interfaces.get(name).unwrap_or(name);
+
+        interface_filter
+            .iter()
+            .any(|filter| filter == name || filter == local_name)
+    });
+
+    if host.interfaces.is_empty() {
+        return Err(anyhow!(
+            "None of the interfaces of host '{}' match the provided names: {}",
+            host.hostname,
+            interface_filter.join(", ")
+        ));
+    }
+
+    Ok(host)
+}
+
+/// Order the host interfaces so that the connection files of the ports are written before
+/// the ones of their controllers, e.g. the Ethernet ports before the bond (which lists
+/// their MAC addresses) and the bond before the bridge on top of it.
+/// This reduces transient activation failures when reloading the connections.
+fn order_parents_first(host: &mut Host) {
+    let rank = |interface: &Interface| {
+        let interface_type = interface.interface_type.as_str();
+
+        if is_physical_interface(interface) {
+            0
+        } else if !interface.additional_macs.is_empty()
+            || interface_type == InterfaceType::Bond.to_string()
+        {
+            1
+        } else if interface_type == InterfaceType::LinuxBridge.to_string()
+            || interface_type == InterfaceType::OvsBridge.to_string()
+            || interface_type == InterfaceType::Vrf.to_string()
+        {
+            3
+        } else {
+            // Interfaces stacked on top of the physical ones or the bonds e.g. VLANs.
+            2
+        }
+    };
+
+    host.interfaces.sort_by_key(rank);
+}
+
+/// Resolve the source and destination paths of the connection files of all host interfaces
+/// skipping broken symlinks.
+pub(crate) fn resolve_targets(
+    host: &Host,
+    local_interfaces: &HashMap<String, String>,
+    source_dir: &str,
+    destination_dir: &str,
+    keep_filenames: bool,
+) -> Result<Vec<(PathBuf, PathBuf)>, anyhow::Error> {
+    let mut targets = connection_targets(
+        host,
+        local_interfaces,
+        source_dir,
+        destination_dir,
+        keep_filenames,
+    )?;
+    targets.retain(|(source, _)| !is_broken_symlink(source));
+
+    Ok(targets)
+}
+
+/// Source and destination paths of the connection files of all host interfaces
+/// including the ones whose sources are broken symlinks.
+fn connection_targets(
+    host: &Host,
+    local_interfaces: &HashMap<String, String>,
+    source_dir: &str,
+    destination_dir: &str,
+    keep_filenames: bool,
+) -> Result<Vec<(PathBuf, PathBuf)>, anyhow::Error> {
+    let host_config_dir = Path::new(source_dir).join(&host.hostname);
+    let host_config_dir = host_config_dir
+        .to_str()
+        .ok_or_else(|| anyhow!("Determining host config path"))?;
+
+    let mut targets = Vec::new();
+
+    for interface in &host.interfaces {
+        let name = &interface.logical_name;
+        let stem = match local_interfaces.get(name) {
+            Some(local_name) if !keep_filenames => local_name,
+            _ => name,
+        };
+
+        for suffix in connection_file_suffixes(host_config_dir, interface) {
+            let source = keyfile_path(host_config_dir, &format!("{name}{suffix}"))
+                .ok_or_else(|| anyhow!("Determining source keyfile path"))?;
+            let destination = keyfile_path(destination_dir, &format!("{stem}{suffix}"))
+                .ok_or_else(|| anyhow!("Determining destination keyfile path"))?;
+
+            targets.push((source, destination));
+        }
+    }
+
+    Ok(targets)
+}
+
+/// Copy all *.nmconnection files from the preconfigured host dir to the
+/// appropriate NetworkManager dir (default `/etc/NetworkManager/system-connections`).
+///
+/// Existing files are either overwritten or, in merge mode, updated with the
+/// generated sections and keys preserving any other keys. Files which already have
+/// the expected contents are not written again unless forced.
+///
+/// Symlinked connection files are copied unless `preserve_symlinks` is set in which case
+/// the destination links to the resolved source file as long as it doesn't need adjustments
+/// for the local interface names.
+///
+/// Returns the paths of the stored files along with their expected contents.
+fn copy_connection_files(
+    host: Host,
+    local_interfaces: HashMap<String, String>,
+    source_dir: &str,
+    destination_dir: &str,
+    secrets: &HashMap<String, String>,
+    options: &ApplyOptions,
+) -> Result<Vec<(PathBuf, String)>, anyhow::Error> {
+    fs::create_dir_all(destination_dir).context("Creating destination dir")?;
+
+    // The staging dir is created in the destination dir in order to be on the same filesystem
+    // so that the files can be renamed atomically. NetworkManager ignores its subdirectories.
+    let staging_dir = options
+        .staged
+        .then(|| {
+            tempfile::Builder::new()
+                .prefix(".nmc-staging-")
+                .tempdir_in(destination_dir)
+        })
+        .transpose()
+        .context("Creating staging dir")?;
+    let mut staged = Vec::new();
+
+    let mut links = HashMap::new();
+    if options.preserve_symlinks {
+        for (source, destination) in resolve_targets(
+            &host,
+            &local_interfaces,
+            source_dir,
+            destination_dir,
+            options.keep_filenames,
+        )? {
+            if source.is_symlink() && source.file_name() == destination.file_name() {
+                let target = fs::canonicalize(&source).context(format!("Resolving {source:?}"))?;
+                links.insert(destination, target);
+            }
+        }
+    }
+
+    let mut stored = Vec::new();
+
+    for (filename, template) in
+        read_connection_files(&host, &local_interfaces, source_dir, options.keep_filenames)?
+    {
+        check_deadline(options, "copying the connection files")?;
+        let destination = keyfile_path(destination_dir, &filename)
+            .ok_or_else(|| anyhow!("Determining destination keyfile path"))?;
+        let mut contents = resolve_secrets(&template, secrets)
+            .with_context(|| format!("Resolving secrets of {filename}"))?;
+
+        // Files with secrets are written instead of linked since their targets
+        // only contain the placeholders.
+        if let Some(target) = links.get(&destination).filter(|_| contents == template) {
+            match fs::remove_file(&destination) {
+                Err(err) if err.kind() != io::ErrorKind::NotFound => {
+                    return Err(err).context(format!("Removing {destination:?}"));
+                }
+                _ => {}
+            }
+            symlink(target, &destination).context("Creating symlink")?;
+            debug!("Linked {destination:?} -> {target:?}");
+            stored.push((destination, contents));
+            continue;
+        }
+
+        if options.merge {
+            if let Some((merged, warnings)) =
+                merge_connection_file(&destination, &contents, &template)?
+            {
+                warnings.iter().for_each(|warning| warn!("{warning}"));
+                contents = merged;
+            }
+        }
+
+        if !options.force
+            && !destination.is_symlink()
+            && fs::read_to_string(&destination).is_ok_and(|existing| existing == contents)
+        {
+            debug!("Unchanged: {destination:?}");
+            stored.push((destination, contents));
+            continue;
+        }
+
+        if let Some(staging_dir) = &staging_dir {
+            let path = staging_dir.path().join(&filename);
+            write_connection_file(&path, &contents)?;
+            staged.push(((path, contents.clone()), destination.clone()));
+            stored.push((destination, contents));
+            continue;
+        }
+
+        // Replace previously preserved symlinks instead of writing to their targets.
+        if destination.is_symlink() {
+            fs::remove_file(&destination).context(format!("Removing {destination:?}"))?;
+        }
+
+        write_connection_file(&destination, &contents)?;
+        stored.push((destination, contents));
+    }
+
+    if !staged.is_empty() {
+        let (files, destinations): (Vec<_>, Vec<_>) = staged.into_iter().unzip();
+        verify_connection_files(&files).context("Verifying staged connection files")?;
+        check_deadline(options, "moving the staged connection files")?;
+
+        // Renaming replaces previously preserved symlinks instead of writing to their targets.
+        for ((path, _), destination) in files.iter().zip(destinations) {
+            fs::rename(path, &destination)
+                .context(format!("Moving {path:?} to {destination:?}"))?;
+            debug!("Moved staged {path:?} to {destination:?}");
+        }
+    }
+
+    Ok(stored)
+}
+
+/// Remove the connection files from the destination dir which were stored by NMC but are no longer
+/// part of the host's config e.g. since an interface was removed from its desired state.
+///
+/// The manifest is updated with the `managed` files, i.e. the destinations of all connection files
+/// of the host regardless of whether they were written by the current run, afterwards.
+fn delete_missing_files(
+    destination_dir: &Path,
+    managed: &[PathBuf],
+) -> Result<Vec<PathBuf>, anyhow::Error> {
+    let missing = find_missing_files(destination_dir, managed)?;
+
+    for path in &missing {
+        fs::remove_file(path).context(format!("Removing {path:?}"))?;
+        info!("Removed {path:?} which is no longer part of the config");
+    }
+
+    let manifest: String = managed_filenames(managed)
+        .into_iter()
+        .map(|filename| filename + "\n")
+        .collect();
+    let manifest_path = destination_dir.join(MANIFEST_FILE);
+    fs::write(&manifest_path, manifest).context(format!("Writing {manifest_path:?}"))?;
+
+    Ok(missing)
+}
+
+/// Find the connection files in the destination dir which were stored by NMC but are not
+/// among the `managed` ones.
+///
+/// The files are considered to be stored by NMC if they are listed in the manifest of a previous run
+/// or start with the provenance header of `nmc generate --annotate`. Any other files are left out.
+pub(crate) fn find_missing_files(
+    destination_dir: &Path,
+    managed: &[PathBuf],
+) -> Result<Vec<PathBuf>, anyhow::Error> {
+    let manifest_path = destination_dir.join(MANIFEST_FILE);
+    let manifest: HashSet<String> = match fs::read_to_string(&manifest_path) {
+        Ok(contents) => contents.lines().map(str::to_owned).collect(),
+        Err(err) if err.kind() == io::ErrorKind::NotFound => HashSet::new(),
+        Err(err) => return Err(err).context(format!("Reading {manifest_path:?}")),
+    };
+    let current = managed_filenames(managed);
+
+    let mut missing = Vec::new();
+    for entry in fs::read_dir(destination_dir).context("Reading destination dir")? {
+        let path = entry?.path();
+        let Some(filename) = path.file_name().map(|f| f.to_string_lossy().into_owned()) else {
+            continue;
+        };
+        if path
+            .extension()
+            .is_none_or(|ext| ext != CONNECTION_FILE_EXT)
+            || current.contains(&filename)
+        {
+            continue;
+        }
+
+        let owned = manifest.contains(&filename)
+            || fs::read_to_string(&path)
+                .is_ok_and(|contents| contents.starts_with(PROVENANCE_HEADER_PREFIX));
+        if owned {
+            missing.push(path);
+        }
+    }
+    missing.sort();
+
+    Ok(missing)
+}
+
+fn managed_filenames(managed: &[PathBuf]) -> BTreeSet<String> {
+    managed
+        .iter()
+        .filter_map(|path| Some(path.file_name()?.to_string_lossy().into_owned()))
+        .collect()
+}
+
+fn write_connection_file(path: &Path, contents: &str) -> Result<(), anyhow::Error> {
+    fs::OpenOptions::new()
+        .create(true)
+        .truncate(true)
+        .write(true)
+        .mode(0o600)
+        .open(path)
+        .context("Creating file")?
+        .write_all(contents.as_bytes())
+        .context("Writing file")
+}
+
+/// Load the secrets from a YAML file of `name -> value` pairs which is expected
+/// to only be accessible by its owner.
+fn load_secrets(path: &str, strict: bool) -> Result<HashMap<String, String>, anyhow::Error> {
+    let mode = fs::metadata(path)
+        .context(format!("Reading {path}"))?
+        .permissions()
+        .mode();
+    if mode & 0o077 != 0 {
+        let warning = format!(
+            "Secrets file {path} is accessible by other users (mode {:o})",
+            mode & 0o777
+        );
+        if strict {
+            return Err(anyhow!(warning));
+        }
+        warn!("{warning}");
+    }
+
+    let contents = fs::read_to_string(path).context(format!("Reading {path}"))?;
+    serde_yaml::from_str(&contents).context(format!("Parsing {path}"))
+}
+
+/// Substitute the `${SECRET:name}` placeholders with the values from the secrets file
+/// or the `NMC_SECRET_<name>` environment variables. Unresolved placeholders are an error.
+fn resolve_secrets(
+    contents: &str,
+    secrets: &HashMap<String, String>,
+) -> Result<String, anyhow::Error> {
+    let mut resolved = String::with_capacity(contents.len());
+    let mut rest = contents;
+
+    while let Some(start) = rest.find(SECRET_PLACEHOLDER_PREFIX) {
+        resolved.push_str(&rest[..start]);
+        rest = &rest[start + SECRET_PLACEHOLDER_PREFIX.len()..];
+
+        let end = rest
+            .find('}')
+            .ok_or_else(|| anyhow!("Unterminated secret placeholder"))?;
+        let name = &rest[..end];
+
+        let value = match secrets.get(name) {
+            Some(value) => value.clone(),
+            None => std::env::var(format!("{SECRET_ENV_PREFIX}{name}"))
+                .map_err(|_| anyhow!("Unresolved secret '{name}'"))?,
+        };
+        resolved.push_str(&value);
+        rest = &rest[end + 1..];
+    }
+    resolved.push_str(rest);
+
+    Ok(resolved)
+}
+
+/// Re-read the stored connection files and ensure that they have the expected contents
+/// and are only accessible by their owner (unless they are symlinks).
+fn verify_connection_files(files: &[(PathBuf, String)]) -> Result<(), anyhow::Error> {
+    for (path, expected) in files {
+        let contents = fs::read_to_string(path).context(format!("Reading {path:?}"))?;
+        if contents != *expected {
+            return Err(anyhow!(
+                "Contents of {path:?} do not match the applied config"
+            ));
+        }
+
+        if path.is_symlink() {
+            continue;
+        }
+
+        let mode = fs::metadata(path)
+            .context(format!("Reading metadata of {path:?}"))?
+            .permissions()
+            .mode()
+            & 0o777;
+        if mode != 0o600 {
+            return Err(anyhow!(
+                "Unexpected mode of {path:?}: {mode:o} instead of 600"
+            ));
+        }
+    }
+
+    debug!("Verified {} connection files", files.len());
+    Ok(())
+}
+
+/// Overlay the contents onto the existing connection file, if any, and return the result
+/// along with warnings about the overridden values.
+///
+/// The values of the keys which are set to secrets in the `template` (i.e. the contents
+/// before resolving the secrets) are redacted in the warnings.
+fn merge_connection_file(
+    destination: &Path,
+    contents: &str,
+    template: &str,
+) -> Result<Option<(String, Vec<String>)>, anyhow::Error> {
+    let existing = match fs::read_to_string(destination) {
+        Ok(existing) => existing,
+        Err(err) if err.kind() == io::ErrorKind::NotFound => return Ok(None),
+        Err(err) => return Err(err).context(format!("Reading {destination:?}")),
+    };
+
+    let mut keyfile = Keyfile::parse(&existing).context(format!("Parsing {destination:?}"))?;
+    let generated = Keyfile::parse(contents).context("Parsing generated keyfile")?;
+    let template = Keyfile::parse(template).context("Parsing keyfile template")?;
+
+    let warnings = keyfile
+        .merge(&generated)
+        .into_iter()
+        .map(|(key, old, new)| {
+            let secret = key
+                .split_once('.')
+                .and_then(|(section, name)| template.get(section, name))
+                .is_some_and(|value| value.contains(SECRET_PLACEHOLDER_PREFIX));
+            if secret {
+                format!("Overriding {key} in {destination:?}: <redacted>")
+            } else {
+                format!("Overriding {key} in {destination:?}: '{old}' -> '{new}'")
+            }
+        })
+        .collect();
+
+    Ok(Some((keyfile.to_string(), warnings)))
+}
+
+/// Read all *.nmconnection files from the preconfigured host dir and return them in the
+/// following format: `Vec<(interface_name, file_content)>` where the interface name (and all of
+/// its references in the contents) is already adjusted to the local one, if needed.
+pub(crate) fn read_connection_files(
+    host: &Host,
+    local_interfaces: &HashMap<String, String>,
+    source_dir: &str,
+    keep_filenames: bool,
+) -> Result<Vec<(String, String)>, anyhow::Error> {
+    let host_config_dir = Path::new(source_dir).join(&host.hostname);
+    let host_config_dir = host_config_dir
+        .to_str()
+        .ok_or_else(|| anyhow!("Determining host config path"))?;
+
+    let mut files = Vec::new();
+
+    for interface in &host.interfaces {
+        info!(
+            interface = interface.logical_name.as_str();
+            "Processing interface '{}'...", &interface.logical_name
+        );
+
+        let local_name = local_interfaces.get(&interface.logical_name);
+        if let Some(local_name) = local_name {
+            info!(
+                interface = interface.logical_name.as_str(), local_name = local_name.as_str();
+                "Using interface name '{}' instead of the preconfigured '{}'",
+                local_name, interface.logical_name
+            );
+        }
+
+        for suffix in connection_file_suffixes(host_config_dir, interface) {
+            let filename = format!("{}{suffix}", interface.logical_name);
+            let filepath = keyfile_path(host_config_dir, &filename)
+                .ok_or_else(|| anyhow!("Determining source keyfile path"))?;
+
+            if is_broken_symlink(&filepath) {
+                warn!("Skipping broken symlink: {filepath:?}");
+                continue;
+            }
+
+            let mut contents = fs::read_to_string(filepath).context("Reading file")?;
+
+            // Update the name and all references of the host NIC in the settings file if there is a difference from the static config.
+            match local_name {
+                None => files.push((filename, contents)),
+                Some(local_name) => {
+                    contents = contents.replace(&interface.logical_name, local_name);
+                    let stem = if keep_filenames {
+                        &interface.logical_name
+                    } else {
+                        local_name
+                    };
+                    files.push((format!("{stem}{suffix}"), contents));
+                }
+            }
+        }
+    }
+
+    Ok(files)
+}
+
+/// Suffixes of the names of the connection files belonging to the interface.
+///
+/// nmstate suffixes the names of the OVS connections since bridges, ports and interfaces
+/// may share the same name e.g. `br0-br.nmconnection`. Interfaces attached to OVS bridges
+/// have an additional `<name>-port.nmconnection` file.
+fn connection_file_suffixes(host_config_dir: &str, interface: &Interface) -> Vec<&'static str> {
+    let interface_type = interface.interface_type.as_str();
+
+    let suffix = if interface_type == InterfaceType::OvsBridge.to_string() {
+        OVS_BRIDGE_SUFFIX
+    } else if interface_type == InterfaceType::OvsInterface.to_string() {
+        OVS_INTERFACE_SUFFIX
+    } else {
+        ""
+    };
+
+    let mut suffixes = vec![suffix];
+
+    let port_file = keyfile_path(
+        host_config_dir,
+        &format!("{}{OVS_PORT_SUFFIX}", interface.logical_name),
+    );
+    if port_file.is_some_and(|path| path.exists()) {
+        suffixes.push(OVS_PORT_SUFFIX);
+    }
+
+    suffixes
+}
+
+fn is_broken_symlink(path: &Path) -> bool {
+    path.is_symlink() && !path.exists()
+}
+
+pub(crate) fn keyfile_path(dir: &str, filename: &str) -> Option<PathBuf> {
+    if dir.is_empty() || filename.is_empty() {
+        return None;
+    }
+
+    let mut destination = Path::new(dir).join(filename).into_os_string();
+
+    // Manually append the extension since Path::with_extension() would overwrite a portion of the
+    // filename (i.e. interface name) in the cases where the interface name contains one or more dots
+    destination.push(".");
+    destination.push(CONNECTION_FILE_EXT);
+
+    Some(destination.into())
+}
+
+fn disable_wired_connections(config_dir: &str, conn_dir: &str) -> Result<(), anyhow::Error> {
+    let _ = fs::remove_dir_all(conn_dir);
+    fs::create_dir_all(conn_dir).context(format!("Recreating {} directory", conn_dir))?;
+
+    fs::create_dir_all(config_dir).context(format!("Creating {} directory", config_dir))?;
+
+    let config_path = Path::new(config_dir).join(NO_AUTO_DEFAULT_FILE);
+
+    fs::OpenOptions::new()
+        .create(true)
+        .truncate(true)
+        .write(true)
+        .open(config_path)
+        .context("Creating config file")?
+        .write_all(NO_AUTO_DEFAULT_CONFIG.as_bytes())
+        .context("Writing config file")
+}
+
+#[cfg(test)]
+mod tests {
+    use std::cell::Cell;
+    use std::collections::HashMap;
+    use std::os::unix::fs::{symlink, PermissionsExt};
+    use std::path::{Path, PathBuf};
+    use std::time::{Duration, SystemTime};
+    use std::{fs, io};
+
+    use network_interface::NetworkInterface;
+
+    use crate::apply_conf::{
+        apply, check_exact_match, check_mac_address_bits, check_metadata,
+        check_required_interfaces, check_writable, copy_connection_files, delete_missing_files,
+        detect_local_interfaces, disable_wired_connections, explain, filter_interfaces,
+        find_unexpected_entries, identify_host, identify_host_with_retries, is_valid_mac_address,
+        keyfile_path, load_rename_map, load_secrets, local_interface_names, match_all_hosts,
+        match_host, match_host_with_ratio, merge_connection_file, order_parents_first,
+        parse_altnames, parse_config, probe_source_dir, read_connection_files, renamed_interfaces,
+        resolve_secrets, resolve_targets, validate_mapping, verify_connection_files, ApplyOptions,
+        InterfaceSource, RecordedInterfaces, Rename,
+    };
+    use crate::error::ConfigError;
+    use crate::matcher::{HostMatcher, MacMatcher};
+    use crate::types::{Host, Interface};
+    use crate::{HOST_MAPPING_FILE, METADATA_FILE, NMSTATE_VERSION, PER_HOST_MAPPING_FILE};
+
+    fn interface(name: &str, mac: Option<&str>, interface_type: &str) -> Interface {
+        Interface {
+            logical_name: name.to_string(),
+            mac_address: mac.map(str::to_string),
+            additional_macs: Vec::new(),
+            predictable_name: None,
+            interface_type: interface_type.to_string(),
+        }
+    }
+
+    /// NICs fabricated by the tests instead of the ones of the host running them.
+    #[derive(Default)]
+    struct FakeInterfaces {
+        interfaces: Vec<NetworkInterface>,
+        altnames: HashMap<String, Vec<String>>,
+    }
+
+    impl FakeInterfaces {
+        fn new(nics: &[(&str, &str, &[&str])]) -> Self {
+            FakeInterfaces {
+                interfaces: nics
+                    .iter()
+                    .map(|(name, mac, _)| NetworkInterface {
+                        name: name.to_string(),
+                        mac_addr: Some(mac.to_string()),
+                        addr: vec![],
+                        index: 0,
+                    })
+                    .collect(),
+                altnames: nics
+                    .iter()
+                    .filter(|(_, _, altnames)| !altnames.is_empty())
+                    .map(|(name, _, altnames)| {
+                        let altnames = altnames.iter().map(|a| a.to_string()).collect();
+                        (name.to_string(), altnames)
+                    })
+                    .collect(),
+            }
+        }
+    }
+
+    impl InterfaceSource for FakeInterfaces {
+        fn network_interfaces(&self) -> Result<Vec<NetworkInterface>, anyhow::Error> {
+            Ok(self.interfaces.clone())
+        }
+
+        fn altnames(&self) -> Result<HashMap<String, Vec<String>>, anyhow::Error> {
+            Ok(self.altnames.clone())
+        }
+    }
+
+    /// Run the same steps as `apply` with the fabricated NICs, without touching the host running the
+    /// tests, and return the identified host along with its interfaces whose local names differ.
+    fn apply_with_interfaces(
+        source_dir: &str,
+        destination_dir: &str,
+        source: &FakeInterfaces,
+        options: &ApplyOptions,
+    ) -> Result<(String, HashMap<String, String>), anyhow::Error> {
+        let hosts = parse_config(source_dir, HOST_MAPPING_FILE, false)?;
+        let (host, network_interfaces) = identify_host_with_retries(
+            &hosts,
+            &[Box::new(MacMatcher::default())],
+            || source.network_interfaces(),
+            0,
+            Duration::ZERO,
+        )?;
+        let hostname = host.hostname.clone();
+
+        let local_interfaces = local_interface_names(&host, network_interfaces, source, options)?;
+        copy_connection_files(
+            host,
+            local_interfaces.clone(),
+            source_dir,
+            destination_dir,
+            &HashMap::new(),
+            options,
+        )?;
+
+        Ok((hostname, local_interfaces))
+    }
+
+    /// Compare the files of the destination dir with the expected ones.
+    fn assert_golden_files(destination_dir: &str, expected_dir: &str) -> io::Result<()> {
+        let filenames = |dir: &str| -> io::Result<Vec<String>> {
+            let mut filenames = fs::read_dir(dir)?
+                .map(|entry| Ok(entry?.file_name().to_string_lossy().into_owned()))
+                .collect::<io::Result<Vec<_>>>()?;
+            filenames.sort();
+            Ok(filenames)
+        };
+
+        let expected = filenames(expected_dir)?;
+        assert_eq!(filenames(destination_dir)?, expected);
+
+        for filename in expected {
+            assert_eq!(
+                fs::read_to_string(Path::new(destination_dir).join(&filename))?,
+                fs::read_to_string(Path::new(expected_dir).join(&filename))?,
+                "{filename} differs from the expected contents"
+            );
+        }
+
+        Ok(())
+    }
+
+    #[test]
+    fn apply_golden_files() -> Result<(), anyhow::Error> {
+        let source_dir = "testdata/apply/golden";
+        let destination_dir = "_out_golden";
+        // "eth0" is renamed, "eth1" keeps its name and "eth2" is one of the alternative names of its NIC.
+        let source = FakeInterfaces::new(&[
+            ("lo", "00:00:00:00:00:00", &[]),
+            ("ens1f0", "00:11:22:33:44:55", &[]),
+            ("eth1", "00:11:22:33:44:57", &[]),
+            ("enp3s0", "00:11:22:33:44:56", &["eth2"]),
+        ]);
+
+        let (hostname, local_interfaces) = apply_with_interfaces(
+            source_dir,
+            destination_dir,
+            &source,
+            &ApplyOptions::default(),
+        )?;
+        assert_eq!(hostname, "node1");
+        assert_eq!(
+            local_interfaces,
+            HashMap::from([
+                ("eth0".to_string(), "ens1f0".to_string()),
+                ("eth0.1365".to_string(), "ens1f0.1365".to_string()),
+            ])
+        );
+        assert_golden_files(destination_dir, "testdata/apply/golden/expected")?;
+        fs::remove_dir_all(destination_dir)?;
+
+        // The files keep the preconfigured names, while their contents still refer to the local ones.
+        let options = ApplyOptions {
+            keep_filenames: true,
+            ..Default::default()
+        };
+        apply_with_interfaces(source_dir, destination_dir, &source, &options)?;
+        let contents = fs::read_to_string(Path::new(destination_dir).join("eth0.nmconnection"))?;
+        assert!(contents.contains("interface-name = ens1f0"));
+        assert!(!Path::new(destination_dir)
+            .join("ens1f0.nmconnection")
+            .exists());
+
+        // cleanup
+        fs::remove_dir_all(destination_dir)?;
+
+        Ok(())
+    }
+
+    #[test]
+    fn disable_wired_conn() {
+        assert!(disable_wired_connections("config", "connections").is_ok());
+
+        assert!(Path::new("config").exists());
+        assert!(Path::new("connections").exists());
+
+        let config_contents = fs::read_to_string("config/no-auto-default.conf").unwrap();
+        assert_eq!(config_contents, "[main]\nno-auto-default=*\n");
+
+        // cleanup
+        assert!(fs::remove_dir_all("config").is_ok());
+        assert!(fs::remove_dir_all("connections").is_ok());
+    }
+
+    #[test]
+    fn check_writable_destination() -> io::Result<()> {
+        let destination_dir = "_out_writable/connections";
+
+        fs::create_dir_all("_out_writable")?;
+        assert!(check_writable(destination_dir, "--destination-dir").is_ok());
+        assert!(!Path::new(destination_dir).exists());
+        assert_eq!(fs::read_dir("_out_writable")?.count(), 0);
+
+        fs::create_dir_all(destination_dir)?;
+        assert!(check_writable(destination_dir, "--destination-dir").is_ok());
+        assert_eq!(fs::read_dir(destination_dir)?.count(), 0);
+
+        fs::write("_out_writable/file", "")?;
+        assert!(
+            check_writable("_out_writable/file/connections", "--destination-dir")
+                .unwrap_err()
+                .to_string()
+                .contains("Checking destination _out_writable/file/connections")
+        );
+
+        // cleanup
+        fs::remove_dir_all("_out_writable")
+    }
+
+    #[test]
+    fn check_metadata_nmstate_version() -> Result<(), anyhow::Error> {
+        let source_dir = "_in_metadata";
+        fs::create_dir_all(source_dir)?;
+
+        assert_eq!(check_metadata(source_dir)?, None);
+
+        let write_metadata = |nmstate_version: &str| {
+            fs::write(
+                Path::new(source_dir).join(METADATA_FILE),
+                format!(
+                    "nmstate_version: {nmstate_version}\nnmc_version: 0.2.3\n\
+                    generated_at: 2024-05-01T10:00:00Z\n"
+                ),
+            )
+        };
+
+        write_metadata("2.2.99")?;
+        assert_eq!(check_metadata(source_dir)?, None);
+
+        write_metadata("2.1.0")?;
+        assert_eq!(
+            check_metadata(source_dir)?,
+            Some(format!(
+                "Config was generated with nmstate 2.1.0 (nmc 0.2.3) while nmstate {NMSTATE_VERSION} is expected"
+            ))
+        );
+
+        // cleanup
+        fs::remove_dir_all(source_dir)?;
+
+        Ok(())
+    }
+
+    #[test]
+    fn find_unexpected_entries_in_host_dir() -> Result<(), anyhow::Error> {
+        let source_dir = "_in_unexpected";
+        let host_dir = Path::new(source_dir).join("node1");
+
+        fs::create_dir_all(host_dir.join("backup"))?;
+        fs::write(host_dir.join("eth0.nmconnection"), "")?;
+        fs::write(host_dir.join("eth0.nmconnection.bak"), "")?;
+        fs::write(host_dir.join(PER_HOST_MAPPING_FILE), "")?;
+
+        let host = Host {
+            hostname: "node1".to_string(),
+            serial: None,
+            interfaces: vec![Interface {
+                logical_name: "eth0".to_string(),
+                mac_address: Some("00:11:22:33:44:55".to_string()),
+                additional_macs: Vec::new(),
+                predictable_name: None,
+                interface_type: "ethernet".to_string(),
+            }],
+        };
+
+        let entries = find_unexpected_entries(&host, source_dir)?;
+        assert_eq!(
+            entries,
+            vec![
+                host_dir.join("backup"),
+                host_dir.join("eth0.nmconnection.bak")
+            ]
+        );
+
+        // cleanup
+        fs::remove_dir_all(source_dir)?;
+
+        Ok(())
+    }
+
+    #[test]
+    fn check_mac_address_bits_of_interfaces() {
+        let interfaces = vec![
+            Interface {
+                logical_name: "eth0".to_string(),
+                mac_address: Some("00:11:22:33:44:55".to_string()),
+                additional_macs: vec!["03:11:22:33:44:56".to_string()],
+                predictable_name: None,
+                interface_type: "ethernet".to_string(),
+            },
+            Interface {
+                logical_name: "eth1".to_string(),
+                mac_address: Some("0a:11:22:33:44:57".to_string()),
+                additional_macs: Vec::new(),
+                predictable_name: None,
+                interface_type: "ethernet".to_string(),
+            },
+            Interface {
+                logical_name: "bond0".to_string(),
+                mac_address: None,
+                additional_macs: Vec::new(),
+                predictable_name: None,
+                interface_type: "bond".to_string(),
+            },
+        ];
+
+        let (errors, warnings) = check_mac_address_bits(&interfaces);
+        assert_eq!(
+            errors,
+            vec!["eth0 has the multicast MAC address 03:11:22:33:44:56"]
+        );
+        assert_eq!(
+            warnings,
+            vec!["eth1 has the locally-administered MAC address 0a:11:22:33:44:57"]
+        );
+    }
+
+    #[test]
+    fn check_exact_match_of_interfaces() {
+        let host = Host {
+            hostname: "node5".to_string(),
+            serial: None,
+            interfaces: vec![
+                interface("eth0", Some("00:11:22:33:44:55"), "ethernet"),
+                interface("eth1", Some("00:11:22:33:44:56"), "ethernet"),
+                interface("bond0", Some("00:11:22:33:44:57"), "bond"),
+            ],
+        };
+        let nic = |mac: &str| NetworkInterface {
+            name: "eth0".to_string(),
+            mac_addr: Some(mac.to_string()),
+            addr: vec![],
+            index: 0,
+        };
+
+        // Virtual interfaces (e.g. bonds) are not required to be present.
+        let network_interfaces = vec![nic("00:11:22:33:44:55"), nic("00:11:22:33:44:56")];
+        assert!(check_exact_match(&host, &network_interfaces).is_ok());
+
+        // The generated mappings keep the case of the desired state while the NICs are reported in lowercase.
+        let uppercase = Host {
+            hostname: "node6".to_string(),
+            serial: None,
+            interfaces: vec![interface("eth0", Some("FE:C4:05:42:8B:AA"), "ethernet")],
+        };
+        let network_interfaces = vec![nic("fe:c4:05:42:8b:aa")];
+        assert!(check_exact_match(&uppercase, &network_interfaces).is_ok());
+
+        let network_interfaces = vec![nic("00:11:22:33:44:55")];
+        let error = check_exact_match(&host, &network_interfaces).unwrap_err();
+        assert!(matches!(
+            error.downcast_ref::<ConfigError>(),
+            Some(ConfigError::MissingInterfaces(_))
+        ));
+        assert_eq!(
+            error.to_string(),
+            "Refusing to apply the config of host 'node5' since not all of its MAC addresses are present, \
+            missing: eth1 (00:11:22:33:44:56)"
+        );
+    }
+
+    #[test]
+    fn check_required_interfaces_present() {
+        // Virtual interfaces (e.g. bonds) neither count as present nor as missing,
+        // and the MAC addresses are matched regardless of their case.
+        let host = Host {
+            hostname: "h1".to_string(),
+            serial: None,
+            interfaces: vec![
+                interface("eth0", Some("00:11:22:33:44:55"), "ethernet"),
+                interface("eth1", Some("00:11:22:33:44:56"), "ethernet"),
+                interface("eth2", Some("00:11:22:33:44:AA"), "ethernet"),
+                interface("bond0", Some("00:11:22:33:44:55"), "bond"),
+                interface("bond1", Some("00:11:22:33:44:58"), "bond"),
+            ],
+        };
+        let network_interfaces = vec![
+            NetworkInterface {
+                name: "eth0".to_string(),
+                mac_addr: Some("00:11:22:33:44:55".to_string()),
+                addr: vec![],
+                index: 0,
+            },
+            NetworkInterface {
+                name: "eth2".to_string(),
+                mac_addr: Some("00:11:22:33:44:aa".to_string()),
+                addr: vec![],
+                index: 1,
+            },
+        ];
+
+        assert!(check_required_interfaces(&host, &network_interfaces, 2).is_ok());
+
+        let error = check_required_interfaces(&host, &network_interfaces, 3).unwrap_err();
+        assert!(matches!(
+            error.downcast_ref::<ConfigError>(),
+            Some(ConfigError::MissingInterfaces(_))
+        ));
+        assert_eq!(
+            error.to_string(),
+            "Only 2 of the required 3 interfaces of host 'h1' are present, \
+            missing: eth1 (00:11:22:33:44:56)"
+        );
+    }
+
+    #[test]
+    fn identify_host_successfully() {
+        let hosts = vec![
+            Host {
+                hostname: "h1".to_string(),
+                serial: None,
+                interfaces: vec![Interface {
+                    logical_name: "eth0".to_string(),
+                    mac_address: Option::from("00:11:22:33:44:55".to_string()),
+                    additional_macs: Vec::new(),
+                    predictable_name: None,
+                    interface_type: "ethernet".to_string(),
+                }],
+            },
+            Host {
+                hostname: "h2".to_string(),
+                serial: None,
+                interfaces: vec![Interface {
+                    logical_name: "".to_string(),
+                    mac_address: Option::from("10:10:10:10:10:10".to_string()),
+                    additional_macs: Vec::new(),
+                    predictable_name: None,
+                    interface_type: "".to_string(),
+                }],
+            },
+        ];
+        let interfaces = [
+            NetworkInterface {
+                name: "eth0".to_string(),
+                mac_addr: Some("00:11:22:33:44:55".to_string()),
+                addr: vec![],
+                index: 0,
+            },
+            NetworkInterface {
+                name: "eth0".to_string(),
+                mac_addr: Some("00:10:20:30:40:50".to_string()),
+                addr: vec![],
+                index: 0,
+            },
+        ];
+
+        let host = identify_host(hosts, &interfaces).unwrap();
+        assert_eq!(host.hostname, "h1");
+        assert_eq!(
+            host.interfaces,
+            vec![Interface {
+                logical_name: "eth0".to_string(),
+                mac_address: Option::from("00:11:22:33:44:55".to_string()),
+                additional_macs: Vec::new(),
+                predictable_name: None,
+                interface_type: "ethernet".to_string(),
+            }]
+        );
+    }
+
+    #[test]
+    fn identify_host_prefers_most_matches() {
+        let nic = |name: &str, mac: &str| NetworkInterface {
+            name: name.to_string(),
+            mac_addr: Some(mac.to_string()),
+            addr: vec![],
+            index: 0,
+        };
+
+        // Both hosts share the MAC address of "eth0" (e.g. cloned VMs).
+        let hosts = vec![
+            Host {
+                hostname: "h1".to_string(),
+                serial: None,
+                interfaces: vec![
+                    interface("eth0", Some("00:11:22:33:44:55"), "ethernet"),
+                    interface("eth1", Some("00:11:22:33:44:66"), "ethernet"),
+                ],
+            },
+            Host {
+                hostname: "h2".to_string(),
+                serial: None,
+                interfaces: vec![
+                    interface("eth0", Some("00:11:22:33:44:55"), "ethernet"),
+                    interface("eth1", Some("00:11:22:33:44:77"), "ethernet"),
+                ],
+            },
+        ];
+
+        let interfaces = [
+            nic("eth0", "00:11:22:33:44:55"),
+            nic("eth1", "00:11:22:33:44:77"),
+        ];
+        let host = identify_host(hosts.clone(), &interfaces).unwrap();
+        assert_eq!(host.hostname, "h2");
+
+        // Ties are resolved in favour of the first listed host.
+        let interfaces = [nic("eth0", "00:11:22:33:44:55")];
+        let host = identify_host(hosts, &interfaces).unwrap();
+        assert_eq!(host.hostname, "h1");
+    }
+
+    #[test]
+    fn match_host_by_mac_addresses() {
+        let hosts = vec![
+            Host {
+                hostname: "h1".to_string(),
+                serial: None,
+                interfaces: vec![
+                    interface("eth0", Some("00:11:22:33:44:55"), "ethernet"),
+                    interface("br0", Some("00:11:22:33:44:66"), "linux-bridge"),
+                ],
+            },
+            Host {
+                hostname: "h2".to_string(),
+                serial: None,
+                interfaces: vec![
+                    interface("eth0", Some("00:11:22:33:44:55"), "ethernet"),
+                    interface("eth1", Some("00:11:22:33:44:77"), "ethernet"),
+                ],
+            },
+        ];
+        let macs = |macs: &[&str]| -> Vec<String> { macs.iter().map(|m| m.to_string()).collect() };
+
+        let host = match_host(&hosts, &macs(&["00:11:22:33:44:77", "00:11:22:33:44:55"]));
+        assert_eq!(host.map(|h| h.hostname()), Some("h2"));
+
+        let host = match_host(&hosts, &macs(&["00:11:22:33:44:55"]));
+        assert_eq!(host.map(|h| h.hostname()), Some("h1"));
+
+        // MAC addresses are compared case-insensitively.
+        let host = match_host(&hosts, &macs(&["00:11:22:33:44:AA", "00:11:22:33:44:77"]));
+        assert_eq!(host.map(|h| h.hostname()), Some("h2"));
+
+        // Virtual interfaces are not taken into account.
+        assert!(match_host(&hosts, &macs(&["00:11:22:33:44:66"])).is_none());
+        assert!(match_host(&hosts, &[]).is_none());
+    }
+
+    #[test]
+    fn match_host_by_min_match_ratio() {
+        let hosts = vec![
+            Host {
+                hostname: "h1".to_string(),
+                serial: None,
+                interfaces: vec![
+                    interface("eth0", Some("00:11:22:33:44:01"), "ethernet"),
+                    interface("eth1", Some("00:11:22:33:44:02"), "ethernet"),
+                    interface("eth2", Some("00:11:22:33:44:03"), "ethernet"),
+                    interface("eth3", Some("00:11:22:33:44:04"), "ethernet"),
+                ],
+            },
+            Host {
+                hostname: "h2".to_string(),
+                serial: None,
+                interfaces: vec![
+                    interface("eth0", Some("00:11:22:33:44:01"), "ethernet"),
+                    interface("eth1", Some("00:11:22:33:44:05"), "ethernet"),
+                ],
+            },
+        ];
+        let macs = |macs: &[&str]| -> Vec<String> { macs.iter().map(|m| m.to_string()).collect() };
+
+        let local = macs(&[
+            "00:11:22:33:44:01",
+            "00:11:22:33:44:02",
+            "00:11:22:33:44:05",
+        ]);
+        assert_eq!(match_host(&hosts, &local).map(|h| h.hostname()), Some("h1"));
+
+        // Only h2 has enough of its interfaces present.
+        let host = match_host_with_ratio(&hosts, &local, 0.75);
+        assert_eq!(host.map(|h| h.hostname()), Some("h2"));
+
+        let host = match_host_with_ratio(&hosts, &local, 0.5);
+        assert_eq!(host.map(|h| h.hostname()), Some("h1"));
+
+        let local = macs(&["00:11:22:33:44:01"]);
+        assert!(match_host_with_ratio(&hosts, &local, 0.75).is_none());
+
+        // All matching hosts are found when applying all matches.
+        let hostnames = |hosts: Vec<&Host>| -> Vec<String> {
+            hosts.into_iter().map(|h| h.hostname.clone()).collect()
+        };
+        assert_eq!(
+            hostnames(match_all_hosts(&hosts, &local, None)),
+            ["h1", "h2"]
+        );
+        assert_eq!(
+            hostnames(match_all_hosts(&hosts, &local, Some(0.5))),
+            ["h2"]
+        );
+        assert!(match_all_hosts(&hosts, &macs(&["00:11:22:33:44:06"]), None).is_empty());
+    }
+
+    #[test]
+    fn explain_host_identification() -> Result<(), anyhow::Error> {
+        let hosts = vec![
+            Host {
+                hostname: "h1".to_string(),
+                serial: None,
+                interfaces: vec![
+                    Interface {
+                        logical_name: "eth0".to_string(),
+                        mac_address: Some("00:11:22:33:44:01".to_string()),
+                        additional_macs: Vec::new(),
+                        predictable_name: None,
+                        interface_type: "ethernet".to_string(),
+                    },
+                    Interface {
+                        logical_name: "eth1".to_string(),
+                        mac_address: Some("00:11:22:33:44:02".to_string()),
+                        additional_macs: Vec::new(),
+                        predictable_name: None,
+                        interface_type: "ethernet".to_string(),
+                    },
+                ],
+            },
+            Host {
+                hostname: "h2".to_string(),
+                serial: None,
+                interfaces: vec![Interface {
+                    logical_name: "eth0".to_string(),
+                    mac_address: Some("00:11:22:33:44:03".to_string()),
+                    additional_macs: Vec::new(),
+                    predictable_name: None,
+                    interface_type: "ethernet".to_string(),
+                }],
+            },
+        ];
+        let nics = [NetworkInterface {
+            name: "eth0".to_string(),
+            mac_addr: Some("00:11:22:33:44:01".to_string()),
+            addr: vec![],
+            index: 0,
+        }];
+
+        let matchers: Vec<Box<dyn HostMatcher>> = vec![Box::new(MacMatcher::default())];
+        let explanation = serde_json::to_value(explain(&hosts, &matchers, &nics, None))?;
+        assert_eq!(
+            explanation,
+            serde_json::json!({
+                "local_mac_addresses": ["00:11:22:33:44:01"],
+                "hosts": [
+                    {
+                        "hostname": "h1",
+                        "mac_addresses": ["00:11:22:33:44:01", "00:11:22:33:44:02"],
+                        "matched": ["00:11:22:33:44:01"],
+                        "match_ratio": 0.5,
+                    },
+                    {
+                        "hostname": "h2",
+                        "mac_addresses": ["00:11:22:33:44:03"],
+                        "matched": [],
+                        "match_ratio": 0.0,
+                    },
+                ],
+                "selected": "h1",
+                "matched_by": "MAC address",
+            })
+        );
+
+        let matchers: Vec<Box<dyn HostMatcher>> =
+            vec![Box::new(MacMatcher::with_min_match_ratio(Some(0.75)))];
+        let explanation = explain(&hosts, &matchers, &nics, Some(0.75));
+        assert_eq!(explanation.selected, None);
+        assert_eq!(
+            explanation.reasons,
+            vec![
+                "Host h1 only has 50% of its physical interfaces present, below the minimum of 75%",
+                "No confident match by MAC address",
+            ]
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn identify_host_with_retries_successfully() {
+        let hosts = vec![Host {
+            hostname: "h1".to_string(),
+            serial: None,
+            interfaces: vec![Interface {
+                logical_name: "eth0".to_string(),
+                mac_address: Option::from("00:11:22:33:44:55".to_string()),
+                additional_macs: Vec::new(),
+                predictable_name: None,
+                interface_type: "ethernet".to_string(),
+            }],
+        }];
+        let attempts = Cell::new(0);
+        let retrieve_interfaces = || {
+            attempts.set(attempts.get() + 1);
+
+            // The matching NIC only shows up on the third attempt.
+            let mac_addr = if attempts.get() < 3 {
+                "00:10:20:30:40:50"
+            } else {
+                "00:11:22:33:44:55"
+            };
+
+            Ok(vec![NetworkInterface {
+                name: "eth0".to_string(),
+                mac_addr: Some(mac_addr.to_string()),
+                addr: vec![],
+                index: 0,
+            }])
+        };
+
+        let (host, _) = identify_host_with_retries(
+            &hosts,
+            &[Box::new(MacMatcher::default())],
+            retrieve_interfaces,
+            3,
+            Duration::ZERO,
+        )
+        .unwrap();
+        assert_eq!(host.hostname, "h1");
+        assert_eq!(attempts.get(), 3);
+    }
+
+    #[test]
+    fn identify_host_with_retries_fails() {
+        let hosts = vec![Host {
+            hostname: "h1".to_string(),
+            serial: None,
+            interfaces: vec![Interface {
+                logical_name: "eth0".to_string(),
+                mac_address: Option::from("00:11:22:33:44:55".to_string()),
+                additional_macs: Vec::new(),
+                predictable_name: None,
+                interface_type: "ethernet".to_string(),
+            }],
+        }];
+        let attempts = Cell::new(0);
+        let retrieve_interfaces = || {
+            attempts.set(attempts.get() + 1);
+            Ok(vec![])
+        };
+
+        let error = identify_host_with_retries(
+            &hosts,
+            &[Box::new(MacMatcher::default())],
+            retrieve_interfaces,
+            2,
+            Duration::ZERO,
+        )
+        .unwrap_err();
+        assert_eq!(
+            error.to_string(),
+            "None of the preconfigured hosts match local NICs"
+        );
+        assert_eq!(attempts.get(), 3);
+    }
+
+    #[test]
+    fn identify_host_skips_virtual_interfaces() {
+        let hosts = vec![
+            Host {
+                hostname: "h1".to_string(),
+                serial: None,
+                interfaces: vec![
+                    Interface {
+                        logical_name: "eth0".to_string(),
+                        mac_address: Option::from("10:20:30:40:50:60".to_string()),
+                        additional_macs: Vec::new(),
+                        predictable_name: None,
+                        interface_type: "ethernet".to_string(),
+                    },
+                    Interface {
+                        logical_name: "bond0".to_string(),
+                        mac_address: Option::from("00:11:22:33:44:55".to_string()),
+                        additional_macs: Vec::new(),
+                        predictable_name: None,
+                        interface_type: "bond".to_string(),
+                    },
+                ],
+            },
+            Host {
+                hostname: "h2".to_string(),
+                serial: None,
+                interfaces: vec![Interface {
+                    logical_name: "ib0".to_string(),
+                    mac_address: Option::from("00:11:22:33:44:55".to_string()),
+                    additional_macs: Vec::new(),
+                    predictable_name: None,
+                    interface_type: "infiniband".to_string(),
+                }],
+            },
+        ];
+        let interfaces = [NetworkInterface {
+            name: "ib0".to_string(),
+            mac_addr: Some("00:11:22:33:44:55".to_string()),
+            addr: vec![],
+            index: 0,
+        }];
+
+        let host = identify_host(hosts, &interfaces).unwrap();
+        assert_eq!(host.hostname, "h2");
+    }
+
+    #[test]
+    fn identify_host_by_additional_mac_address() {
+        let hosts = vec![Host {
+            hostname: "h1".to_string(),
+            serial: None,
+            interfaces: vec![Interface {
+                logical_name: "bond0".to_string(),
+                mac_address: Option::from("00:11:22:33:44:55".to_string()),
+                additional_macs: vec![
+                    "00:11:22:33:44:55".to_string(),
+                    "00:11:22:33:44:56".to_string(),
+                ],
+                predictable_name: None,
+                interface_type: "bond".to_string(),
+            }],
+        }];
+        let interfaces = [NetworkInterface {
+            name: "eth1".to_string(),
+            mac_addr: Some("00:11:22:33:44:56".to_string()),
+            addr: vec![],
+            index: 0,
+        }];
+
+        let host = identify_host(hosts, &interfaces).unwrap();
+        assert_eq!(host.hostname, "h1");
+    }
+
+    #[test]
+    fn identify_host_fails() {
+        let hosts = vec![
+            Host {
+                hostname: "h1".to_string(),
+                serial: None,
+                interfaces: vec![Interface {
+                    logical_name: "eth0".to_string(),
+                    mac_address: Option::from("10:20:30:40:50:60".to_string()),
+                    additional_macs: Vec::new(),
+                    predictable_name: None,
+                    interface_type: "ethernet".to_string(),
+                }],
+            },
+            Host {
+                hostname: "h2".to_string(),
+                serial: None,
+                interfaces: vec![Interface {
+                    logical_name: "".to_string(),
+                    mac_address: Option::from("00:10:20:30:40:50".to_string()),
+                    additional_macs: Vec::new(),
+                    predictable_name: None,
+                    interface_type: "".to_string(),
+                }],
+            },
+        ];
+        let interfaces = [NetworkInterface {
+            name: "eth0".to_string(),
+            mac_addr: Some("00:11:22:33:44:55".to_string()),
+            addr: vec![],
+            index: 0,
+        }];
+
+        assert!(identify_host(hosts, &interfaces).is_none())
+    }
+
+    #[test]
+    fn parse_config_fails_due_to_missing_file() {
+        let error = parse_config("<missing>", HOST_MAPPING_FILE, false).unwrap_err();
+        assert!(error.to_string().contains("No such file or directory"))
+    }
+
+    #[test]
+    fn parse_config_with_host_without_interfaces() -> Result<(), anyhow::Error> {
+        let source_dir = "_out_no_interfaces";
+
+        fs::create_dir_all(source_dir)?;
+        fs::write(
+            Path::new(source_dir).join(HOST_MAPPING_FILE),
+            r#"- hostname: node1
+  interfaces:
+    - logical_name: eth0
+      mac_address: 00:11:22:33:44:55
+      interface_type: ethernet
+- hostname: node2
+  interfaces: []
+"#,
+        )?;
+
+        assert_eq!(parse_config(source_dir, HOST_MAPPING_FILE, false)?.len(), 2);
+
+        let error = parse_config(source_dir, HOST_MAPPING_FILE, true).unwrap_err();
+        assert_eq!(
+            error.to_string(),
+            "Host node2 has no interfaces and can never be identified"
+        );
+
+        // cleanup
+        fs::remove_dir_all(source_dir)?;
+
+        Ok(())
+    }
+
+    #[test]
+    fn parse_config_fails_due_to_invalid_mac_address() -> io::Result<()> {
+        let source_dir = "_out_invalid_mac";
+
+        fs::create_dir_all(source_dir)?;
+        fs::write(
+            Path::new(source_dir).join("host_config.yaml"),
+            r#"- hostname: node1
+  interfaces:
+    - logical_name: eth0
+      mac_address: 00:11:22:33:44
+      interface_type: ethernet
+"#,
+        )?;
+
+        let error = parse_config(source_dir, HOST_MAPPING_FILE, false).unwrap_err();
+        assert_eq!(
+            error.to_string(),
+            "Invalid MAC address '00:11:22:33:44' for host node1 interface eth0"
+        );
+
+        // cleanup
+        fs::remove_dir_all(source_dir)
+    }
+
+    #[test]
+    fn validate_mapping_successfully() {
+        let hosts = parse_config("testdata/apply/config", HOST_MAPPING_FILE, false).unwrap();
+        assert!(validate_mapping(&hosts).is_ok());
+    }
+
+    #[test]
+    fn validate_mapping_fails() {
+        let hosts = vec![
+            Host {
+                hostname: "node1".to_string(),
+                serial: None,
+                interfaces: vec![interface("eth0", None, "ethernet")],
+            },
+            Host {
+                hostname: " ".to_string(),
+                serial: None,
+                interfaces: vec![interface("eth0", None, "ethernet")],
+            },
+            Host {
+                hostname: "node1".to_string(),
+                serial: None,
+                interfaces: vec![],
+            },
+            Host {
+                hostname: "node2".to_string(),
+                serial: None,
+                interfaces: vec![
+                    interface("eth0", None, "ethernet"),
+                    interface("", None, "ethernet"),
+                ],
+            },
+        ];
+
+        let error = validate_mapping(&hosts).unwrap_err();
+        assert_eq!(
+            error.to_string(),
+            "Invalid host mapping: host #2: blank hostname; host node1: duplicate hostname; \
+            host node1: no interfaces; host node2: 1 interface(s) with empty logical name"
+        );
+    }
+
+    #[test]
+    fn validate_mac_address() {
+        assert!(is_valid_mac_address("00:11:22:33:44:55"));
+        assert!(is_valid_mac_address("fe:c4:05:42:8b:aa"));
+        assert!(!is_valid_mac_address(""));
+        assert!(!is_valid_mac_address("00:11:22:33:44"));
+        assert!(!is_valid_mac_address("00:11:22:33:44:55:66"));
+        assert!(!is_valid_mac_address("00:11:22:33:44:5"));
+        assert!(!is_valid_mac_address("00:11:22:33:44:5g"));
+        assert!(!is_valid_mac_address("00-11-22-33-44-55"));
+    }
+
+    #[test]
+    fn parse_config_successfully() {
+        let hosts = parse_config("testdata/apply/config", HOST_MAPPING_FILE, false).unwrap();
+        assert_eq!(
+            hosts,
+            vec![
+                Host {
+                    hostname: "node1".to_string(),
+                    serial: None,
+                    interfaces: vec![
+                        Interface {
+                            logical_name: "eth0".to_string(),
+                            mac_address: Option::from("00:11:22:33:44:55".to_string()),
+                            additional_macs: Vec::new(),
+                            predictable_name: None,
+                            interface_type: "ethernet".to_string(),
+                        },
+                        Interface {
+                            logical_name: "eth1".to_string(),
+                            mac_address: Option::from("00:11:22:33:44:58".to_string()),
+                            additional_macs: Vec::new(),
+                            predictable_name: None,
+                            interface_type: "ethernet".to_string(),
+                        },
+                        Interface {
+                            logical_name: "eth2".to_string(),
+                            mac_address: Option::from("36:5e:6b:a2:ed:80".to_string()),
+                            additional_macs: Vec::new(),
+                            predictable_name: None,
+                            interface_type: "ethernet".to_string(),
+                        },
+                        Interface {
+                            logical_name: "bond0".to_string(),
+                            mac_address: Option::from("00:11:22:aa:44:58".to_string()),
+                            additional_macs: Vec::new(),
+                            predictable_name: None,
+                            interface_type: "bond".to_string(),
+                        },
+                    ],
+                },
+                Host {
+                    hostname: "node2".to_string(),
+                    serial: None,
+                    interfaces: vec![
+                        Interface {
+                            logical_name: "eth0".to_string(),
+                            mac_address: Option::from("36:5e:6b:a2:ed:81".to_string()),
+                            additional_macs: Vec::new(),
+                            predictable_name: None,
+                            interface_type: "ethernet".to_string(),
+                        },
+                        Interface {
+                            logical_name: "eth0.1365".to_string(),
+                            mac_address: None,
+                            additional_macs: Vec::new(),
+                            predictable_name: None,
+                            interface_type: "vlan".to_string(),
+                        },
+                    ],
+                },
+            ]
+        )
+    }
+
+    #[test]
+    fn detect_interface_differences() {
+        let host = Host {
+            hostname: "node1".to_string(),
+            serial: None,
+            interfaces: vec![
+                Interface {
+                    logical_name: "eth0".to_string(),
+                    mac_address: Option::from("00:11:22:33:44:55".to_string()),
+                    additional_macs: Vec::new(),
+                    predictable_name: None,
+                    interface_type: "ethernet".to_string(),
+                },
+                Interface {
+                    logical_name: "eth0.1365".to_string(),
+                    mac_address: None,
+                    additional_macs: Vec::new(),
+                    predictable_name: None,
+                    interface_type: "vlan".to_string(),
+                },
+                Interface {
+                    logical_name: "eth2".to_string(),
+                    mac_address: Option::from("00:11:22:33:44:56".to_string()),
+                    additional_macs: Vec::new(),
+                    predictable_name: None,
+                    interface_type: "ethernet".to_string(),
+                },
+                Interface {
+                    logical_name: "eth2.bridge".to_string(),
+                    mac_address: None,
+                    additional_macs: Vec::new(),
+                    predictable_name: None,
+                    interface_type: "linux-bridge".to_string(),
+                },
+                Interface {
+                    logical_name: "bond0".to_string(),
+                    mac_address: Option::from("00:11:22:33:44:58".to_string()),
+                    additional_macs: Vec::new(),
+                    predictable_name: None,
+                    interface_type: "bond".to_string(),
+                },
+            ],
+        };
+        let interfaces = vec![
+            NetworkInterface {
+                name: "eth0".to_string(),
+                mac_addr: Some("00:11:22:33:44:55".to_string()),
+                addr: vec![],
+                index: 0,
+            },
+            NetworkInterface {
+                name: "eth0.1365".to_string(), // VLAN
+                addr: vec![],
+                mac_addr: Some("00:11:22:33:44:55".to_string()),
+                index: 0,
+            },
+            NetworkInterface {
+                name: "ens1f0".to_string(),
+                mac_addr: Some("00:11:22:33:44:56".to_string()),
+                addr: vec![],
+                index: 0,
+            },
+        ];
+
+        let local_interfaces =
+            detect_local_interfaces(&host, interfaces.clone(), &HashMap::new(), &HashMap::new());
+        assert_eq!(
+            local_interfaces,
+            HashMap::from([
+                ("eth2".to_string(), "ens1f0".to_string()),
+                ("eth2.bridge".to_string(), "ens1f0.bridge".to_string())
+            ])
+        );
+
+        let rename_map = HashMap::from([
+            ("00:11:22:33:44:55".to_string(), "mgmt0".to_string()),
+            ("00:11:22:33:44:56".to_string(), "lan0".to_string()),
+        ]);
+        let local_interfaces =
+            detect_local_interfaces(&host, interfaces.clone(), &rename_map, &HashMap::new());
+        assert_eq!(
+            local_interfaces,
+            HashMap::from([
+                ("eth0".to_string(), "mgmt0".to_string()),
+                ("eth0.1365".to_string(), "mgmt0.1365".to_string()),
+                ("eth2".to_string(), "lan0".to_string()),
+                ("eth2.bridge".to_string(), "lan0.bridge".to_string())
+            ])
+        );
+
+        let options = ApplyOptions {
+            no_rename: true,
+            ..Default::default()
+        };
+        let source = FakeInterfaces::default();
+        let local_interfaces = local_interface_names(&host, interfaces, &source, &options).unwrap();
+        assert!(local_interfaces.is_empty())
+    }
+
+    #[test]
+    fn detect_interface_differences_by_altnames() {
+        let nic = |name: &str, mac: &str| NetworkInterface {
+            name: name.to_string(),
+            mac_addr: Some(mac.to_string()),
+            addr: vec![],
+            index: 0,
+        };
+
+        let host = Host {
+            hostname: "node1".to_string(),
+            serial: None,
+            interfaces: vec![
+                interface("enp1s0", Some("00:11:22:33:44:55"), "ethernet"),
+                interface("eth1", Some("00:11:22:33:44:56"), "ethernet"),
+                interface("eth2", Some("00:11:22:33:44:57"), "ethernet"),
+            ],
+        };
+        let interfaces = vec![
+            nic("eth0", "00:11:22:33:44:55"),
+            nic("eth2", "00:11:22:33:44:56"),
+            nic("eth1", "00:11:22:33:44:57"),
+        ];
+        let altnames = HashMap::from([
+            ("eth0".to_string(), vec!["enp1s0".to_string()]),
+            ("eth2".to_string(), vec!["enp2s0".to_string()]),
+        ]);
+
+        // "enp1s0" is already an altname of "eth0" while the primary name of the NIC
+        // of "eth1" is taken by "eth2" so its altname is used instead.
+        let local_interfaces =
+            detect_local_interfaces(&host, interfaces, &HashMap::new(), &altnames);
+        assert_eq!(
+            local_interfaces,
+            HashMap::from([("eth1".to_string(), "enp2s0".to_string())])
+        );
+    }
+
+    #[test]
+    fn detect_interface_differences_by_predictable_names() {
+        let ethernet = |name: &str, mac: &str, predictable_name: Option<&str>| Interface {
+            predictable_name: predictable_name.map(str::to_string),
+            ..interface(name, Some(mac), "ethernet")
+        };
+
+        let host = Host {
+            hostname: "node1".to_string(),
+            serial: None,
+            interfaces: vec![
+                ethernet("eth0", "00:11:22:33:44:55", Some("enx001122334455")),
+                ethernet("eth1", "00:11:22:33:44:56", Some("enx001122334456")),
+                ethernet("eth2", "00:11:22:33:44:57", None),
+                ethernet("eth3", "00:11:22:33:44:58", Some("enx001122334458")),
+                ethernet("eth4", "00:11:22:33:44:59", Some("enx001122334459")),
+                ethernet("eth5", "00:11:22:33:44:60", Some("enx001122334460")),
+            ],
+        };
+        // The NIC of "eth0" is not present, the one of "eth1" still has its kernel name,
+        // the one of "eth3" is named differently and the one of "eth4" has the predictable
+        // name as an alternative one.
+        let interfaces = vec![
+            NetworkInterface {
+                name: "eth1".to_string(),
+                mac_addr: Some("00:11:22:33:44:56".to_string()),
+                addr: vec![],
+                index: 0,
+            },
+            NetworkInterface {
+                name: "ens3".to_string(),
+                mac_addr: Some("00:11:22:33:44:57".to_string()),
+                addr: vec![],
+                index: 0,
+            },
+            NetworkInterface {
+                name: "ens4".to_string(),
+                mac_addr: Some("00:11:22:33:44:58".to_string()),
+                addr: vec![],
+                index: 0,
+            },
+            NetworkInterface {
+                name: "ens5".to_string(),
+                mac_addr: Some("00:11:22:33:44:59".to_string()),
+                addr: vec![],
+                index: 0,
+            },
+            NetworkInterface {
+                name: "enx001122334460".to_string(),
+                mac_addr: Some("00:11:22:33:44:60".to_string()),
+                addr: vec![],
+                index: 0,
+            },
+        ];
+        let rename_map = HashMap::from([("00:11:22:33:44:56".to_string(), "lan0".to_string())]);
+        let altnames = HashMap::from([("ens5".to_string(), vec!["enx001122334459".to_string()])]);
+
+        let local_interfaces = detect_local_interfaces(&host, interfaces, &rename_map, &altnames);
+        assert_eq!(
+            local_interfaces,
+            HashMap::from([
+                ("eth1".to_string(), "lan0".to_string()),
+                ("eth2".to_string(), "ens3".to_string()),
+                ("eth3".to_string(), "ens4".to_string()),
+                ("eth4".to_string(), "enx001122334459".to_string()),
+                ("eth5".to_string(), "enx001122334460".to_string()),
+            ])
+        );
+    }
+
+    #[test]
+    fn probe_source_dir_in_order() -> Result<(), anyhow::Error> {
+        let dir = Path::new("_in_probe");
+        fs::create_dir_all(dir.join("etc-nmc"))?;
+        let cmdline = dir.join("cmdline");
+        let well_known_dirs = ["_in_probe/run-nmc", "_in_probe/etc-nmc"];
+
+        fs::write(
+            &cmdline,
+            "BOOT_IMAGE=/vmlinuz root=/dev/sda1 nmc.source=/run/initramfs/nmc quiet\n",
+        )?;
+        let from_cmdline = probe_source_dir(&cmdline, &well_known_dirs);
+
+        fs::write(&cmdline, "BOOT_IMAGE=/vmlinuz nmc.source= quiet\n")?;
+        let from_well_known_dir = probe_source_dir(&cmdline, &well_known_dirs);
+        let from_default = probe_source_dir(&dir.join("missing"), &well_known_dirs[..1]);
+
+        fs::remove_dir_all(dir)?;
+
+        assert_eq!(from_cmdline, "/run/initramfs/nmc");
+        assert_eq!(from_well_known_dir, "_in_probe/etc-nmc");
+        assert_eq!(from_default, "config");
+
+        Ok(())
+    }
+
+    #[test]
+    fn parse_link_altnames() -> Result<(), anyhow::Error> {
+        let output = r#"[
+            {"ifindex": 1, "ifname": "lo"},
+            {"ifindex": 2, "ifname": "eth0", "altnames": ["enp1s0", "enx001122334455"]}
+        ]"#;
+
+        assert_eq!(
+            parse_altnames(output)?,
+            HashMap::from([(
+                "eth0".to_string(),
+                vec!["enp1s0".to_string(), "enx001122334455".to_string()]
+            )])
+        );
+        assert!(parse_altnames("").is_err());
+
+        Ok(())
+    }
+
+    #[test]
+    fn list_renamed_interfaces() {
+        let host = Host {
+            hostname: "node1".to_string(),
+            serial: None,
+            interfaces: vec![
+                interface("eth2", Some("00:11:22:33:44:56"), "ethernet"),
+                interface("eth0", Some("00:11:22:33:44:55"), "ethernet"),
+                interface("eth2.100", None, "ethernet"),
+            ],
+        };
+        let local_interfaces = HashMap::from([
+            ("eth2".to_string(), "ens1f0".to_string()),
+            ("eth2.100".to_string(), "ens1f0.100".to_string()),
+        ]);
+
+        assert_eq!(
+            renamed_interfaces(&host, &local_interfaces),
+            vec![
+                Rename {
+                    from: "eth2".to_string(),
+                    to: "ens1f0".to_string(),
+                    mac_address: Some("00:11:22:33:44:56".to_string()),
+                },
+                Rename {
+                    from: "eth2.100".to_string(),
+                    to: "ens1f0.100".to_string(),
+                    mac_address: None,
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn load_rename_map_from_file() -> Result<(), anyhow::Error> {
+        let path = "_rename_map.yml";
+
+        fs::write(path, "\"00:11:22:33:44:AA\": lan0\n")?;
+        assert_eq!(
+            load_rename_map(path)?,
+            HashMap::from([("00:11:22:33:44:aa".to_string(), "lan0".to_string())])
+        );
+
+        fs::write(path, "00-11-22-33-44-aa: lan0\n")?;
+        assert_eq!(
+            load_rename_map(path).unwrap_err().to_string(),
+            "Invalid MAC address '00-11-22-33-44-aa' in _rename_map.yml"
+        );
+
+        // cleanup
+        fs::remove_file(path)?;
+
+        Ok(())
+    }
+
+    #[test]
+    fn load_recorded_interfaces() -> Result<(), anyhow::Error> {
+        let path = "_recorded_interfaces.yml";
+
+        fs::write(
+            path,
+            "- name: ens1f0\n  mac_address: 00:11:22:33:44:AA\n  altnames: [eth0]\n\
+            - name: eth1\n  mac_address: 00:11:22:33:44:bb\n",
+        )?;
+        let recorded = RecordedInterfaces::load(path)?;
+        let interfaces: Vec<(String, Option<String>)> = recorded
+            .network_interfaces()?
+            .into_iter()
+            .map(|nic| (nic.name, nic.mac_addr))
+            .collect();
+        assert_eq!(
+            interfaces,
+            vec![
+                ("ens1f0".to_string(), Some("00:11:22:33:44:aa".to_string())),
+                ("eth1".to_string(), Some("00:11:22:33:44:bb".to_string())),
+            ]
+        );
+        assert_eq!(
+            recorded.altnames()?,
+            HashMap::from([("ens1f0".to_string(), vec!["eth0".to_string()])])
+        );
+
+        fs::write(path, "- name: eth0\n  mac_address: 00-11-22-33-44-aa\n")?;
+        assert_eq!(
+            RecordedInterfaces::load(path).err().unwrap().to_string(),
+            "Invalid MAC address '00-11-22-33-44-aa' of interface 'eth0' in _recorded_interfaces.yml"
+        );
+
+        // cleanup
+        fs::remove_file(path)?;
+
+        Ok(())
+    }
+
+    #[test]
+    fn load_secrets_from_file() -> Result<(), anyhow::Error> {
+        let path = "_secrets.yml";
+
+        fs::write(path, "wifi_psk: hunter2\n")?;
+        fs::set_permissions(path, fs::Permissions::from_mode(0o644))?;
+        assert_eq!(
+            load_secrets(path, false)?,
+            HashMap::from([("wifi_psk".to_string(), "hunter2".to_string())])
+        );
+        assert_eq!(
+            load_secrets(path, true).unwrap_err().to_string(),
+            "Secrets file _secrets.yml is accessible by other users (mode 644)"
+        );
+
+        fs::set_permissions(path, fs::Permissions::from_mode(0o600))?;
+        assert!(load_secrets(path, true).is_ok());
+
+        // cleanup
+        fs::remove_file(path)?;
+
+        Ok(())
+    }
+
+    #[test]
+    fn resolve_secret_placeholders() {
+        let secrets = HashMap::from([("wifi_psk".to_string(), "hunter2".to_string())]);
+        std::env::set_var("NMC_SECRET_eap_password", "s3cret");
+
+        assert_eq!(
+            resolve_secrets(
+                "[wifi-security]\npsk=${SECRET:wifi_psk}\n[802-1x]\npassword=${SECRET:eap_password}\n",
+                &secrets
+            )
+            .unwrap(),
+            "[wifi-security]\npsk=hunter2\n[802-1x]\npassword=s3cret\n"
+        );
+        assert_eq!(
+            resolve_secrets("[ethernet]\nmtu=1500\n", &secrets).unwrap(),
+            "[ethernet]\nmtu=1500\n"
+        );
+        assert_eq!(
+            resolve_secrets("psk=${SECRET:missing}\n", &secrets)
+                .unwrap_err()
+                .to_string(),
+            "Unresolved secret 'missing'"
+        );
+        assert_eq!(
+            resolve_secrets("psk=${SECRET:wifi_psk\n", &secrets)
+                .unwrap_err()
+                .to_string(),
+            "Unterminated secret placeholder"
+        );
+    }
+
+    #[test]
+    fn filter_interfaces_successfully() {
+        let host = Host {
+            hostname: "node1".to_string(),
+            serial: None,
+            interfaces: vec![
+                Interface {
+                    logical_name: "eth0".to_string(),
+                    mac_address: Option::from("00:11:22:33:44:55".to_string()),
+                    additional_macs: Vec::new(),
+                    predictable_name: None,
+                    interface_type: "ethernet".to_string(),
+                },
+                Interface {
+                    logical_name: "eth1".to_string(),
+                    mac_address: Option::from("00:11:22:33:44:56".to_string()),
+                    additional_macs: Vec::new(),
+                    predictable_name: None,
+                    interface_type: "ethernet".to_string(),
+                },
+                Interface {
+                    logical_name: "bond0".to_string(),
+                    mac_address: None,
+                    additional_macs: Vec::new(),
+                    predictable_name: None,
+                    interface_type: "bond".to_string(),
+                },
+            ],
+        };
+        let local_interfaces = HashMap::from([("eth1".to_string(), "ens1f0".to_string())]);
+        let filter = ["bond0".to_string(), "ens1f0".to_string()];
+
+        let host = filter_interfaces(host, &local_interfaces, &filter).unwrap();
+        let names: Vec<&str> = host
+            .interfaces
+            .iter()
+            .map(|i| i.logical_name.as_str())
+            .collect();
+        assert_eq!(names, vec!["eth1", "bond0"]);
+    }
+
+    #[test]
+    fn order_interfaces_parents_first() {
+        let mut host = Host {
+            hostname: "node1".to_string(),
+            serial: None,
+            interfaces: vec![
+                interface("br0", None, "linux-bridge"),
+                interface("bond0.10", None, "vlan"),
+                Interface {
+                    additional_macs: vec![
+                        "00:11:22:33:44:55".to_string(),
+                        "00:11:22:33:44:56".to_string(),
+                    ],
+                    ..interface("bond0", None, "bond")
+                },
+                interface("eth1", None, "ethernet"),
+                interface("eth0", None, "ethernet"),
+                interface("ib0", None, "infiniband"),
+            ],
+        };
 
-        assert!(identify_host(hosts, &interfaces).is_none())
+        order_parents_first(&mut host);
+
+        let names: Vec<&str> = host
+            .interfaces
+            .iter()
+            .map(|i| i.logical_name.as_str())
+            .collect();
+        assert_eq!(names, ["eth1", "eth0", "ib0", "bond0", "bond0.10", "br0"]);
     }
 
     #[test]
-    fn parse_config_fails_due_to_missing_file() {
-        let error = parse_config("<missing>").unwrap_err();
-        assert!(error.to_string().contains("No such file or directory"))
+    fn filter_interfaces_fails_due_to_no_matches() {
+        let host = Host {
+            hostname: "node1".to_string(),
+            serial: None,
+            interfaces: vec![Interface {
+                logical_name: "eth0".to_string(),
+                mac_address: Option::from("00:11:22:33:44:55".to_string()),
+                additional_macs: Vec::new(),
+                predictable_name: None,
+                interface_type: "ethernet".to_string(),
+            }],
+        };
+
+        let error = filter_interfaces(host, &HashMap::new(), &["eth5".to_string()]).unwrap_err();
+        assert_eq!(
+            error.to_string(),
+            "None of the interfaces of host 'node1' match the provided names: eth5"
+        );
     }
 
     #[test]
-    fn parse_config_successfully() {
-        let hosts = parse_config("testdata/apply/config").unwrap();
+    fn read_ovs_connection_files() -> Result<(), anyhow::Error> {
+        let host = Host {
+            hostname: "node2".to_string(),
+            serial: None,
+            interfaces: vec![
+                interface("br0", None, "ovs-bridge"),
+                interface("eth0", Some("00:11:22:33:44:55"), "ethernet"),
+                interface("ovs0", None, "ovs-interface"),
+            ],
+        };
+        let local_interfaces = HashMap::from([("eth0".to_string(), "ens1f0".to_string())]);
+
+        let files = read_connection_files(&host, &local_interfaces, "testdata/apply", false)?;
+
+        let filenames: Vec<&str> = files.iter().map(|(f, _)| f.as_str()).collect();
         assert_eq!(
-            hosts,
-            vec![
-                Host {
-                    hostname: "node1".to_string(),
-                    interfaces: vec![
-                        Interface {
-                            logical_name: "eth0".to_string(),
-                            mac_address: Option::from("00:11:22:33:44:55".to_string()),
-                            interface_type: "ethernet".to_string(),
-                        },
-                        Interface {
-                            logical_name: "eth1".to_string(),
-                            mac_address: Option::from("00:11:22:33:44:58".to_string()),
-                            interface_type: "ethernet".to_string(),
-                        },
-                        Interface {
-                            logical_name: "eth2".to_string(),
-                            mac_address: Option::from("36:5e:6b:a2:ed:80".to_string()),
-                            interface_type: "ethernet".to_string(),
-                        },
-                        Interface {
-                            logical_name: "bond0".to_string(),
-                            mac_address: Option::from("00:11:22:aa:44:58".to_string()),
-                            interface_type: "bond".to_string(),
-                        },
-                    ],
-                },
-                Host {
-                    hostname: "node2".to_string(),
-                    interfaces: vec![
-                        Interface {
-                            logical_name: "eth0".to_string(),
-                            mac_address: Option::from("36:5e:6b:a2:ed:81".to_string()),
-                            interface_type: "ethernet".to_string(),
-                        },
-                        Interface {
-                            logical_name: "eth0.1365".to_string(),
-                            mac_address: None,
-                            interface_type: "vlan".to_string(),
-                        },
-                    ],
-                },
-            ]
-        )
+            filenames,
+            vec!["br0-br", "ens1f0", "ens1f0-port", "ovs0-if", "ovs0-port"]
+        );
+        assert!(files[2].1.contains("interface-name=ens1f0"));
+
+        let targets = resolve_targets(&host, &local_interfaces, "testdata/apply", "dest", false)?;
+        assert_eq!(
+            targets[2],
+            (
+                PathBuf::from("testdata/apply/node2/eth0-port.nmconnection"),
+                PathBuf::from("dest/ens1f0-port.nmconnection")
+            )
+        );
+
+        // The files keep the preconfigured names while their contents are adjusted.
+        let files = read_connection_files(&host, &local_interfaces, "testdata/apply", true)?;
+        let filenames: Vec<&str> = files.iter().map(|(f, _)| f.as_str()).collect();
+        assert_eq!(
+            filenames,
+            vec!["br0-br", "eth0", "eth0-port", "ovs0-if", "ovs0-port"]
+        );
+        assert!(files[2].1.contains("interface-name=ens1f0"));
+
+        let targets = resolve_targets(&host, &local_interfaces, "testdata/apply", "dest", true)?;
+        assert_eq!(targets[2].1, PathBuf::from("dest/eth0-port.nmconnection"));
+
+        Ok(())
     }
 
     #[test]
-    fn detect_interface_differences() {
+    fn resolve_targets_successfully() {
         let host = Host {
             hostname: "node1".to_string(),
+            serial: None,
             interfaces: vec![
                 Interface {
                     logical_name: "eth0".to_string(),
                     mac_address: Option::from("00:11:22:33:44:55".to_string()),
+                    additional_macs: Vec::new(),
+                    predictable_name: None,
                     interface_type: "ethernet".to_string(),
                 },
                 Interface {
                     logical_name: "eth0.1365".to_string(),
                     mac_address: None,
+                    additional_macs: Vec::new(),
+                    predictable_name: None,
                     interface_type: "vlan".to_string(),
                 },
                 Interface {
-                    logical_name: "eth2".to_string(),
+                    logical_name: "eth1".to_string(),
                     mac_address: Option::from("00:11:22:33:44:56".to_string()),
+                    additional_macs: Vec::new(),
+                    predictable_name: None,
                     interface_type: "ethernet".to_string(),
                 },
-                Interface {
-                    logical_name: "eth2.bridge".to_string(),
-                    mac_address: None,
-                    interface_type: "linux-bridge".to_string(),
-                },
-                Interface {
-                    logical_name: "bond0".to_string(),
-                    mac_address: Option::from("00:11:22:33:44:58".to_string()),
-                    interface_type: "bond".to_string(),
-                },
             ],
         };
-        let interfaces = vec![
-            NetworkInterface {
-                name: "eth0".to_string(),
-                mac_addr: Some("00:11:22:33:44:55".to_string()),
-                addr: vec![],
-                index: 0,
-            },
-            NetworkInterface {
-                name: "eth0.1365".to_string(), // VLAN
-                addr: vec![],
-                mac_addr: Some("00:11:22:33:44:55".to_string()),
-                index: 0,
-            },
-            NetworkInterface {
-                name: "ens1f0".to_string(),
-                mac_addr: Some("00:11:22:33:44:56".to_string()),
-                addr: vec![],
-                index: 0,
-            },
+        let local_interfaces = HashMap::from([
+            ("eth0".to_string(), "ens1f0".to_string()),
+            ("eth0.1365".to_string(), "ens1f0.1365".to_string()),
+        ]);
+
+        let targets = resolve_targets(&host, &local_interfaces, "config", "dest", false).unwrap();
+        assert_eq!(
+            targets,
+            vec![
+                (
+                    PathBuf::from("config/node1/eth0.nmconnection"),
+                    PathBuf::from("dest/ens1f0.nmconnection")
+                ),
+                (
+                    PathBuf::from("config/node1/eth0.1365.nmconnection"),
+                    PathBuf::from("dest/ens1f0.1365.nmconnection")
+                ),
+                (
+                    PathBuf::from("config/node1/eth1.nmconnection"),
+                    PathBuf::from("dest/eth1.nmconnection")
+                ),
+            ]
+        );
+    }
+
+    #[test]
+    fn merge_existing_connection_file() -> Result<(), anyhow::Error> {
+        let destination_dir = "_out_merge";
+        let destination = Path::new(destination_dir).join("eth0.nmconnection");
+
+        fs::create_dir_all(destination_dir)?;
+        fs::write(
+            &destination,
+            "[connection]\nid=eth0\nautoconnect-priority=5\n\n[ethtool]\nring-rx=4096\n",
+        )?;
+
+        let contents = "[connection]\nid=eth0\nautoconnect-priority=10\n";
+        let merged = merge_connection_file(&destination, contents, contents)?;
+        assert_eq!(
+            merged,
+            Some((
+                "[connection]\nid=eth0\nautoconnect-priority=10\n\n[ethtool]\nring-rx=4096\n"
+                    .to_string(),
+                vec![format!(
+                    "Overriding connection.autoconnect-priority in {destination:?}: '5' -> '10'"
+                )]
+            ))
+        );
+
+        let contents = "[connection]\nid=eth1\n";
+        let merged = merge_connection_file(
+            &Path::new(destination_dir).join("eth1.nmconnection"),
+            contents,
+            contents,
+        )?;
+        assert_eq!(merged, None);
+
+        // cleanup
+        fs::remove_dir_all(destination_dir)?;
+
+        Ok(())
+    }
+
+    #[test]
+    fn merge_connection_file_redacts_secrets() -> Result<(), anyhow::Error> {
+        let destination_dir = "_out_merge_secrets";
+        let destination = Path::new(destination_dir).join("wlan0.nmconnection");
+
+        fs::create_dir_all(destination_dir)?;
+        fs::write(
+            &destination,
+            "[connection]\nid=wlan0\nautoconnect-priority=5\n\n[wifi-security]\nkey-mgmt=wpa-psk\npsk=old-psk\n",
+        )?;
+
+        let template = "[connection]\nid=wlan0\nautoconnect-priority=10\n\n[wifi-security]\npsk=${SECRET:wifi_psk}\n";
+        let secrets = HashMap::from([("wifi_psk".to_string(), "new-psk".to_string())]);
+        let contents = resolve_secrets(template, &secrets)?;
+        let merged = merge_connection_file(&destination, &contents, template);
+
+        fs::remove_dir_all(destination_dir)?;
+
+        let (merged, warnings) = merged?.expect("existing file is merged");
+        assert!(merged.contains("psk=new-psk\n"));
+        assert!(merged.contains("key-mgmt=wpa-psk\n"));
+        assert_eq!(
+            warnings,
+            [
+                format!(
+                    "Overriding connection.autoconnect-priority in {destination:?}: '5' -> '10'"
+                ),
+                format!("Overriding wifi-security.psk in {destination:?}: <redacted>"),
+            ]
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn delete_missing_connection_files() -> Result<(), anyhow::Error> {
+        let destination_dir = Path::new("_out_delete_missing");
+        fs::create_dir_all(destination_dir)?;
+
+        // "eth1" was stored by a previous run, "eth2" is annotated and "custom" is not owned by NMC.
+        fs::write(
+            destination_dir.join(".nmc-manifest"),
+            "eth0.nmconnection\neth1.nmconnection\n",
+        )?;
+        fs::write(destination_dir.join("eth0.nmconnection"), "")?;
+        fs::write(destination_dir.join("eth1.nmconnection"), "")?;
+        fs::write(
+            destination_dir.join("eth2.nmconnection"),
+            "# Generated by nmc 0.2.3 (nmstate 2.2.26)\n[connection]\n",
+        )?;
+        fs::write(destination_dir.join("custom.nmconnection"), "")?;
+        fs::write(destination_dir.join("bond0.nmconnection"), "")?;
+
+        let managed = [
+            destination_dir.join("eth0.nmconnection"),
+            destination_dir.join("bond0.nmconnection"),
         ];
+        let deleted = delete_missing_files(destination_dir, &managed);
+
+        let mut remaining: Vec<String> = fs::read_dir(destination_dir)?
+            .map(|entry| Ok(entry?.file_name().to_string_lossy().into_owned()))
+            .collect::<Result<_, io::Error>>()?;
+        remaining.sort();
+        let manifest = fs::read_to_string(destination_dir.join(".nmc-manifest"))?;
+
+        fs::remove_dir_all(destination_dir)?;
 
-        let local_interfaces = detect_local_interfaces(&host, interfaces);
         assert_eq!(
-            local_interfaces,
-            HashMap::from([
-                ("eth2".to_string(), "ens1f0".to_string()),
-                ("eth2.bridge".to_string(), "ens1f0.bridge".to_string())
-            ])
-        )
+            deleted?,
+            [
+                destination_dir.join("eth1.nmconnection"),
+                destination_dir.join("eth2.nmconnection")
+            ]
+        );
+        assert_eq!(
+            remaining,
+            [
+                ".nmc-manifest",
+                "bond0.nmconnection",
+                "custom.nmconnection",
+                "eth0.nmconnection"
+            ]
+        );
+        assert_eq!(manifest, "bond0.nmconnection\neth0.nmconnection\n");
+
+        Ok(())
+    }
+
+    #[test]
+    fn apply_deletes_missing_files_of_filtered_interfaces() -> Result<(), anyhow::Error> {
+        let destination_dir = "_out_delete_missing_filtered";
+        let simulated = "_in_delete_missing_filtered.yaml";
+        fs::write(
+            simulated,
+            "- name: ens1f0\n  mac_address: 00:11:22:33:44:55\n\
+             - name: eth1\n  mac_address: 00:11:22:33:44:57\n\
+             - name: eth2\n  mac_address: 00:11:22:33:44:56\n",
+        )?;
+        fs::create_dir_all(destination_dir)?;
+        fs::write(
+            Path::new(destination_dir).join(".nmc-manifest"),
+            "eth3.nmconnection\n",
+        )?;
+        fs::write(Path::new(destination_dir).join("eth3.nmconnection"), "")?;
+
+        // The files of the interfaces which are not selected are still part of the config.
+        let options = ApplyOptions {
+            interface_filter: vec!["eth1".to_string()],
+            delete_missing: true,
+            simulate_interfaces: Some(simulated.to_string()),
+            ..Default::default()
+        };
+        let summaries = apply("testdata/apply/golden", destination_dir, &options);
+        let manifest = fs::read_to_string(Path::new(destination_dir).join(".nmc-manifest"));
+
+        fs::remove_dir_all(destination_dir)?;
+        fs::remove_file(simulated)?;
+
+        let summaries = summaries?;
+        assert_eq!(
+            summaries[0].deleted,
+            [Path::new(destination_dir).join("eth3.nmconnection")]
+        );
+        assert_eq!(
+            manifest?,
+            "ens1f0.1365.nmconnection\nens1f0.nmconnection\neth1.nmconnection\neth2.nmconnection\n"
+        );
+
+        Ok(())
     }
 
     #[test]
@@ -440,39 +3962,56 @@ mod tests {
         let destination_dir = "_out";
         let host = Host {
             hostname: "node1".to_string(),
+            serial: None,
             interfaces: vec![
                 Interface {
                     logical_name: "eth0".to_string(),
                     mac_address: Option::from("00:11:22:33:44:55".to_string()),
+                    additional_macs: Vec::new(),
+                    predictable_name: None,
                     interface_type: "ethernet".to_string(),
                 },
                 Interface {
                     logical_name: "eth0.1365".to_string(),
                     mac_address: None,
+                    additional_macs: Vec::new(),
+                    predictable_name: None,
                     interface_type: "vlan".to_string(),
                 },
                 Interface {
                     logical_name: "eth2".to_string(),
                     mac_address: Option::from("00:11:22:33:44:56".to_string()),
+                    additional_macs: Vec::new(),
+                    predictable_name: None,
                     interface_type: "ethernet".to_string(),
                 },
                 Interface {
                     logical_name: "eth1".to_string(),
                     mac_address: Option::from("00:11:22:33:44:57".to_string()),
+                    additional_macs: Vec::new(),
+                    predictable_name: None,
                     interface_type: "ethernet".to_string(),
                 },
                 Interface {
                     logical_name: "bond0".to_string(),
                     mac_address: Option::from("00:11:22:33:44:58".to_string()),
+                    additional_macs: Vec::new(),
+                    predictable_name: None,
                     interface_type: "bond".to_string(),
                 },
             ],
         };
         let detected_interfaces = HashMap::from([("eth2".to_string(), "eth4".to_string())]);
 
-        assert!(
-            copy_connection_files(host, detected_interfaces, source_dir, destination_dir).is_ok()
-        );
+        assert!(copy_connection_files(
+            host,
+            detected_interfaces,
+            source_dir,
+            destination_dir,
+            &HashMap::new(),
+            &ApplyOptions::default()
+        )
+        .is_ok());
 
         let source_path = Path::new(source_dir).join("node1");
         let destination_path = Path::new(destination_dir);
@@ -497,6 +4036,269 @@ mod tests {
         fs::remove_dir_all(destination_dir)
     }
 
+    #[test]
+    fn copy_connection_files_skips_unchanged() -> Result<(), anyhow::Error> {
+        let destination_dir = "_out_unchanged";
+        let host = Host {
+            hostname: "node1".to_string(),
+            serial: None,
+            interfaces: vec![Interface {
+                logical_name: "eth0".to_string(),
+                mac_address: Some("00:11:22:33:44:55".to_string()),
+                additional_macs: Vec::new(),
+                predictable_name: None,
+                interface_type: "ethernet".to_string(),
+            }],
+        };
+        let copy = |options: &ApplyOptions| {
+            copy_connection_files(
+                host.clone(),
+                HashMap::new(),
+                "testdata/apply",
+                destination_dir,
+                &HashMap::new(),
+                options,
+            )
+        };
+
+        copy(&ApplyOptions::default())?;
+
+        let destination = Path::new(destination_dir).join("eth0.nmconnection");
+        let modified = SystemTime::UNIX_EPOCH;
+        fs::File::options()
+            .write(true)
+            .open(&destination)?
+            .set_modified(modified)?;
+
+        copy(&ApplyOptions::default())?;
+        assert_eq!(fs::metadata(&destination)?.modified()?, modified);
+
+        copy(&ApplyOptions {
+            force: true,
+            ..Default::default()
+        })?;
+        assert_ne!(fs::metadata(&destination)?.modified()?, modified);
+
+        // cleanup
+        fs::remove_dir_all(destination_dir)?;
+
+        Ok(())
+    }
+
+    #[test]
+    fn copy_connection_files_staged() -> Result<(), anyhow::Error> {
+        let destination_dir = "_out_staged";
+        let host = Host {
+            hostname: "node1".to_string(),
+            serial: None,
+            interfaces: vec![Interface {
+                logical_name: "eth0".to_string(),
+                mac_address: Some("00:11:22:33:44:55".to_string()),
+                additional_macs: Vec::new(),
+                predictable_name: None,
+                interface_type: "ethernet".to_string(),
+            }],
+        };
+
+        // A previously preserved symlink is replaced instead of writing to its target.
+        let target = Path::new(destination_dir).join("target.nmconnection");
+        let destination = Path::new(destination_dir).join("eth0.nmconnection");
+        fs::create_dir_all(destination_dir)?;
+        fs::write(&target, "[connection]\n")?;
+        symlink(fs::canonicalize(&target)?, &destination)?;
+
+        let options = ApplyOptions {
+            staged: true,
+            ..Default::default()
+        };
+        let stored = copy_connection_files(
+            host,
+            HashMap::new(),
+            "testdata/apply",
+            destination_dir,
+            &HashMap::new(),
+            &options,
+        )?;
+
+        assert_eq!(stored.len(), 1);
+        assert!(!destination.is_symlink());
+        assert_eq!(
+            fs::read_to_string(&destination)?,
+            fs::read_to_string("testdata/apply/node1/eth0.nmconnection")?
+        );
+        assert_eq!(
+            fs::metadata(&destination)?.permissions().mode() & 0o777,
+            0o600
+        );
+        assert_eq!(fs::read_to_string(&target)?, "[connection]\n");
+
+        // The staging dir is removed.
+        let mut entries: Vec<_> = fs::read_dir(destination_dir)?
+            .map(|entry| entry.map(|e| e.file_name()))
+            .collect::<Result<_, _>>()?;
+        entries.sort();
+        assert_eq!(entries, ["eth0.nmconnection", "target.nmconnection"]);
+
+        // cleanup
+        fs::remove_dir_all(destination_dir)?;
+
+        Ok(())
+    }
+
+    #[test]
+    fn verify_stored_connection_files() -> Result<(), anyhow::Error> {
+        let destination_dir = "_out_verify";
+        let host = Host {
+            hostname: "node1".to_string(),
+            serial: None,
+            interfaces: vec![Interface {
+                logical_name: "eth0".to_string(),
+                mac_address: Some("00:11:22:33:44:55".to_string()),
+                additional_macs: Vec::new(),
+                predictable_name: None,
+                interface_type: "ethernet".to_string(),
+            }],
+        };
+
+        let stored = copy_connection_files(
+            host,
+            HashMap::new(),
+            "testdata/apply",
+            destination_dir,
+            &HashMap::new(),
+            &ApplyOptions::default(),
+        )?;
+        assert_eq!(stored.len(), 1);
+        assert!(verify_connection_files(&stored).is_ok());
+
+        let (path, contents) = &stored[0];
+
+        fs::set_permissions(path, fs::Permissions::from_mode(0o644))?;
+        assert!(verify_connection_files(&stored)
+            .unwrap_err()
+            .to_string()
+            .starts_with("Unexpected mode"));
+
+        fs::write(path, &contents[..contents.len() / 2])?;
+        assert!(verify_connection_files(&stored)
+            .unwrap_err()
+            .to_string()
+            .ends_with("do not match the applied config"));
+
+        // cleanup
+        fs::remove_dir_all(destination_dir)?;
+
+        Ok(())
+    }
+
+    #[test]
+    fn copy_symlinked_connection_files() -> Result<(), anyhow::Error> {
+        let source_dir = "_in_symlinks";
+        let destination_dir = "_out_symlinks";
+        let host_dir = Path::new(source_dir).join("node1");
+        let common_dir = Path::new(source_dir).join("common");
+
+        fs::create_dir_all(&host_dir)?;
+        fs::create_dir_all(&common_dir)?;
+        fs::write(
+            common_dir.join("eth0.nmconnection"),
+            "[connection]\nid=eth0\n",
+        )?;
+        symlink(
+            "../common/eth0.nmconnection",
+            host_dir.join("eth0.nmconnection"),
+        )?;
+        symlink(
+            "../common/eth1.nmconnection",
+            host_dir.join("eth1.nmconnection"),
+        )?;
+
+        let host = Host {
+            hostname: "node1".to_string(),
+            serial: None,
+            interfaces: vec![
+                Interface {
+                    logical_name: "eth0".to_string(),
+                    mac_address: Some("00:11:22:33:44:55".to_string()),
+                    additional_macs: Vec::new(),
+                    predictable_name: None,
+                    interface_type: "ethernet".to_string(),
+                },
+                Interface {
+                    logical_name: "eth1".to_string(),
+                    mac_address: Some("00:11:22:33:44:56".to_string()),
+                    additional_macs: Vec::new(),
+                    predictable_name: None,
+                    interface_type: "ethernet".to_string(),
+                },
+            ],
+        };
+
+        // The broken "eth1" symlink is skipped.
+        let files = read_connection_files(&host, &HashMap::new(), source_dir, false)?;
+        assert_eq!(
+            files,
+            vec![("eth0".to_string(), "[connection]\nid=eth0\n".to_string())]
+        );
+
+        copy_connection_files(
+            host.clone(),
+            HashMap::new(),
+            source_dir,
+            destination_dir,
+            &HashMap::new(),
+            &ApplyOptions::default(),
+        )?;
+        let destination = Path::new(destination_dir).join("eth0.nmconnection");
+        assert!(!destination.is_symlink());
+        assert_eq!(fs::read_to_string(&destination)?, "[connection]\nid=eth0\n");
+        assert!(!Path::new(destination_dir)
+            .join("eth1.nmconnection")
+            .exists());
+
+        copy_connection_files(
+            host.clone(),
+            HashMap::new(),
+            source_dir,
+            destination_dir,
+            &HashMap::new(),
+            &ApplyOptions {
+                preserve_symlinks: true,
+                ..Default::default()
+            },
+        )?;
+        assert!(destination.is_symlink());
+        assert_eq!(
+            fs::read_link(&destination)?,
+            fs::canonicalize(common_dir.join("eth0.nmconnection"))?
+        );
+
+        // Copying again replaces the link instead of overwriting the source file.
+        fs::write(
+            common_dir.join("eth0.nmconnection"),
+            "[connection]\nid=eth0-new\n",
+        )?;
+        copy_connection_files(
+            host,
+            HashMap::new(),
+            source_dir,
+            destination_dir,
+            &HashMap::new(),
+            &ApplyOptions::default(),
+        )?;
+        assert!(!destination.is_symlink());
+        assert_eq!(
+            fs::read_to_string(common_dir.join("eth0.nmconnection"))?,
+            "[connection]\nid=eth0-new\n"
+        );
+
+        // cleanup
+        fs::remove_dir_all(source_dir)?;
+        fs::remove_dir_all(destination_dir)?;
+
+        Ok(())
+    }
+
     #[test]
     fn generate_keyfile_path() {
         assert_eq!(
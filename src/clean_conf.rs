@@ -0,0 +1,150 @@
+use std::collections::HashMap;
+use std::fs;
+use std::path::PathBuf;
+use std::process::Command;
+
+use anyhow::{anyhow, Context};
+use log::{debug, info};
+use network_interface::{NetworkInterface, NetworkInterfaceConfig};
+
+use crate::apply_conf::{
+    detect_local_interfaces, identify_host, local_altnames, parse_config, resolve_targets,
+};
+use crate::error::ConfigError;
+use crate::HOST_MAPPING_FILE;
+
+/// Remove the *.nmconnection files which were stored in the `destination_dir`
+/// when applying the network configurations of the identified host.
+///
+/// Only the files which would be written by applying the configurations are removed
+/// so any other connections in the `destination_dir` are left intact.
+pub(crate) fn clean(
+    source_dir: &str,
+    destination_dir: &str,
+    dry_run: bool,
+    reload: bool,
+) -> Result<(), anyhow::Error> {
+    let hosts = parse_config(source_dir, HOST_MAPPING_FILE, false).context("Parsing config")?;
+    debug!("Loaded hosts config: {hosts:?}");
+
+    let network_interfaces = NetworkInterface::show()?;
+    debug!("Retrieved network interfaces: {network_interfaces:?}");
+
+    let host = identify_host(hosts, &network_interfaces).ok_or(ConfigError::NoMatchingHost)?;
+    info!(host = host.hostname.as_str(); "Identified host: {}", host.hostname);
+
+    let altnames = local_altnames().unwrap_or_else(|err| {
+        debug!("Retrieving alternative interface names failed: {err:#}");
+        HashMap::new()
+    });
+
+    let local_interfaces =
+        detect_local_interfaces(&host, network_interfaces, &HashMap::new(), &altnames);
+    let files = applied_files(resolve_targets(
+        &host,
+        &local_interfaces,
+        source_dir,
+        destination_dir,
+        false,
+    )?);
+
+    if dry_run {
+        files.iter().for_each(|file| println!("{}", file.display()));
+        return Ok(());
+    }
+
+    for file in &files {
+        fs::remove_file(file).with_context(|| format!("Removing {file:?}"))?;
+        info!("Removed {file:?}");
+    }
+
+    if reload && !files.is_empty() {
+        reload_connections()?;
+    }
+
+    Ok(())
+}
+
+/// Destinations of the resolved targets which are present.
+fn applied_files(targets: Vec<(PathBuf, PathBuf)>) -> Vec<PathBuf> {
+    targets
+        .into_iter()
+        .map(|(_, destination)| destination)
+        .filter(|destination| destination.is_file())
+        .collect()
+}
+
+fn reload_connections() -> Result<(), anyhow::Error> {
+    let output = Command::new("nmcli")
+        .args(["connection", "reload"])
+        .output()
+        .context("Running nmcli")?;
+
+    if !output.status.success() {
+        return Err(anyhow!(
+            "Reloading connections failed: {}",
+            String::from_utf8_lossy(&output.stderr).trim()
+        ));
+    }
+
+    info!("Reloaded NetworkManager connections");
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+    use std::fs;
+    use std::path::PathBuf;
+
+    use crate::apply_conf::resolve_targets;
+    use crate::clean_conf::applied_files;
+    use crate::types::{Host, Interface};
+
+    #[test]
+    fn applied_files_only_include_present_targets() -> Result<(), anyhow::Error> {
+        let destination_dir = "_out_clean";
+        fs::create_dir_all(destination_dir)?;
+        fs::write("_out_clean/ens1f0.nmconnection", "")?;
+        fs::write("_out_clean/unrelated.nmconnection", "")?;
+
+        let host = Host {
+            hostname: "node1".to_string(),
+            serial: None,
+            interfaces: vec![
+                Interface {
+                    logical_name: "eth0".to_string(),
+                    mac_address: Option::from("00:11:22:33:44:55".to_string()),
+                    additional_macs: Vec::new(),
+                    predictable_name: None,
+                    interface_type: "ethernet".to_string(),
+                },
+                Interface {
+                    logical_name: "eth1".to_string(),
+                    mac_address: Option::from("00:11:22:33:44:56".to_string()),
+                    additional_macs: Vec::new(),
+                    predictable_name: None,
+                    interface_type: "ethernet".to_string(),
+                },
+            ],
+        };
+        let local_interfaces = HashMap::from([("eth0".to_string(), "ens1f0".to_string())]);
+
+        let targets = resolve_targets(
+            &host,
+            &local_interfaces,
+            "testdata/apply",
+            destination_dir,
+            false,
+        )?;
+        assert_eq!(
+            applied_files(targets),
+            vec![PathBuf::from("_out_clean/ens1f0.nmconnection")]
+        );
+
+        // cleanup
+        fs::remove_dir_all(destination_dir)?;
+
+        Ok(())
+    }
+}
@@ -0,0 +1,670 @@
+use std::collections::BTreeMap;
+use std::fmt;
+use std::net::IpAddr;
+
+use anyhow::{anyhow, Context};
+use serde::de::IgnoredAny;
+use serde::{Deserialize, Deserializer};
+use serde_json::{json, Map, Value};
+
+/// Format of the desired state files in the config dir.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub(crate) enum InputFormat {
+    #[default]
+    Nmstate,
+    /// cloud-init network config version 2.
+    CloudInit,
+    Netplan,
+}
+
+impl InputFormat {
+    pub(crate) fn from_arg(value: &str) -> Self {
+        match value {
+            "cloud-init" => InputFormat::CloudInit,
+            "netplan" => InputFormat::Netplan,
+            _ => InputFormat::Nmstate,
+        }
+    }
+}
+
+impl fmt::Display for InputFormat {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            InputFormat::Nmstate => write!(f, "nmstate"),
+            InputFormat::CloudInit => write!(f, "cloud-init"),
+            InputFormat::Netplan => write!(f, "netplan"),
+        }
+    }
+}
+
+/// Network config version 2 as used by cloud-init and netplan.
+#[derive(Deserialize, Debug)]
+struct NetworkConfig {
+    version: u8,
+    /// Only NetworkManager is relevant regardless of the renderer.
+    #[serde(default, rename = "renderer")]
+    _renderer: Option<IgnoredAny>,
+    #[serde(default)]
+    ethernets: BTreeMap<String, Device>,
+    #[serde(default)]
+    bonds: BTreeMap<String, Device>,
+    #[serde(default)]
+    bridges: BTreeMap<String, Device>,
+    #[serde(default)]
+    vlans: BTreeMap<String, Device>,
+    #[serde(flatten)]
+    unsupported: BTreeMap<String, serde_yaml::Value>,
+}
+
+#[derive(Deserialize, Debug, Default)]
+#[serde(rename_all = "kebab-case")]
+struct Device {
+    #[serde(rename = "match")]
+    match_rules: Option<MatchRules>,
+    set_name: Option<String>,
+    macaddress: Option<String>,
+    mtu: Option<u32>,
+    #[serde(default)]
+    addresses: Vec<String>,
+    #[serde(default, deserialize_with = "deserialize_bool")]
+    dhcp4: bool,
+    #[serde(default, deserialize_with = "deserialize_bool")]
+    dhcp6: bool,
+    gateway4: Option<String>,
+    gateway6: Option<String>,
+    nameservers: Option<Nameservers>,
+    #[serde(default)]
+    routes: Vec<Route>,
+    /// Ports of bonds and bridges.
+    #[serde(default)]
+    interfaces: Vec<String>,
+    #[serde(default)]
+    parameters: BTreeMap<String, serde_yaml::Value>,
+    /// VLAN ID.
+    id: Option<u16>,
+    /// VLAN parent interface.
+    link: Option<String>,
+    #[serde(flatten)]
+    unsupported: BTreeMap<String, serde_yaml::Value>,
+}
+
+#[derive(Deserialize, Debug)]
+struct MatchRules {
+    name: Option<String>,
+    macaddress: Option<String>,
+    #[serde(flatten)]
+    unsupported: BTreeMap<String, serde_yaml::Value>,
+}
+
+#[derive(Deserialize, Debug)]
+struct Nameservers {
+    #[serde(default)]
+    addresses: Vec<String>,
+    #[serde(default)]
+    search: Vec<String>,
+    #[serde(flatten)]
+    unsupported: BTreeMap<String, serde_yaml::Value>,
+}
+
+#[derive(Deserialize, Debug)]
+struct Route {
+    to: String,
+    via: String,
+    metric: Option<u32>,
+    #[serde(flatten)]
+    unsupported: BTreeMap<String, serde_yaml::Value>,
+}
+
+/// Accept the YAML 1.1 style booleans (e.g. `yes`) commonly found in cloud-init configs.
+fn deserialize_bool<'de, D: Deserializer<'de>>(deserializer: D) -> Result<bool, D::Error> {
+    match serde_yaml::Value::deserialize(deserializer)? {
+        serde_yaml::Value::Bool(value) => Ok(value),
+        serde_yaml::Value::String(value) => match value.to_lowercase().as_str() {
+            "yes" | "true" | "on" => Ok(true),
+            "no" | "false" | "off" => Ok(false),
+            _ => Err(serde::de::Error::custom(format!(
+                "invalid boolean '{value}'"
+            ))),
+        },
+        value => Err(serde::de::Error::custom(format!(
+            "invalid boolean {value:?}"
+        ))),
+    }
+}
+
+/// Convert the desired state into the nmstate YAML format.
+///
+/// Returns the converted state and warnings about the dropped configuration.
+pub(crate) fn to_nmstate(
+    data: &str,
+    format: InputFormat,
+) -> Result<(String, Vec<String>), anyhow::Error> {
+    if format == InputFormat::Nmstate {
+        return Ok((data.to_owned(), Vec::new()));
+    }
+
+    let config = parse_network_config(data, format).context(format!("Parsing {format} config"))?;
+
+    let mut dropped = Vec::new();
+    let state = convert_network_config(config, &mut dropped)?;
+
+    let mut warnings = Vec::new();
+    if !dropped.is_empty() {
+        warnings.push(format!(
+            "Dropped unsupported {format} keys: {}",
+            dropped.join(", ")
+        ));
+    }
+
+    Ok((serde_yaml::to_string(&state)?, warnings))
+}
+
+/// Parse the network config which is nested under `network`.
+/// cloud-init configs may also contain it as the root element.
+fn parse_network_config(data: &str, format: InputFormat) -> Result<NetworkConfig, anyhow::Error> {
+    let mut value: serde_yaml::Value = serde_yaml::from_str(data)?;
+
+    match value.get_mut("network") {
+        Some(network) => value = std::mem::take(network),
+        None if format == InputFormat::Netplan => {
+            return Err(anyhow!("Missing 'network' element"));
+        }
+        None => {}
+    }
+
+    let config: NetworkConfig = serde_yaml::from_value(value)?;
+    if config.version != 2 {
+        return Err(anyhow!(
+            "Unsupported network config version {}",
+            config.version
+        ));
+    }
+
+    Ok(config)
+}
+
+fn convert_network_config(
+    config: NetworkConfig,
+    dropped: &mut Vec<String>,
+) -> Result<Value, anyhow::Error> {
+    dropped.extend(config.unsupported.keys().cloned());
+
+    // Ethernets may be renamed while bonds, bridges and VLANs refer to them by their IDs.
+    let names: BTreeMap<&str, String> = config
+        .ethernets
+        .iter()
+        .map(|(id, device)| (id.as_str(), ethernet_name(id, device)))
+        .collect();
+    let name_of = |id: &str| names.get(id).cloned().unwrap_or_else(|| id.to_owned());
+
+    let mut interfaces = Vec::new();
+    let mut routes = Vec::new();
+    let mut dns_servers: Vec<String> = Vec::new();
+    let mut dns_search: Vec<String> = Vec::new();
+
+    let devices = config
+        .ethernets
+        .iter()
+        .map(|d| ("ethernets", "ethernet", d))
+        .chain(config.bonds.iter().map(|d| ("bonds", "bond", d)))
+        .chain(
+            config
+                .bridges
+                .iter()
+                .map(|d| ("bridges", "linux-bridge", d)),
+        )
+        .chain(config.vlans.iter().map(|d| ("vlans", "vlan", d)));
+
+    for (section, interface_type, (id, device)) in devices {
+        let name = name_of(id);
+        dropped.extend(unsupported_keys(
+            &format!("{section}.{id}"),
+            device,
+            interface_type,
+        ));
+
+        let mut interface = Map::new();
+        interface.insert("name".to_owned(), json!(name));
+        interface.insert("type".to_owned(), json!(interface_type));
+        interface.insert("state".to_owned(), json!("up"));
+
+        let mac_address = device
+            .match_rules
+            .as_ref()
+            .and_then(|m| m.macaddress.as_ref())
+            .or(device.macaddress.as_ref());
+        if let Some(mac_address) = mac_address {
+            interface.insert("mac-address".to_owned(), json!(mac_address));
+        }
+        if let Some(mtu) = device.mtu {
+            interface.insert("mtu".to_owned(), json!(mtu));
+        }
+
+        let (ipv4, ipv6) = ip_config(&name, device)?;
+        interface.insert("ipv4".to_owned(), ipv4);
+        interface.insert("ipv6".to_owned(), ipv6);
+
+        match interface_type {
+            "bond" => {
+                let ports: Vec<String> = device.interfaces.iter().map(|i| name_of(i)).collect();
+                interface.insert("link-aggregation".to_owned(), bond_config(device, ports));
+            }
+            "linux-bridge" => {
+                let ports: Vec<Value> = device
+                    .interfaces
+                    .iter()
+                    .map(|i| json!({ "name": name_of(i) }))
+                    .collect();
+                interface.insert("bridge".to_owned(), bridge_config(device, ports));
+            }
+            "vlan" => {
+                let (Some(vlan_id), Some(link)) = (device.id, &device.link) else {
+                    return Err(anyhow!("VLAN {name} requires both 'id' and 'link'"));
+                };
+                interface.insert(
+                    "vlan".to_owned(),
+                    json!({ "base-iface": name_of(link), "id": vlan_id }),
+                );
+            }
+            _ => {}
+        }
+
+        interfaces.push(Value::Object(interface));
+
+        for (destination, gateway) in [("0.0.0.0/0", &device.gateway4), ("::/0", &device.gateway6)]
+        {
+            if let Some(gateway) = gateway {
+                routes.push(json!({
+                    "destination": destination,
+                    "next-hop-interface": name,
+                    "next-hop-address": gateway,
+                }));
+            }
+        }
+
+        for route in &device.routes {
+            routes.push(route_config(&name, route)?);
+        }
+
+        if let Some(nameservers) = &device.nameservers {
+            for server in &nameservers.addresses {
+                if !dns_servers.contains(server) {
+                    dns_servers.push(server.to_owned());
+                }
+            }
+            for search in &nameservers.search {
+                if !dns_search.contains(search) {
+                    dns_search.push(search.to_owned());
+                }
+            }
+        }
+    }
+
+    let mut state = Map::new();
+    state.insert("interfaces".to_owned(), Value::Array(interfaces));
+
+    if !routes.is_empty() {
+        state.insert("routes".to_owned(), json!({ "config": routes }));
+    }
+    if !dns_servers.is_empty() || !dns_search.is_empty() {
+        state.insert(
+            "dns-resolver".to_owned(),
+            json!({ "config": { "server": dns_servers, "search": dns_search } }),
+        );
+    }
+
+    Ok(Value::Object(state))
+}
+
+/// Collect the paths of the device keys which are not converted.
+fn unsupported_keys(path: &str, device: &Device, interface_type: &str) -> Vec<String> {
+    let mut keys: Vec<String> = device
+        .unsupported
+        .keys()
+        .map(|key| format!("{path}.{key}"))
+        .collect();
+
+    if let Some(match_rules) = &device.match_rules {
+        keys.extend(
+            match_rules
+                .unsupported
+                .keys()
+                .map(|key| format!("{path}.match.{key}")),
+        );
+    }
+
+    if let Some(nameservers) = &device.nameservers {
+        keys.extend(
+            nameservers
+                .unsupported
+                .keys()
+                .map(|key| format!("{path}.nameservers.{key}")),
+        );
+    }
+
+    for (index, route) in device.routes.iter().enumerate() {
+        keys.extend(
+            route
+                .unsupported
+                .keys()
+                .map(|key| format!("{path}.routes[{index}].{key}")),
+        );
+    }
+
+    let is_supported_parameter = |parameter: &str| match interface_type {
+        "bond" => parameter == "mode" || BOND_OPTIONS.iter().any(|(p, _)| *p == parameter),
+        "linux-bridge" => parameter == "stp",
+        _ => false,
+    };
+    keys.extend(
+        device
+            .parameters
+            .keys()
+            .filter(|parameter| !is_supported_parameter(parameter))
+            .map(|parameter| format!("{path}.parameters.{parameter}")),
+    );
+
+    keys
+}
+
+/// Ethernets are named after `set-name`, the exact (non-glob) `match.name` or their ID.
+fn ethernet_name(id: &str, device: &Device) -> String {
+    if let Some(name) = &device.set_name {
+        return name.to_owned();
+    }
+
+    device
+        .match_rules
+        .as_ref()
+        .and_then(|m| m.name.as_ref())
+        .filter(|name| !name.contains(['*', '?', '[']))
+        .cloned()
+        .unwrap_or_else(|| id.to_owned())
+}
+
+fn ip_config(name: &str, device: &Device) -> Result<(Value, Value), anyhow::Error> {
+    let mut ipv4_addresses = Vec::new();
+    let mut ipv6_addresses = Vec::new();
+
+    for address in &device.addresses {
+        let (ip, prefix_length) = address
+            .split_once('/')
+            .and_then(|(ip, prefix)| Some((ip.parse::<IpAddr>().ok()?, prefix.parse::<u8>().ok()?)))
+            .ok_or_else(|| anyhow!("Invalid address '{address}' of interface {name}"))?;
+
+        let entry = json!({ "ip": ip.to_string(), "prefix-length": prefix_length });
+        match ip {
+            IpAddr::V4(_) => ipv4_addresses.push(entry),
+            IpAddr::V6(_) => ipv6_addresses.push(entry),
+        }
+    }
+
+    let ipv4 = if ipv4_addresses.is_empty() && !device.dhcp4 {
+        json!({ "enabled": false })
+    } else {
+        json!({ "enabled": true, "dhcp": device.dhcp4, "address": ipv4_addresses })
+    };
+
+    let ipv6 = if ipv6_addresses.is_empty() && !device.dhcp6 {
+        json!({ "enabled": false })
+    } else {
+        json!({
+            "enabled": true,
+            "dhcp": device.dhcp6,
+            "autoconf": device.dhcp6,
+            "address": ipv6_addresses,
+        })
+    };
+
+    Ok((ipv4, ipv6))
+}
+
+/// Bond parameters supported by the conversion and their nmstate option names.
+const BOND_OPTIONS: [(&str, &str); 4] = [
+    ("mii-monitor-interval", "miimon"),
+    ("primary", "primary"),
+    ("lacp-rate", "lacp_rate"),
+    ("transmit-hash-policy", "xmit_hash_policy"),
+];
+
+fn bond_config(device: &Device, ports: Vec<String>) -> Value {
+    let mode = device
+        .parameters
+        .get("mode")
+        .and_then(serde_yaml::Value::as_str)
+        .unwrap_or("balance-rr");
+
+    let options: Map<String, Value> = BOND_OPTIONS
+        .iter()
+        .filter_map(|(parameter, option)| {
+            let value = device.parameters.get(*parameter)?;
+            Some(((*option).to_owned(), serde_json::to_value(value).ok()?))
+        })
+        .collect();
+
+    let mut config = json!({ "mode": mode, "port": ports });
+    if !options.is_empty() {
+        config["options"] = Value::Object(options);
+    }
+
+    config
+}
+
+fn bridge_config(device: &Device, ports: Vec<Value>) -> Value {
+    let mut config = json!({ "port": ports });
+
+    if let Some(stp) = device
+        .parameters
+        .get("stp")
+        .and_then(serde_yaml::Value::as_bool)
+    {
+        config["options"] = json!({ "stp": { "enabled": stp } });
+    }
+
+    config
+}
+
+fn route_config(name: &str, route: &Route) -> Result<Value, anyhow::Error> {
+    let via: IpAddr = route
+        .via
+        .parse()
+        .map_err(|_| anyhow!("Invalid route gateway '{}' of interface {name}", route.via))?;
+
+    let destination = match (route.to.as_str(), via) {
+        ("default", IpAddr::V4(_)) => "0.0.0.0/0",
+        ("default", IpAddr::V6(_)) => "::/0",
+        (to, _) => to,
+    };
+
+    let mut config = json!({
+        "destination": destination,
+        "next-hop-interface": name,
+        "next-hop-address": route.via,
+    });
+    if let Some(metric) = route.metric {
+        config["metric"] = json!(metric);
+    }
+
+    Ok(config)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs;
+
+    use serde_json::json;
+
+    use crate::convert::{to_nmstate, InputFormat};
+
+    #[test]
+    fn convert_cloud_init_config() -> Result<(), anyhow::Error> {
+        let data = fs::read_to_string("testdata/cloud-init/node1.yaml")?;
+
+        let (state, warnings) = to_nmstate(&data, InputFormat::CloudInit)?;
+        let state: serde_json::Value = serde_yaml::from_str(&state)?;
+        assert!(warnings.is_empty());
+
+        assert_eq!(
+            state,
+            json!({
+                "interfaces": [
+                    {
+                        "name": "eth2",
+                        "type": "ethernet",
+                        "state": "up",
+                        "mac-address": "00:11:22:33:44:57",
+                        "ipv4": { "enabled": true, "dhcp": true, "address": [] },
+                        "ipv6": { "enabled": false },
+                    },
+                    {
+                        "name": "eth0",
+                        "type": "ethernet",
+                        "state": "up",
+                        "mac-address": "00:11:22:33:44:55",
+                        "ipv4": { "enabled": false },
+                        "ipv6": { "enabled": false },
+                    },
+                    {
+                        "name": "eth1",
+                        "type": "ethernet",
+                        "state": "up",
+                        "mac-address": "00:11:22:33:44:56",
+                        "ipv4": { "enabled": false },
+                        "ipv6": { "enabled": false },
+                    },
+                    {
+                        "name": "bond0",
+                        "type": "bond",
+                        "state": "up",
+                        "ipv4": {
+                            "enabled": true,
+                            "dhcp": false,
+                            "address": [{ "ip": "192.168.1.10", "prefix-length": 24 }],
+                        },
+                        "ipv6": {
+                            "enabled": true,
+                            "dhcp": false,
+                            "autoconf": false,
+                            "address": [{ "ip": "2001:db8::10", "prefix-length": 64 }],
+                        },
+                        "link-aggregation": {
+                            "mode": "active-backup",
+                            "port": ["eth0", "eth1"],
+                            "options": { "miimon": 100 },
+                        },
+                    },
+                    {
+                        "name": "bond0.100",
+                        "type": "vlan",
+                        "state": "up",
+                        "ipv4": {
+                            "enabled": true,
+                            "dhcp": false,
+                            "address": [{ "ip": "10.0.100.10", "prefix-length": 24 }],
+                        },
+                        "ipv6": { "enabled": false },
+                        "vlan": { "base-iface": "bond0", "id": 100 },
+                    },
+                ],
+                "routes": {
+                    "config": [
+                        {
+                            "destination": "0.0.0.0/0",
+                            "next-hop-interface": "bond0",
+                            "next-hop-address": "192.168.1.1",
+                        },
+                        {
+                            "destination": "10.10.0.0/16",
+                            "next-hop-interface": "bond0.100",
+                            "next-hop-address": "10.0.100.1",
+                            "metric": 100,
+                        },
+                    ],
+                },
+                "dns-resolver": {
+                    "config": { "server": ["192.168.1.1"], "search": ["example.com"] },
+                },
+            })
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn convert_cloud_init_config_fails() {
+        let error = to_nmstate("version: 1\nconfig: []\n", InputFormat::CloudInit).unwrap_err();
+        assert_eq!(
+            format!("{error:#}"),
+            "Parsing cloud-init config: Unsupported network config version 1"
+        );
+
+        let data = "version: 2\nethernets:\n  eth0:\n    addresses: [192.168.1.10]\n";
+        let error = to_nmstate(data, InputFormat::CloudInit).unwrap_err();
+        assert_eq!(
+            error.to_string(),
+            "Invalid address '192.168.1.10' of interface eth0"
+        );
+    }
+
+    #[test]
+    fn keep_nmstate_config() -> Result<(), anyhow::Error> {
+        let data = fs::read_to_string("testdata/generate/node1.yaml")?;
+        assert_eq!(to_nmstate(&data, InputFormat::Nmstate)?, (data, vec![]));
+
+        Ok(())
+    }
+
+    #[test]
+    fn convert_netplan_config() -> Result<(), anyhow::Error> {
+        let data = r#"
+network:
+  version: 2
+  renderer: networkd
+  ethernets:
+    eth0:
+      match:
+        macaddress: 00:11:22:33:44:55
+        driver: ixgbe
+      wakeonlan: true
+      dhcp4: true
+      routes:
+        - to: default
+          via: 192.168.1.1
+          on-link: true
+  bridges:
+    br0:
+      interfaces: [eth0]
+      parameters:
+        stp: false
+        forward-delay: 4
+"#;
+
+        let (state, warnings) = to_nmstate(data, InputFormat::Netplan)?;
+        let state: serde_json::Value = serde_yaml::from_str(&state)?;
+
+        assert_eq!(
+            state["interfaces"][1]["bridge"],
+            json!({ "port": [{ "name": "eth0" }], "options": { "stp": { "enabled": false } } })
+        );
+        assert_eq!(
+            state["routes"]["config"][0]["destination"],
+            json!("0.0.0.0/0")
+        );
+        assert_eq!(
+            warnings,
+            vec![
+                "Dropped unsupported netplan keys: ethernets.eth0.wakeonlan, \
+                ethernets.eth0.match.driver, ethernets.eth0.routes[0].on-link, \
+                bridges.br0.parameters.forward-delay"
+            ]
+        );
+
+        let error = to_nmstate("version: 2\n", InputFormat::Netplan).unwrap_err();
+        assert_eq!(
+            format!("{error:#}"),
+            "Parsing netplan config: Missing 'network' element"
+        );
+
+        Ok(())
+    }
+}
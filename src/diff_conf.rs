@@ -0,0 +1,211 @@
+use std::collections::HashMap;
+use std::fs;
+use std::io;
+use std::path::Path;
+
+use anyhow::{anyhow, Context};
+use log::{debug, info};
+use network_interface::{NetworkInterface, NetworkInterfaceConfig};
+use serde::Serialize;
+use similar::TextDiff;
+
+use crate::apply_conf::{
+    detect_local_interfaces, find_missing_files, identify_host, keyfile_path, local_altnames,
+    parse_config, read_connection_files,
+};
+use crate::error::ConfigError;
+use crate::{OutputFormat, HOST_MAPPING_FILE};
+
+#[derive(Serialize, Debug)]
+#[serde(rename_all = "lowercase")]
+#[cfg_attr(test, derive(PartialEq))]
+enum FileStatus {
+    Added,
+    Changed,
+    Removed,
+}
+
+#[derive(Serialize, Debug)]
+struct FileDiff {
+    file: String,
+    status: FileStatus,
+    diff: String,
+}
+
+/// Compare the *.nmconnection files which would be applied for the identified host
+/// with the ones currently stored in the `destination_dir` and print the differences.
+pub(crate) fn diff(
+    source_dir: &str,
+    destination_dir: &str,
+    format: OutputFormat,
+) -> Result<(), anyhow::Error> {
+    let hosts = parse_config(source_dir, HOST_MAPPING_FILE, false).context("Parsing config")?;
+    debug!("Loaded hosts config: {hosts:?}");
+
+    let network_interfaces = NetworkInterface::show()?;
+    debug!("Retrieved network interfaces: {network_interfaces:?}");
+
+    let host = identify_host(hosts, &network_interfaces).ok_or(ConfigError::NoMatchingHost)?;
+    info!(host = host.hostname.as_str(); "Identified host: {}", host.hostname);
+
+    let altnames = local_altnames().unwrap_or_else(|err| {
+        debug!("Retrieving alternative interface names failed: {err:#}");
+        HashMap::new()
+    });
+
+    let local_interfaces =
+        detect_local_interfaces(&host, network_interfaces, &HashMap::new(), &altnames);
+    let files = read_connection_files(&host, &local_interfaces, source_dir, false)
+        .context("Reading connection files")?;
+
+    let diffs = diff_connection_files(files, destination_dir).context("Comparing files")?;
+
+    match format {
+        OutputFormat::Text => diffs.iter().for_each(|d| print!("{}", d.diff)),
+        OutputFormat::Json => println!("{}", serde_json::to_string_pretty(&diffs)?),
+    }
+
+    Ok(())
+}
+
+/// Produce a unified diff for every new, changed or removed file in the `destination_dir`.
+/// Unchanged files are omitted. Only the files which `nmc apply --delete-missing` would delete
+/// are reported as removed, leaving out the connections not stored by NMC (e.g. VPN ones).
+fn diff_connection_files(
+    files: Vec<(String, String)>,
+    destination_dir: &str,
+) -> Result<Vec<FileDiff>, anyhow::Error> {
+    let mut diffs = Vec::new();
+    let mut expected_paths = Vec::new();
+
+    for (filename, contents) in files {
+        let path = keyfile_path(destination_dir, &filename)
+            .ok_or_else(|| anyhow!("Determining destination keyfile path"))?;
+        let file = path.to_string_lossy().to_string();
+
+        let (status, existing) = match fs::read_to_string(&path) {
+            Ok(existing) if existing == contents => {
+                expected_paths.push(path);
+                continue;
+            }
+            Ok(existing) => (FileStatus::Changed, existing),
+            Err(err) if err.kind() == io::ErrorKind::NotFound => (FileStatus::Added, String::new()),
+            Err(err) => return Err(err).context(format!("Reading {file}")),
+        };
+
+        let old_header = match status {
+            FileStatus::Added => "/dev/null",
+            _ => &file,
+        };
+
+        diffs.push(FileDiff {
+            diff: unified_diff(&existing, &contents, old_header, &file),
+            file,
+            status,
+        });
+        expected_paths.push(path);
+    }
+
+    if !Path::new(destination_dir).exists() {
+        return Ok(diffs);
+    }
+
+    for path in find_missing_files(Path::new(destination_dir), &expected_paths)? {
+        let file = path.to_string_lossy().to_string();
+        let existing = fs::read_to_string(&path).context(format!("Reading {file}"))?;
+
+        diffs.push(FileDiff {
+            diff: unified_diff(&existing, "", &file, "/dev/null"),
+            file,
+            status: FileStatus::Removed,
+        });
+    }
+
+    Ok(diffs)
+}
+
+fn unified_diff(old: &str, new: &str, old_header: &str, new_header: &str) -> String {
+    TextDiff::from_lines(old, new)
+        .unified_diff()
+        .header(old_header, new_header)
+        .to_string()
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs;
+
+    use crate::diff_conf::{diff_connection_files, FileStatus};
+
+    #[test]
+    fn diff_connection_files_successfully() -> Result<(), anyhow::Error> {
+        let destination_dir = "_out_diff";
+
+        fs::create_dir_all(destination_dir)?;
+        fs::write("_out_diff/eth0.nmconnection", "[connection]\nid=eth0\n")?;
+        fs::write("_out_diff/eth1.nmconnection", "[connection]\nid=eth1\n")?;
+        fs::write("_out_diff/eth2.nmconnection", "[connection]\nid=eth2\n")?;
+        fs::write("_out_diff/unrelated.conf", "")?;
+        // Connections not stored by NMC (neither listed in the manifest nor annotated) are left out.
+        fs::write("_out_diff/vpn.nmconnection", "[connection]\nid=vpn\n")?;
+        fs::write(
+            "_out_diff/eth4.nmconnection",
+            "# Generated by nmc 0.2.3 (nmstate 2.2.0)\n[connection]\nid=eth4\n",
+        )?;
+        fs::write(
+            "_out_diff/.nmc-manifest",
+            "eth0.nmconnection\neth2.nmconnection\n",
+        )?;
+
+        let files = vec![
+            ("eth0".to_string(), "[connection]\nid=eth0\n".to_string()),
+            (
+                "eth1".to_string(),
+                "[connection]\nid=eth1\nautoconnect=true\n".to_string(),
+            ),
+            ("eth3".to_string(), "[connection]\nid=eth3\n".to_string()),
+        ];
+
+        let diffs = diff_connection_files(files, destination_dir)?;
+
+        let statuses: Vec<(&str, &FileStatus)> =
+            diffs.iter().map(|d| (d.file.as_str(), &d.status)).collect();
+        assert_eq!(
+            statuses,
+            vec![
+                ("_out_diff/eth1.nmconnection", &FileStatus::Changed),
+                ("_out_diff/eth3.nmconnection", &FileStatus::Added),
+                ("_out_diff/eth2.nmconnection", &FileStatus::Removed),
+                ("_out_diff/eth4.nmconnection", &FileStatus::Removed),
+            ]
+        );
+
+        assert_eq!(
+            diffs[0].diff,
+            "--- _out_diff/eth1.nmconnection\n\
+             +++ _out_diff/eth1.nmconnection\n\
+             @@ -1,2 +1,3 @@\n \
+             [connection]\n \
+             id=eth1\n\
+             +autoconnect=true\n"
+        );
+        assert!(diffs[1].diff.starts_with("--- /dev/null\n"));
+        assert!(diffs[2].diff.ends_with("-id=eth2\n"));
+
+        // cleanup
+        fs::remove_dir_all(destination_dir)?;
+
+        Ok(())
+    }
+
+    #[test]
+    fn diff_connection_files_missing_destination_dir() -> Result<(), anyhow::Error> {
+        let files = vec![("eth0".to_string(), "[connection]\n".to_string())];
+
+        let diffs = diff_connection_files(files, "<missing>")?;
+        assert_eq!(diffs.len(), 1);
+        assert_eq!(diffs[0].status, FileStatus::Added);
+
+        Ok(())
+    }
+}
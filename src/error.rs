@@ -0,0 +1,31 @@
+use std::fmt;
+
+/// Errors which scripts wrapping NMC need to tell apart from generic (e.g. IO) failures.
+#[derive(Debug)]
+pub(crate) enum ConfigError {
+    /// The host mapping file is malformed or inconsistent.
+    InvalidMapping(String),
+    /// The desired state of the host could not be turned into network configurations.
+    InvalidHostConfig(String),
+    /// None of the preconfigured hosts match the network interfaces.
+    NoMatchingHost,
+    /// The matched host is missing some of the interfaces required to apply its config.
+    MissingInterfaces(String),
+}
+
+impl fmt::Display for ConfigError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ConfigError::InvalidMapping(message) => write!(f, "{message}"),
+            ConfigError::InvalidHostConfig(hostname) => {
+                write!(f, "Invalid config for host {hostname}")
+            }
+            ConfigError::NoMatchingHost => {
+                write!(f, "None of the preconfigured hosts match local NICs")
+            }
+            ConfigError::MissingInterfaces(message) => write!(f, "{message}"),
+        }
+    }
+}
+
+impl std::error::Error for ConfigError {}
@@ -0,0 +1,97 @@
+use std::io;
+
+use anyhow::Context;
+use serde::Serialize;
+
+use crate::apply_conf::parse_config;
+use crate::types::Host;
+
+/// Row of the interface table. The MAC address is empty for virtual interfaces (e.g. VLANs).
+#[derive(Serialize, Debug)]
+struct InterfaceRow<'a> {
+    hostname: &'a str,
+    logical_name: &'a str,
+    mac_address: Option<&'a str>,
+    interface_type: &'a str,
+}
+
+/// Print the interfaces of all hosts of the mapping in the config dir as CSV
+/// e.g. in order to audit the MAC addresses in a spreadsheet.
+pub(crate) fn export(config_dir: &str, mapping_file: &str) -> Result<(), anyhow::Error> {
+    let hosts = parse_config(config_dir, mapping_file, false).context("Parsing config")?;
+
+    write_csv(&hosts, io::stdout().lock())
+}
+
+fn write_csv<W: io::Write>(hosts: &[Host], writer: W) -> Result<(), anyhow::Error> {
+    let mut writer = csv::Writer::from_writer(writer);
+
+    for host in hosts {
+        for interface in &host.interfaces {
+            writer.serialize(InterfaceRow {
+                hostname: &host.hostname,
+                logical_name: &interface.logical_name,
+                mac_address: interface.mac_address.as_deref(),
+                interface_type: &interface.interface_type,
+            })?;
+        }
+    }
+
+    writer.flush().context("Writing CSV")
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::export::write_csv;
+    use crate::types::{Host, Interface};
+
+    #[test]
+    fn export_interfaces_as_csv() -> Result<(), anyhow::Error> {
+        let hosts = vec![
+            Host {
+                hostname: "node1".to_string(),
+                serial: None,
+                interfaces: vec![
+                    Interface {
+                        logical_name: "eth0".to_string(),
+                        mac_address: Some("00:11:22:33:44:55".to_string()),
+                        additional_macs: Vec::new(),
+                        predictable_name: None,
+                        interface_type: "ethernet".to_string(),
+                    },
+                    Interface {
+                        logical_name: "eth0.100".to_string(),
+                        mac_address: None,
+                        additional_macs: Vec::new(),
+                        predictable_name: None,
+                        interface_type: "vlan".to_string(),
+                    },
+                ],
+            },
+            Host {
+                hostname: "node,2".to_string(),
+                serial: None,
+                interfaces: vec![Interface {
+                    logical_name: "eth0".to_string(),
+                    mac_address: Some("00:11:22:33:44:66".to_string()),
+                    additional_macs: Vec::new(),
+                    predictable_name: None,
+                    interface_type: "ethernet".to_string(),
+                }],
+            },
+        ];
+
+        let mut output = Vec::new();
+        write_csv(&hosts, &mut output)?;
+
+        assert_eq!(
+            String::from_utf8(output)?,
+            "hostname,logical_name,mac_address,interface_type\n\
+            node1,eth0,00:11:22:33:44:55,ethernet\n\
+            node1,eth0.100,,vlan\n\
+            \"node,2\",eth0,00:11:22:33:44:66,ethernet\n"
+        );
+
+        Ok(())
+    }
+}
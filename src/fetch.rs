@@ -0,0 +1,163 @@
+use std::io::Read;
+use std::path::{Path, PathBuf};
+
+use anyhow::{anyhow, Context};
+use base64::Engine;
+use flate2::read::GzDecoder;
+use log::{debug, info};
+use sha2::{Digest, Sha256};
+use tempfile::TempDir;
+
+use crate::PER_HOST_MAPPING_FILE;
+
+/// Config bundle (a gzip-compressed tarball of the config dir) served over HTTP(S).
+#[derive(Clone, Debug)]
+pub(crate) struct SourceBundle {
+    pub(crate) url: String,
+    /// Credentials for basic authentication in the format `<USER>:<PASSWORD>`.
+    pub(crate) auth: Option<String>,
+    /// Expected SHA-256 checksum of the bundle as a hex string.
+    pub(crate) sha256: Option<String>,
+}
+
+/// Config dir extracted from a downloaded bundle which is removed once dropped.
+pub(crate) struct FetchedConfig {
+    _dir: TempDir,
+    path: PathBuf,
+}
+
+impl FetchedConfig {
+    pub(crate) fn path(&self) -> &Path {
+        &self.path
+    }
+}
+
+impl SourceBundle {
+    /// Download the bundle, verify its checksum (if provided) and extract it to a temporary dir.
+    pub(crate) fn fetch(&self, mapping_file: &str) -> Result<FetchedConfig, anyhow::Error> {
+        info!("Downloading config bundle from {}...", self.url);
+
+        let mut request = ureq::get(&self.url);
+        if let Some(auth) = &self.auth {
+            let credentials = base64::engine::general_purpose::STANDARD.encode(auth);
+            request = request.set("Authorization", &format!("Basic {credentials}"));
+        }
+
+        let mut bundle = Vec::new();
+        request
+            .call()
+            .context("Downloading config bundle")?
+            .into_reader()
+            .read_to_end(&mut bundle)
+            .context("Reading config bundle")?;
+        debug!("Downloaded {} bytes", bundle.len());
+
+        if let Some(expected) = &self.sha256 {
+            verify_checksum(&bundle, expected)?;
+        }
+
+        let dir = tempfile::Builder::new()
+            .prefix("nmc-")
+            .tempdir()
+            .context("Creating temporary dir")?;
+        tar::Archive::new(GzDecoder::new(bundle.as_slice()))
+            .unpack(dir.path())
+            .context("Extracting config bundle")?;
+
+        let path = locate_config_dir(dir.path(), mapping_file)?;
+        debug!("Extracted config dir: {path:?}");
+
+        Ok(FetchedConfig { _dir: dir, path })
+    }
+}
+
+fn verify_checksum(data: &[u8], expected: &str) -> Result<(), anyhow::Error> {
+    let actual: String = Sha256::digest(data)
+        .iter()
+        .map(|byte| format!("{byte:02x}"))
+        .collect();
+
+    if !actual.eq_ignore_ascii_case(expected.trim()) {
+        return Err(anyhow!(
+            "Checksum mismatch of config bundle: expected {expected}, got {actual}"
+        ));
+    }
+
+    Ok(())
+}
+
+/// The config dir is either the root of the bundle or its only top-level dir
+/// e.g. when archiving the `network-config` dir itself.
+fn locate_config_dir(root: &Path, mapping_file: &str) -> Result<PathBuf, anyhow::Error> {
+    if contains_mapping(root, mapping_file) {
+        return Ok(root.to_path_buf());
+    }
+
+    let entries: Vec<PathBuf> = root
+        .read_dir()?
+        .map(|entry| entry.map(|e| e.path()))
+        .collect::<Result<_, _>>()?;
+
+    match entries.as_slice() {
+        [dir] if contains_mapping(dir, mapping_file) => Ok(dir.clone()),
+        _ => Err(anyhow!("Config bundle does not contain {mapping_file}")),
+    }
+}
+
+/// Whether the dir contains the aggregate host mapping or any per-host ones.
+fn contains_mapping(dir: &Path, mapping_file: &str) -> bool {
+    dir.join(mapping_file).exists()
+        || dir.read_dir().is_ok_and(|mut entries| {
+            entries.any(|entry| entry.is_ok_and(|e| e.path().join(PER_HOST_MAPPING_FILE).is_file()))
+        })
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs;
+    use std::path::Path;
+
+    use crate::fetch::{locate_config_dir, verify_checksum};
+    use crate::{HOST_MAPPING_FILE, PER_HOST_MAPPING_FILE};
+
+    #[test]
+    fn verify_bundle_checksum() {
+        let checksum = "2cf24dba5fb0a30e26e83b2ac5b9e29e1b161e5c1fa7425e73043362938b9824";
+
+        assert!(verify_checksum(b"hello", checksum).is_ok());
+        assert!(verify_checksum(b"hello", &checksum.to_uppercase()).is_ok());
+
+        let error = verify_checksum(b"hello!", checksum).unwrap_err();
+        assert!(error.to_string().starts_with("Checksum mismatch"));
+    }
+
+    #[test]
+    fn locate_bundle_config_dir() -> Result<(), anyhow::Error> {
+        let root = Path::new("_in_bundle");
+        fs::create_dir_all(root.join("network-config"))?;
+
+        assert!(locate_config_dir(root, HOST_MAPPING_FILE).is_err());
+
+        fs::write(root.join("network-config").join(HOST_MAPPING_FILE), "")?;
+        assert_eq!(
+            locate_config_dir(root, HOST_MAPPING_FILE)?,
+            root.join("network-config")
+        );
+
+        fs::write(root.join(HOST_MAPPING_FILE), "")?;
+        assert_eq!(locate_config_dir(root, HOST_MAPPING_FILE)?, root);
+
+        // cleanup
+        fs::remove_dir_all(root)?;
+
+        // Bundle of a single host with its own mapping.
+        fs::create_dir_all(root.join("node1"))?;
+        fs::write(root.join("node1").join(PER_HOST_MAPPING_FILE), "")?;
+        assert_eq!(locate_config_dir(root, HOST_MAPPING_FILE)?, root);
+
+        // cleanup
+        fs::remove_dir_all(root)?;
+
+        Ok(())
+    }
+}
@@ -1,55 +1,701 @@
+use std::collections::{BTreeMap, BTreeSet, HashMap, HashSet};
 use std::ffi::OsStr;
-use std::fs;
-use std::path::Path;
+use std::io::{Read, Write};
+use std::net::IpAddr;
+use std::os::unix::fs::chown;
+use std::path::{Path, PathBuf};
+use std::process::{Command, Stdio};
+use std::time::SystemTime;
+use std::{fs, io};
 
 use anyhow::{anyhow, Context};
-use log::{info, warn};
-use nmstate::{InterfaceType, NetworkState};
+use flate2::read::GzDecoder;
+use ignore::gitignore::{Gitignore, GitignoreBuilder};
+use indicatif::{MultiProgress, ProgressBar, ProgressFinish, ProgressStyle};
+use log::{debug, error, info, warn};
+use nmstate::{BondMode, InterfaceType, NetworkState, RouteState};
+use serde::{Deserialize, Serialize};
+use uuid::Uuid;
 
-use crate::types::{Host, Interface};
-use crate::HOST_MAPPING_FILE;
+use crate::apply_conf::{
+    check_mac_address_bits, check_writable, is_physical_interface, CONNECTION_FILE_EXT,
+    PROVENANCE_HEADER_PREFIX,
+};
+use crate::convert::{to_nmstate, InputFormat};
+use crate::error::ConfigError;
+use crate::keyfile::Keyfile;
+use crate::networkd::gen_networkd_conf;
+use crate::types::{Host, Interface, Metadata};
+use crate::{HOST_MAPPING_FILE, METADATA_FILE, NMSTATE_VERSION, PER_HOST_MAPPING_FILE};
 
 /// `NetworkConfig` contains the generated configurations in the
 /// following format: `Vec<(config_file_name, config_content>)`
 type NetworkConfig = Vec<(String, String)>;
 
-/// Generate network configurations from all YAML files in the `config_dir`
+/// Files in the config dir containing the desired state shared by all hosts.
+const BASE_FILES: [&str; 2] = ["base.yml", "base.yaml"];
+
+/// VXLAN network identifiers are 24-bit values.
+const MAX_VXLAN_ID: u32 = (1 << 24) - 1;
+
+/// Interface types of the GRE tunnels which are not supported by nmstate.
+const GRE_TYPES: [&str; 4] = ["gre", "gretap", "ip6gre", "ip6gretap"];
+
+/// Values of `connection.type` of the Ethernet connections.
+const ETHERNET_CONNECTION_TYPES: [&str; 2] = ["802-3-ethernet", "ethernet"];
+
+const LOOPBACK_CONNECTION_TYPE: &str = "loopback";
+
+/// Extensions of the desired state files which are not part of the hostnames.
+/// JSON files are parsed as YAML which is a superset of it.
+const CONFIG_FILE_EXTENSIONS: [&str; 3] = ["yml", "yaml", "json"];
+
+const GZIP_EXTENSION: &str = "gz";
+
+/// Maximum size of the (decompressed) desired state files guarding against
+/// accidentally processing huge files which are not configs e.g. logs or disk images.
+pub(crate) const DEFAULT_MAX_FILE_SIZE: u64 = 10 * 1024 * 1024;
+
+/// File in the config dir listing gitignore-style patterns of the entries which are not processed.
+const IGNORE_FILE: &str = ".nmc-ignore";
+
+/// File in the config dir tuning the rules of `nmc lint`.
+pub(crate) const LINT_CONFIG_FILE: &str = ".nmc-lint.yml";
+
+/// Top-level key of the desired states holding nmc specific annotations of the host.
+const ANNOTATIONS_KEY: &str = "nmc";
+
+/// Network configuration service the configurations are generated for.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub(crate) enum OutputBackend {
+    /// `*.nmconnection` keyfiles.
+    #[default]
+    NetworkManager,
+    /// systemd-networkd `*.network` and `*.netdev` files.
+    Networkd,
+}
+
+impl OutputBackend {
+    pub(crate) fn from_arg(value: &str) -> Self {
+        match value {
+            "networkd" => OutputBackend::Networkd,
+            _ => OutputBackend::NetworkManager,
+        }
+    }
+}
+
+/// Host mapping files stored in the output dir.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub(crate) enum MappingScope {
+    /// Single mapping of all hosts in the output dir.
+    #[default]
+    Aggregate,
+    /// Mapping of each host in its own dir so that the dir is a self-contained bundle.
+    PerHost,
+    /// Both the aggregate and the per-host mappings.
+    Both,
+}
+
+impl MappingScope {
+    pub(crate) fn from_arg(value: &str) -> Self {
+        match value {
+            "per-host" => MappingScope::PerHost,
+            "both" => MappingScope::Both,
+            _ => MappingScope::Aggregate,
+        }
+    }
+
+    fn aggregate(self) -> bool {
+        self != MappingScope::PerHost
+    }
+
+    fn per_host(self) -> bool {
+        self != MappingScope::Aggregate
+    }
+}
+
+/// Property binding the generated Ethernet connections to the NICs.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub(crate) enum InterfaceBinding {
+    /// `connection.interface-name`.
+    #[default]
+    Name,
+    /// `ethernet.mac-address` which is not affected by renaming the NICs.
+    Mac,
+}
+
+impl InterfaceBinding {
+    pub(crate) fn from_arg(value: &str) -> Self {
+        match value {
+            "mac" => InterfaceBinding::Mac,
+            _ => InterfaceBinding::Name,
+        }
+    }
+}
+
+/// Naming scheme of the NICs on the target hosts recorded in the host mapping.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub(crate) enum NamingScheme {
+    /// Keep the authored names (e.g. `eth0`) relying on the local NIC names detected on apply.
+    #[default]
+    Kernel,
+    /// systemd's `NamePolicy=mac` deriving the names from the MAC addresses e.g. `enx001122334455`.
+    Mac,
+}
+
+impl NamingScheme {
+    pub(crate) fn from_arg(value: &str) -> Self {
+        match value {
+            "mac" => NamingScheme::Mac,
+            _ => NamingScheme::Kernel,
+        }
+    }
+}
+
+/// Options adjusting the behaviour of [`generate`].
+#[derive(Default)]
+pub(crate) struct GenerateOptions {
+    /// Only process the config file of the given host and replace (or insert) its entry
+    /// in the existing host mapping instead of regenerating the whole mapping.
+    pub(crate) only_host: Option<String>,
+    /// Descend into the subdirectories of the config dir.
+    pub(crate) recursive: bool,
+    /// Prefix the hostnames with the names of the subdirectories containing
+    /// their config files e.g. `rack1/node5.yaml` -> `rack1-node5`.
+    pub(crate) prefix_hostnames: bool,
+    /// Print the generated configurations to stdout instead of storing them.
+    pub(crate) dump_conf: bool,
+    /// Print the paths of the files which would be written to stdout instead of storing them.
+    pub(crate) dry_run: bool,
+    /// Values for `connection.autoconnect-priority` keyed by interface name.
+    pub(crate) autoconnect_priorities: HashMap<String, i32>,
+    /// Sort the sections and keys of the generated keyfiles alphabetically.
+    pub(crate) normalize_keyfiles: bool,
+    /// Prefix of `connection.id` in the generated keyfiles e.g. `nmc-`.
+    pub(crate) connection_id_prefix: Option<String>,
+    /// Only generate configurations for interfaces of the given types e.g. `ethernet`.
+    /// All types are included if empty.
+    pub(crate) include_types: Vec<String>,
+    /// Skip the interfaces of the given types e.g. `ovs-bridge`.
+    pub(crate) exclude_types: Vec<String>,
+    /// Format of the desired state files.
+    pub(crate) input_format: InputFormat,
+    /// Derive the connection UUIDs from the hostnames and connection IDs
+    /// instead of keeping the ones generated by nmstate.
+    pub(crate) deterministic_uuids: bool,
+    /// Prepend a comment recording the source file, nmc version and timestamp to the keyfiles.
+    pub(crate) annotate: bool,
+    /// Network configuration service to generate the configurations for.
+    pub(crate) output_backend: OutputBackend,
+    /// Fail instead of logging warnings e.g. about unexpected dirs or dropped keys.
+    pub(crate) strict: bool,
+    /// Change the ownership of the generated files and dirs to the given `(uid, gid)`.
+    pub(crate) output_owner: Option<(u32, u32)>,
+    /// Draw a progress bar of the processed hosts. Log records are printed above it.
+    pub(crate) progress: Option<MultiProgress>,
+    /// Check that NetworkManager accepts the generated keyfiles by parsing them with `nmcli`.
+    pub(crate) verify_nmcli: bool,
+    /// Bind the Ethernet connections to the NICs by name or by MAC address.
+    pub(crate) bind: InterfaceBinding,
+    /// Record the names the Ethernet interfaces are expected to get on the target hosts.
+    pub(crate) naming_scheme: NamingScheme,
+    /// Drop the loopback connection files which are not part of the host mapping either.
+    pub(crate) skip_loopback_files: bool,
+    /// Reject multicast MAC addresses and warn about locally-administered ones.
+    pub(crate) check_mac_addresses: bool,
+    /// Name of the host mapping file in the output dir. Defaults to [`HOST_MAPPING_FILE`].
+    pub(crate) mapping_file: Option<String>,
+    /// Maximum size of the desired state files in bytes. Defaults to [`DEFAULT_MAX_FILE_SIZE`].
+    pub(crate) max_file_size: Option<u64>,
+    /// Record the failures per desired state file and proceed with the remaining ones
+    /// instead of aborting the whole run.
+    pub(crate) continue_on_error: bool,
+    /// Store the aggregate host mapping, the per-host ones or both.
+    pub(crate) mapping_scope: MappingScope,
+}
+
+impl GenerateOptions {
+    pub(crate) fn max_file_size(&self) -> u64 {
+        self.max_file_size.unwrap_or(DEFAULT_MAX_FILE_SIZE)
+    }
+
+    fn includes_type(&self, interface_type: &InterfaceType) -> bool {
+        let interface_type = interface_type.to_string();
+
+        (self.include_types.is_empty() || self.include_types.contains(&interface_type))
+            && !self.exclude_types.contains(&interface_type)
+    }
+}
+
+/// Summary of a [`generate`] run.
+#[derive(Serialize, Debug, Default)]
+pub(crate) struct GenerateSummary {
+    /// Hostnames for which configurations were generated.
+    pub(crate) hosts: Vec<String>,
+    /// Total number of interfaces across all generated hosts.
+    pub(crate) interfaces: usize,
+    /// Number of ignored entries in the config dir.
+    pub(crate) skipped: usize,
+    /// Desired state files which failed to generate when continuing on errors.
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub(crate) failed: Vec<FailedConfig>,
+}
+
+#[derive(Serialize, Debug)]
+#[cfg_attr(test, derive(PartialEq))]
+pub(crate) struct FailedConfig {
+    pub(crate) path: PathBuf,
+    pub(crate) error: String,
+}
+
+/// Generate network configurations from all YAML files in the `config_dirs`
 /// and store the result *.nmconnection files and host mapping under `output_dir`.
-pub(crate) fn generate(config_dir: &str, output_dir: &str) -> Result<(), anyhow::Error> {
-    if fs::read_dir(config_dir)?.count() == 0 {
-        return Err(anyhow!("Empty config directory"));
+///
+/// Config files in later dirs override the ones for the same hostname in earlier dirs.
+pub(crate) fn generate(
+    config_dirs: &[&str],
+    output_dir: &str,
+    options: &GenerateOptions,
+) -> Result<GenerateSummary, anyhow::Error> {
+    for config_dir in config_dirs {
+        if fs::read_dir(config_dir)?.count() == 0 {
+            return Err(anyhow!("Empty config directory: {config_dir}"));
+        };
+    }
+
+    // Fail before spending any time on generating the configurations which can't be stored.
+    if !options.dump_conf && !options.dry_run {
+        check_writable(output_dir, "--output-dir")?;
+    }
+
+    let only_host = options.only_host.as_deref();
+    let mapping_file = options.mapping_file.as_deref().unwrap_or(HOST_MAPPING_FILE);
+    let owner = output_owner(options)?;
+    let verify_nmcli = options.verify_nmcli && nmcli_available(options)?;
+
+    let (files, skipped) = merge_config_files(config_dirs, options)?;
+    let base = load_base_state(config_dirs, options.max_file_size())?;
+
+    let mut summary = GenerateSummary {
+        skipped,
+        ..Default::default()
     };
+    let mut hosts = Vec::new();
+
+    let progress = options.progress.as_ref().map(|multi| {
+        let style = ProgressStyle::with_template("{bar:40} {pos}/{len} hosts")
+            .expect("valid progress template");
+        multi.add(
+            ProgressBar::new(files.len() as u64)
+                .with_style(style)
+                .with_finish(ProgressFinish::AndClear),
+        )
+    });
 
-    for entry in fs::read_dir(config_dir)? {
-        let entry = entry?;
-        let path = entry.path();
+    for (path, hostname) in files {
+        if let Some(progress) = &progress {
+            progress.inc(1);
+        }
 
-        if entry.metadata()?.is_dir() {
-            warn!("Ignoring unexpected dir: {path:?}");
+        if only_host.is_some_and(|only| only != hostname) {
+            summary.skipped += 1;
             continue;
         }
 
-        info!("Generating config from {path:?}...");
+        info!(host = hostname.as_str(), path:% = path.display(); "Generating config from {path:?}...");
+
+        let (host, config) = match generate_host(
+            &path,
+            &hostname,
+            options,
+            base.as_ref(),
+            verify_nmcli,
+        ) {
+            Ok(generated) => generated,
+            Err(err) if options.continue_on_error => {
+                error!(host = hostname.as_str(); "Generating config from {path:?} failed: {err:#}");
+                summary.failed.push(FailedConfig {
+                    path,
+                    error: format!("{err:#}"),
+                });
+                continue;
+            }
+            Err(err) => return Err(err),
+        };
 
-        let hostname = extract_hostname(&path)
-            .and_then(OsStr::to_str)
-            .ok_or_else(|| anyhow!("Invalid file path"))?
-            .to_owned();
+        summary.hosts.push(hostname.clone());
+        summary.interfaces += host.interfaces.len();
 
-        let data = fs::read_to_string(&path).context("Reading network config")?;
+        if options.dump_conf {
+            dump_network_config(&hostname, &config);
+        } else if options.dry_run {
+            for (filename, _) in &config {
+                println!(
+                    "{}",
+                    Path::new(output_dir)
+                        .join(&hostname)
+                        .join(filename)
+                        .display()
+                );
+            }
+            if options.mapping_scope.per_host() {
+                println!(
+                    "{}",
+                    Path::new(output_dir)
+                        .join(&hostname)
+                        .join(PER_HOST_MAPPING_FILE)
+                        .display()
+                );
+            }
 
-        let (interfaces, config) = generate_config(data)?;
+            let names: Vec<&str> = host
+                .interfaces
+                .iter()
+                .map(|i| i.logical_name.as_str())
+                .collect();
+            info!(
+                "Would add host mapping entry: {hostname} ({})",
+                names.join(", ")
+            );
+        } else {
+            store_network_config(output_dir, &hostname, config, owner).context("Storing config")?;
+        }
 
-        store_network_config(output_dir, hostname, interfaces, config).context("Storing config")?;
+        hosts.push(host);
     }
 
-    Ok(())
+    if let Some(progress) = progress {
+        progress.finish_and_clear();
+    }
+
+    if let Some(hostname) = only_host {
+        if summary.hosts.is_empty() && summary.failed.is_empty() {
+            return Err(anyhow!("No config file found for host '{hostname}'"));
+        }
+    }
+
+    check_duplicate_mac_addresses(&hosts)?;
+
+    if !options.dump_conf && !options.dry_run && options.mapping_scope.per_host() {
+        for host in &hosts {
+            store_host_mapping(output_dir, host, owner).context("Storing per-host mapping file")?;
+        }
+    }
+
+    if !options.dump_conf && !options.dry_run && options.mapping_scope.aggregate() {
+        if only_host.is_some() {
+            for host in hosts {
+                update_network_mapping(output_dir, mapping_file, host)
+                    .context("Updating mapping file")?;
+            }
+        } else {
+            store_network_mapping(output_dir, mapping_file, &hosts)
+                .context("Storing mapping file")?;
+        }
+    }
+
+    if options.dry_run {
+        if options.mapping_scope.aggregate() {
+            println!("{}", Path::new(output_dir).join(mapping_file).display());
+        }
+        println!("{}", Path::new(output_dir).join(METADATA_FILE).display());
+
+        info!(
+            "Validated {} host configs with {} interfaces in total, skipped {} entries, nothing written",
+            summary.hosts.len(),
+            summary.interfaces,
+            summary.skipped
+        );
+
+        return Ok(summary);
+    }
+
+    let destination = if options.dump_conf {
+        "stdout"
+    } else {
+        store_metadata(output_dir).context("Storing metadata file")?;
+
+        if let Some((uid, gid)) = owner {
+            let output_path = Path::new(output_dir);
+            let paths = [
+                output_path.to_path_buf(),
+                output_path.join(mapping_file),
+                output_path.join(METADATA_FILE),
+            ];
+
+            for path in paths {
+                if path.exists() {
+                    chown(&path, Some(uid), Some(gid))
+                        .with_context(|| format!("Changing owner of {path:?}"))?;
+                }
+            }
+        }
+
+        output_dir
+    };
+
+    info!(
+        "Generated {} host configs with {} interfaces in total, skipped {} entries, wrote to {destination}",
+        summary.hosts.len(),
+        summary.interfaces,
+        summary.skipped
+    );
+
+    Ok(summary)
+}
+
+/// Read the desired state file of the host and generate its network configurations.
+fn generate_host(
+    path: &Path,
+    hostname: &str,
+    options: &GenerateOptions,
+    base: Option<&serde_yaml::Value>,
+    verify_nmcli: bool,
+) -> Result<(Host, NetworkConfig), anyhow::Error> {
+    let data = read_config_file(path, options.max_file_size()).context("Reading network config")?;
+    let (data, annotations) = extract_annotations(data)
+        .with_context(|| ConfigError::InvalidHostConfig(hostname.to_owned()))?;
+
+    let (interfaces, mut config) = generate_config(hostname, data, options, base)
+        .with_context(|| ConfigError::InvalidHostConfig(hostname.to_owned()))?;
+
+    if verify_nmcli {
+        for (filename, content) in &config {
+            verify_keyfile(filename, content)
+                .with_context(|| ConfigError::InvalidHostConfig(hostname.to_owned()))?;
+        }
+    }
+
+    if options.annotate {
+        annotate_keyfiles(&mut config, path);
+    }
+
+    let host = Host {
+        hostname: hostname.to_owned(),
+        serial: annotations.serial,
+        interfaces,
+    };
+
+    Ok((host, config))
+}
+
+/// Prepend a provenance header to the keyfiles so that the deployed connections can be traced
+/// back to their desired state. NetworkManager ignores lines starting with `#`.
+fn annotate_keyfiles(config: &mut NetworkConfig, source: &Path) {
+    let header = format!(
+        "{PROVENANCE_HEADER_PREFIX}{} (nmstate {NMSTATE_VERSION})\n# Source: {}\n# Generated at: {}\n",
+        clap::crate_version!(),
+        source.display(),
+        humantime::format_rfc3339_seconds(SystemTime::now())
+    );
+
+    for (filename, content) in config.iter_mut() {
+        if Path::new(filename)
+            .extension()
+            .is_some_and(|ext| ext == CONNECTION_FILE_EXT)
+        {
+            content.insert_str(0, &header);
+        }
+    }
+}
+
+/// Annotations of a host which are carried over to its mapping entry, e.g.
+///
+/// ```yaml
+/// nmc:
+///   serial: ABC1234
+/// interfaces:
+///   ...
+/// ```
+#[derive(Deserialize, Default, Debug)]
+#[serde(deny_unknown_fields)]
+pub(crate) struct Annotations {
+    serial: Option<String>,
+}
+
+/// Strip the annotations from the desired state since they are not part of it.
+/// The data is returned as is if it does not contain any.
+pub(crate) fn extract_annotations(data: String) -> Result<(String, Annotations), anyhow::Error> {
+    let mut value = parse_yaml(&data).context("Parsing desired state")?;
+
+    let Some(annotations) = value
+        .as_mapping_mut()
+        .and_then(|state| state.remove(ANNOTATIONS_KEY))
+    else {
+        return Ok((data, Annotations::default()));
+    };
+
+    let annotations: Annotations = serde_yaml::from_value(annotations)
+        .with_context(|| format!("Parsing '{ANNOTATIONS_KEY}' annotations"))?;
+
+    Ok((serde_yaml::to_string(&value)?, annotations))
+}
+
+/// Collect the config files of all `config_dirs` where files in later dirs
+/// replace the ones for the same hostname in earlier dirs.
+///
+/// Returns the collected files and the number of ignored entries.
+fn merge_config_files(
+    config_dirs: &[&str],
+    options: &GenerateOptions,
+) -> Result<(Vec<(PathBuf, String)>, usize), anyhow::Error> {
+    let mut files: Vec<(PathBuf, String)> = Vec::new();
+    let mut positions: HashMap<String, usize> = HashMap::new();
+    let mut skipped = 0;
+
+    for config_dir in config_dirs {
+        let (dir_files, dir_skipped) = collect_config_files(Path::new(config_dir), options)?;
+        skipped += dir_skipped;
+
+        for (path, hostname) in dir_files {
+            match positions.get(&hostname) {
+                Some(&index) => {
+                    info!(host = hostname.as_str(); "Config of host {hostname} from {:?} is overridden by {path:?}", files[index].0);
+                    files[index].0 = path;
+                }
+                None => {
+                    positions.insert(hostname.clone(), files.len());
+                    files.push((path, hostname));
+                }
+            }
+        }
+    }
+
+    Ok((files, skipped))
+}
+
+/// Collect the paths of all config files in the `config_dir` (and its subdirectories
+/// if running in recursive mode) together with the hostnames derived from them
+/// unless configured in the desired states.
+///
+/// Returns the collected files and the number of ignored entries.
+pub(crate) fn collect_config_files(
+    config_dir: &Path,
+    options: &GenerateOptions,
+) -> Result<(Vec<(PathBuf, String)>, usize), anyhow::Error> {
+    let ignore = load_ignore_patterns(config_dir)?;
+
+    let mut files = Vec::new();
+    let mut skipped = 0;
+    let mut dirs = vec![config_dir.to_path_buf()];
+
+    while let Some(dir) = dirs.pop() {
+        for entry in fs::read_dir(&dir)? {
+            let entry = entry?;
+            let path = entry.path();
+            let is_dir = entry.metadata()?.is_dir();
+
+            if dir == config_dir
+                && (entry.file_name() == IGNORE_FILE || entry.file_name() == LINT_CONFIG_FILE)
+            {
+                continue;
+            }
+
+            if dir == config_dir && BASE_FILES.iter().any(|f| entry.file_name() == *f) {
+                continue;
+            }
+
+            if ignore.matched(&path, is_dir).is_ignore() {
+                debug!("Ignoring {path:?} matching the patterns in {IGNORE_FILE}");
+                skipped += 1;
+                continue;
+            }
+
+            if is_dir {
+                if options.recursive {
+                    dirs.push(path);
+                } else if options.strict {
+                    return Err(anyhow!("Unexpected dir: {path:?}"));
+                } else {
+                    warn!("Ignoring unexpected dir: {path:?}");
+                    skipped += 1;
+                }
+                continue;
+            }
+
+            let mut hostname = extract_hostname(&path)
+                .and_then(OsStr::to_str)
+                .ok_or_else(|| anyhow!("Invalid file path"))?
+                .to_owned();
+
+            if options.prefix_hostnames {
+                let parents = dir
+                    .strip_prefix(config_dir)?
+                    .components()
+                    .map(|c| c.as_os_str().to_string_lossy())
+                    .collect::<Vec<_>>();
+
+                if !parents.is_empty() {
+                    hostname = format!("{}-{hostname}", parents.join("-"));
+                }
+            }
+
+            if options.input_format == InputFormat::Nmstate {
+                if let Some(configured) = configured_hostname(&path, options.max_file_size())? {
+                    debug!("Using hostname '{configured}' configured in {path:?}");
+                    hostname = configured;
+                }
+            }
+
+            files.push((path, hostname));
+        }
+    }
+
+    files.sort();
+    check_hostname_collisions(&files)?;
+
+    Ok((files, skipped))
+}
+
+/// Ensure that no two config files within a config dir (e.g. `rack1/node5.yaml` and `rack2/node5.yaml`)
+/// produce the same hostname since their output dirs would overwrite each other.
+fn check_hostname_collisions(files: &[(PathBuf, String)]) -> Result<(), anyhow::Error> {
+    let mut paths: BTreeMap<&str, Vec<&PathBuf>> = BTreeMap::new();
+    for (path, hostname) in files {
+        paths.entry(hostname).or_default().push(path);
+    }
+
+    let collisions: Vec<String> = paths
+        .into_iter()
+        .filter(|(_, paths)| paths.len() > 1)
+        .map(|(hostname, paths)| format!("'{hostname}' ({paths:?})"))
+        .collect();
+
+    if collisions.is_empty() {
+        return Ok(());
+    }
+
+    Err(anyhow!(
+        "Multiple config files produce the same hostname: {}",
+        collisions.join(", ")
+    ))
+}
+
+/// Load the gitignore-style patterns from the ignore file in the config dir, if any.
+fn load_ignore_patterns(config_dir: &Path) -> Result<Gitignore, anyhow::Error> {
+    let mut builder = GitignoreBuilder::new(config_dir);
+
+    let path = config_dir.join(IGNORE_FILE);
+    if path.exists() {
+        if let Some(err) = builder.add(&path) {
+            return Err(err).context(format!("Parsing {path:?}"));
+        }
+    }
+
+    Ok(builder.build()?)
 }
 
+/// Derive the hostname from the file name stripping any recognized config extension
+/// (preceded by `.gz` in case of compressed files).
 fn extract_hostname(path: &Path) -> Option<&OsStr> {
+    let path = match path.extension() {
+        Some(ext) if ext == GZIP_EXTENSION => Path::new(path.file_stem()?),
+        _ => path,
+    };
+
     if path
         .extension()
-        .is_some_and(|ext| ext == "yml" || ext == "yaml")
+        .is_some_and(|ext| CONFIG_FILE_EXTENSIONS.iter().any(|e| ext == *e))
     {
         path.file_stem()
     } else {
@@ -57,169 +703,2952 @@ fn extract_hostname(path: &Path) -> Option<&OsStr> {
     }
 }
 
-fn generate_config(data: String) -> Result<(Vec<Interface>, NetworkConfig), anyhow::Error> {
-    let network_state = NetworkState::new_from_yaml(&data)?;
+/// Read the desired state file decompressing it in case it's gzipped.
+/// Files exceeding `max_size` bytes (after decompression) are rejected.
+pub(crate) fn read_config_file(path: &Path, max_size: u64) -> Result<String, anyhow::Error> {
+    let file = fs::File::open(path)?;
 
-    let interfaces = extract_interfaces(&network_state);
-    validate_interfaces(&interfaces)?;
+    let size = file.metadata()?.len();
+    if size > max_size {
+        return Err(anyhow!(
+            "{path:?} exceeds the maximum file size of {max_size} bytes ({size} bytes)"
+        ));
+    }
 
-    let config = network_state
-        .gen_conf()?
-        .get("NetworkManager")
-        .ok_or_else(|| anyhow!("Invalid NM configuration"))?
-        .to_owned();
+    let mut data = String::new();
+    if path.extension().is_some_and(|ext| ext == GZIP_EXTENSION) {
+        // Only decompress up to a byte past the limit in order to detect exceeding it.
+        GzDecoder::new(file)
+            .take(max_size + 1)
+            .read_to_string(&mut data)?;
 
-    Ok((interfaces, config))
-}
+        if data.len() as u64 > max_size {
+            return Err(anyhow!(
+                "{path:?} exceeds the maximum file size of {max_size} bytes once decompressed"
+            ));
+        }
+    } else {
+        io::BufReader::new(file).read_to_string(&mut data)?;
+    }
 
-fn extract_interfaces(network_state: &NetworkState) -> Vec<Interface> {
-    network_state
-        .interfaces
-        .iter()
-        .filter(|i| i.iface_type() != InterfaceType::Loopback)
-        .map(|i| Interface {
-            logical_name: i.name().to_owned(),
-            mac_address: i.base_iface().mac_address.clone(),
-            interface_type: i.iface_type().to_string(),
-        })
-        .collect()
+    Ok(data)
 }
 
-fn validate_interfaces(interfaces: &[Interface]) -> anyhow::Result<()> {
-    let ethernet_interfaces: Vec<&Interface> = interfaces
-        .iter()
-        .filter(|i| i.interface_type == InterfaceType::Ethernet.to_string())
-        .collect();
+/// Load the desired states shared by all hosts from the `config_dirs` where the ones
+/// in later dirs override the ones in earlier dirs.
+pub(crate) fn load_base_state(
+    config_dirs: &[&str],
+    max_size: u64,
+) -> Result<Option<serde_yaml::Value>, anyhow::Error> {
+    let mut base: Option<serde_yaml::Value> = None;
 
-    if ethernet_interfaces.is_empty() {
-        return Err(anyhow!("No Ethernet interfaces were provided"));
-    }
+    for config_dir in config_dirs {
+        for file in BASE_FILES {
+            let path = Path::new(config_dir).join(file);
+            if !path.is_file() {
+                continue;
+            }
 
-    let ethernet_interfaces: Vec<String> = ethernet_interfaces
-        .iter()
-        .filter(|i| i.mac_address.is_none())
-        .map(|i| i.logical_name.to_owned())
-        .collect();
+            info!("Merging base config {path:?} into all hosts");
 
-    if !ethernet_interfaces.is_empty() {
-        return Err(anyhow!(
-            "Detected Ethernet interfaces without a MAC address: {}",
-            ethernet_interfaces.join(", ")
-        ));
-    };
+            let data = read_config_file(&path, max_size).context("Reading base config")?;
+            let state = parse_yaml(&data)
+                .with_context(|| ConfigError::InvalidHostConfig(path.display().to_string()))?;
 
-    Ok(())
+            base = Some(match base {
+                Some(base) => merge_states(base, state, true),
+                None => state,
+            });
+        }
+    }
+
+    Ok(base)
 }
 
-fn store_network_config(
-    output_dir: &str,
-    hostname: String,
-    interfaces: Vec<Interface>,
-    config: NetworkConfig,
-) -> Result<(), anyhow::Error> {
-    let path = Path::new(output_dir);
+/// Parse the YAML resolving its aliases as well as merge keys (`<<: *base`) so that any further
+/// processing (e.g. merging the base config or converting the input format) operates on plain values
+/// and the anchors keep working regardless of it.
+fn parse_yaml(data: &str) -> Result<serde_yaml::Value, anyhow::Error> {
+    let mut value: serde_yaml::Value = serde_yaml::from_str(data)?;
+    value.apply_merge()?;
 
-    fs::create_dir_all(path.join(&hostname)).context("Creating output dir")?;
+    Ok(value)
+}
 
-    config.iter().try_for_each(|(filename, content)| {
-        let path = path.join(&hostname).join(filename);
+/// Merge the `overlay` desired state into the `base` one where the values of the overlay take precedence.
+///
+/// Mappings are merged recursively while other values (including lists) are replaced.
+/// On the top level, interfaces are merged by name and the routes and route rules are combined.
+fn merge_states(
+    base: serde_yaml::Value,
+    overlay: serde_yaml::Value,
+    top_level: bool,
+) -> serde_yaml::Value {
+    use serde_yaml::Value;
 
-        fs::write(path, content).context("Writing config file")
-    })?;
+    let (mut base, overlay) = match (base, overlay) {
+        (Value::Mapping(base), Value::Mapping(overlay)) => (base, overlay),
+        (_, overlay) => return overlay,
+    };
 
-    let mapping_file = fs::OpenOptions::new()
-        .create(true)
-        .append(true)
-        .open(path.join(HOST_MAPPING_FILE))?;
+    for (key, value) in overlay {
+        let merged = match (top_level, key.as_str(), base.remove(&key)) {
+            (_, _, None) => value,
+            (true, Some("interfaces"), Some(existing)) => merge_interfaces(existing, value),
+            (true, Some("routes" | "route-rules"), Some(Value::Mapping(mut existing))) => {
+                combine_configs(&mut existing, value);
+                Value::Mapping(existing)
+            }
+            (_, _, Some(existing)) => merge_states(existing, value, false),
+        };
 
-    let hosts = [Host {
-        hostname,
-        interfaces,
-    }];
+        base.insert(key, merged);
+    }
 
-    serde_yaml::to_writer(mapping_file, &hosts).context("Writing mapping file")
+    Value::Mapping(base)
 }
 
-#[cfg(test)]
-mod tests {
-    use std::fs;
-    use std::path::Path;
+/// Merge the interfaces with the same names and append the remaining ones.
+fn merge_interfaces(base: serde_yaml::Value, overlay: serde_yaml::Value) -> serde_yaml::Value {
+    use serde_yaml::Value;
 
-    use crate::generate_conf::{
-        extract_hostname, extract_interfaces, generate, generate_config, validate_interfaces,
+    let (mut base, overlay) = match (base, overlay) {
+        (Value::Sequence(base), Value::Sequence(overlay)) => (base, overlay),
+        (_, overlay) => return overlay,
     };
-    use crate::types::{Host, Interface};
-    use crate::HOST_MAPPING_FILE;
 
-    #[test]
-    fn generate_successfully() -> Result<(), anyhow::Error> {
-        let config_dir = "testdata/generate";
-        let exp_output_path = Path::new("testdata/generate/expected");
-        let out_dir = "_out";
-        let output_path = Path::new("_out").join("node1");
+    for interface in overlay {
+        let name = interface.get("name").cloned();
+        let existing = base
+            .iter_mut()
+            .find(|i| name.is_some() && i.get("name").cloned() == name);
 
-        assert!(generate(config_dir, out_dir).is_ok());
+        match existing {
+            Some(existing) => *existing = merge_states(existing.clone(), interface, false),
+            None => base.push(interface),
+        }
+    }
 
-        // verify contents of *.nmconnection files
-        let exp_eth0_conn = fs::read_to_string(exp_output_path.join("eth0.nmconnection"))?;
-        let exp_bridge_conn = fs::read_to_string(exp_output_path.join("bridge0.nmconnection"))?;
-        let exp_lo_conn = fs::read_to_string(exp_output_path.join("lo.nmconnection"))?;
-        let eth0_conn = fs::read_to_string(output_path.join("eth0.nmconnection"))?;
-        let bridge_conn = fs::read_to_string(output_path.join("bridge0.nmconnection"))?;
-        let lo_conn = fs::read_to_string(output_path.join("lo.nmconnection"))?;
+    Value::Sequence(base)
+}
 
-        assert_eq!(exp_eth0_conn, eth0_conn);
-        assert_eq!(exp_bridge_conn, bridge_conn);
-        assert_eq!(exp_lo_conn, lo_conn);
+/// Append the overlay's `config` list (e.g. of routes) to the base one skipping duplicates.
+fn combine_configs(base: &mut serde_yaml::Mapping, overlay: serde_yaml::Value) {
+    use serde_yaml::Value;
 
-        // verify contents of the host mapping file
-        let mut exp_hosts: Vec<Host> = serde_yaml::from_str(
-            fs::read_to_string(exp_output_path.join(HOST_MAPPING_FILE))?.as_str(),
-        )?;
-        let mut hosts: Vec<Host> = serde_yaml::from_str(
-            fs::read_to_string(Path::new(out_dir).join(HOST_MAPPING_FILE))?.as_str(),
-        )?;
+    let Value::Mapping(overlay) = overlay else {
+        return;
+    };
 
-        assert_eq!(exp_hosts.len(), hosts.len());
+    for (key, value) in overlay {
+        match (base.get_mut(&key), value) {
+            (Some(Value::Sequence(existing)), Value::Sequence(items)) if key == "config" => {
+                for item in items {
+                    if !existing.contains(&item) {
+                        existing.push(item);
+                    }
+                }
+            }
+            (_, value) => {
+                base.insert(key, value);
+            }
+        }
+    }
+}
 
-        exp_hosts.sort_by(|a, b| a.hostname.cmp(&b.hostname));
-        hosts.sort_by(|a, b| a.hostname.cmp(&b.hostname));
+/// Hostname configured in the desired state (`hostname.config`) which takes
+/// precedence over the one derived from the file name.
+fn configured_hostname(path: &Path, max_size: u64) -> Result<Option<String>, anyhow::Error> {
+    let data = read_config_file(path, max_size).context("Reading network config")?;
 
-        for (h1, h2) in exp_hosts.iter_mut().zip(hosts.iter_mut()) {
-            h1.interfaces
-                .sort_by(|a, b| a.logical_name.cmp(&b.logical_name));
-            h2.interfaces
-                .sort_by(|a, b| a.logical_name.cmp(&b.logical_name));
-        }
+    // Malformed desired states are reported when generating their config.
+    let Ok(state) = serde_yaml::from_str::<serde_yaml::Value>(&data) else {
+        return Ok(None);
+    };
 
-        assert_eq!(exp_hosts, hosts);
+    let Some(hostname) = state.get("hostname").and_then(|h| h.get("config")) else {
+        return Ok(None);
+    };
+
+    match hostname.as_str() {
+        Some(name) if is_valid_hostname(name) => Ok(Some(name.to_owned())),
+        _ => Err(anyhow!(
+            "Invalid hostname '{}'",
+            serde_yaml::to_string(hostname)?.trim()
+        ))
+        .with_context(|| ConfigError::InvalidHostConfig(path.display().to_string())),
+    }
+}
+
+/// Whether the name is a sequence of RFC 1123 labels, which also rules out the names
+/// escaping the output dir once joined to it (e.g. `..` or `rack1/node1`).
+fn is_valid_hostname(name: &str) -> bool {
+    name.len() <= 253
+        && name.split('.').all(|label| {
+            (1..=63).contains(&label.len())
+                && !label.starts_with('-')
+                && !label.ends_with('-')
+                && label.chars().all(|c| c.is_ascii_alphanumeric() || c == '-')
+        })
+}
+
+/// Generate the NetworkManager connection files for the nmstate desired state without
+/// touching the filesystem. Returns the files in the format `Vec<(file_name, file_content)>`.
+///
+/// Unlike `nmc generate`, MAC addresses are not required since no host mapping is produced.
+pub fn gen_connections(yaml: &str) -> Result<Vec<(String, String)>, anyhow::Error> {
+    let yaml = serde_yaml::to_string(&parse_yaml(yaml)?)?;
+    let network_state = NetworkState::new_from_yaml(&yaml)?;
+    validate_network_state(&network_state)?;
+
+    let config = network_connections(&network_state)?;
+    if config.is_empty() {
+        return Err(anyhow!("No connections generated"));
+    }
+
+    Ok(config)
+}
+
+fn network_connections(network_state: &NetworkState) -> Result<NetworkConfig, anyhow::Error> {
+    Ok(network_state
+        .gen_conf()?
+        .get("NetworkManager")
+        .ok_or_else(|| anyhow!("Invalid NM configuration"))?
+        .to_owned())
+}
+
+fn validate_network_state(network_state: &NetworkState) -> anyhow::Result<()> {
+    validate_ovs_bridges(network_state)?;
+    validate_tunnels(network_state)?;
+    validate_addresses(network_state)?;
+    validate_gateways(network_state)?;
+    validate_dns_servers(network_state)
+}
+
+/// Resolve the nmstate desired state the configurations are generated from by converting
+/// the input format and merging the base state. Returns the state along with the warnings
+/// about keys dropped during the conversion.
+pub(crate) fn resolve_state(
+    data: &str,
+    input_format: InputFormat,
+    base: Option<&serde_yaml::Value>,
+) -> Result<(String, Vec<String>), anyhow::Error> {
+    let data = serde_yaml::to_string(&parse_yaml(data).context("Parsing desired state")?)?;
+    let (mut data, warnings) = to_nmstate(&data, input_format)?;
+
+    if let Some(base) = base {
+        let state = serde_yaml::from_str(&data).context("Parsing desired state")?;
+        data = serde_yaml::to_string(&merge_states(base.clone(), state, true))?;
+    }
+
+    Ok((data, warnings))
+}
+
+fn generate_config(
+    hostname: &str,
+    data: String,
+    options: &GenerateOptions,
+    base: Option<&serde_yaml::Value>,
+) -> Result<(Vec<Interface>, NetworkConfig), anyhow::Error> {
+    let (data, warnings) = resolve_state(&data, options.input_format, base)?;
+    report_warnings(hostname, warnings, options.strict)?;
+
+    let mut network_state = NetworkState::new_from_yaml(&data)?;
+    filter_interface_types(&mut network_state, options)?;
+
+    let mut interfaces = extract_interfaces(&network_state);
+    validate_interfaces(&interfaces)?;
+    validate_network_state(&network_state)?;
+
+    if options.naming_scheme == NamingScheme::Mac {
+        let warnings = assign_predictable_names(&mut interfaces);
+        report_warnings(hostname, warnings, options.strict)?;
+    }
+
+    if options.check_mac_addresses {
+        let (errors, warnings) = check_mac_address_bits(&interfaces);
+        if !errors.is_empty() {
+            return Err(anyhow!("{}", errors.join("; ")));
+        }
+        report_warnings(hostname, warnings, options.strict)?;
+    }
+
+    report_warnings(
+        hostname,
+        lint_port_ip_config(&network_state),
+        options.strict,
+    )?;
+    report_warnings(hostname, lint_bond_options(&network_state), options.strict)?;
+    report_warnings(
+        hostname,
+        lint_dns_duplicates(&network_state),
+        options.strict,
+    )?;
+
+    if options.output_backend == OutputBackend::Networkd {
+        let config = gen_networkd_conf(&network_state)?;
+        if config.is_empty() {
+            return Err(anyhow!("No connections generated for {hostname}"));
+        }
+
+        return Ok((interfaces, config));
+    }
+
+    let mut config = network_connections(&network_state)?;
+
+    if options.skip_loopback_files {
+        config = drop_loopback_connections(config)?;
+    }
+
+    if config.is_empty() {
+        return Err(anyhow!("No connections generated for {hostname}"));
+    }
+
+    if !options.autoconnect_priorities.is_empty() {
+        set_autoconnect_priorities(&mut config, &options.autoconnect_priorities)?;
+    }
+
+    report_warnings(
+        hostname,
+        check_autoconnect_priorities(&config)?,
+        options.strict,
+    )?;
+    report_warnings(hostname, check_connection_types(&config)?, options.strict)?;
+
+    if options.bind == InterfaceBinding::Mac {
+        bind_by_mac_address(&mut config, &interfaces)?;
+    }
+
+    if options.deterministic_uuids {
+        set_deterministic_uuids(hostname, &mut config)?;
+    }
+
+    if let Some(prefix) = &options.connection_id_prefix {
+        prefix_connection_ids(&mut config, prefix)?;
+    }
+
+    if options.normalize_keyfiles {
+        normalize_keyfiles(&mut config)?;
+    }
+
+    Ok((interfaces, config))
+}
+
+/// Log the warnings found in the config of the host or fail with all of them in strict mode.
+fn report_warnings(hostname: &str, warnings: Vec<String>, strict: bool) -> anyhow::Result<()> {
+    if strict && !warnings.is_empty() {
+        return Err(anyhow!("{}", warnings.join("; ")));
+    }
+
+    for warning in warnings {
+        warn!("Host {hostname}: {warning}");
+    }
+
+    Ok(())
+}
+
+/// Set `connection.autoconnect-priority` in the connections bound to the given interfaces.
+fn set_autoconnect_priorities(
+    config: &mut NetworkConfig,
+    priorities: &HashMap<String, i32>,
+) -> Result<(), anyhow::Error> {
+    for (filename, content) in config.iter_mut() {
+        let mut keyfile = Keyfile::parse(content).context(format!("Parsing {filename}"))?;
+
+        let priority = keyfile
+            .get("connection", "interface-name")
+            .and_then(|name| priorities.get(name));
+
+        if let Some(priority) = priority {
+            keyfile.set("connection", "autoconnect-priority", &priority.to_string());
+            *content = keyfile.to_string();
+        }
+    }
+
+    Ok(())
+}
+
+fn drop_loopback_connections(config: NetworkConfig) -> anyhow::Result<NetworkConfig> {
+    let mut retained = Vec::new();
+
+    for (filename, content) in config {
+        let keyfile = Keyfile::parse(&content).context(format!("Parsing {filename}"))?;
+
+        if keyfile.get("connection", "type") == Some(LOOPBACK_CONNECTION_TYPE) {
+            debug!("Skipping loopback connection file {filename}");
+            continue;
+        }
+
+        retained.push((filename, content));
+    }
+
+    Ok(retained)
+}
+
+/// Bind the Ethernet connections to the MAC addresses of their interfaces
+/// by replacing `connection.interface-name` with `ethernet.mac-address`.
+fn bind_by_mac_address(config: &mut NetworkConfig, interfaces: &[Interface]) -> anyhow::Result<()> {
+    for (filename, content) in config.iter_mut() {
+        let mut keyfile = Keyfile::parse(content).context(format!("Parsing {filename}"))?;
+
+        if !keyfile
+            .get("connection", "type")
+            .is_some_and(|t| ETHERNET_CONNECTION_TYPES.contains(&t))
+        {
+            continue;
+        }
+
+        let mac_address = keyfile
+            .get("connection", "interface-name")
+            .and_then(|name| interfaces.iter().find(|i| i.logical_name == name))
+            .and_then(|interface| interface.mac_address.clone());
+
+        if let Some(mac_address) = mac_address {
+            keyfile.remove("connection", "interface-name");
+            keyfile.set("ethernet", "mac-address", &mac_address.to_uppercase());
+            *content = keyfile.to_string();
+        }
+    }
+
+    Ok(())
+}
+
+/// Replace the connection UUIDs with ones derived from the hostname and connection ID
+/// so that they are unique across hosts and stable across runs. References to the
+/// replaced UUIDs (e.g. `connection.master` or `vlan.parent`) are updated accordingly.
+fn set_deterministic_uuids(hostname: &str, config: &mut NetworkConfig) -> anyhow::Result<()> {
+    let mut keyfiles = Vec::new();
+    let mut uuids = Vec::new();
+
+    for (filename, content) in config.iter() {
+        let keyfile = Keyfile::parse(content).context(format!("Parsing {filename}"))?;
+
+        if let Some(uuid) = keyfile.get("connection", "uuid") {
+            let id = keyfile.get("connection", "id").unwrap_or(filename);
+            let name = format!("nmc://{hostname}/{id}");
+            let replacement = Uuid::new_v5(&Uuid::NAMESPACE_URL, name.as_bytes());
+
+            uuids.push((uuid.to_owned(), replacement.hyphenated().to_string()));
+        }
+
+        keyfiles.push(keyfile);
+    }
+
+    for ((_, content), mut keyfile) in config.iter_mut().zip(keyfiles) {
+        for (uuid, replacement) in &uuids {
+            keyfile.replace_value(uuid, replacement);
+        }
+        *content = keyfile.to_string();
+    }
+
+    Ok(())
+}
+
+/// Prefix `connection.id` in the keyfiles so that the connections are distinguishable from ones
+/// created by other tools. The deterministic UUIDs are derived from the unprefixed IDs beforehand
+/// and therefore remain stable regardless of the prefix.
+fn prefix_connection_ids(config: &mut NetworkConfig, prefix: &str) -> anyhow::Result<()> {
+    for (filename, content) in config.iter_mut() {
+        let mut keyfile = Keyfile::parse(content).context(format!("Parsing {filename}"))?;
+
+        if let Some(id) = keyfile.get("connection", "id") {
+            let id = format!("{prefix}{id}");
+            keyfile.set("connection", "id", &id);
+            *content = keyfile.to_string();
+        }
+    }
+
+    Ok(())
+}
+
+/// Re-serialize the keyfiles with their sections and keys in a canonical order
+/// so that the output remains stable regardless of the nmstate version.
+fn normalize_keyfiles(config: &mut NetworkConfig) -> Result<(), anyhow::Error> {
+    for (filename, content) in config.iter_mut() {
+        let mut keyfile = Keyfile::parse(content).context(format!("Parsing {filename}"))?;
+        keyfile.normalize();
+        *content = keyfile.to_string();
+    }
+
+    Ok(())
+}
+
+/// Detect autoconnecting connections of the same type bound to the same interface with equal
+/// priorities since NetworkManager would then activate either one of them nondeterministically.
+/// Connections of different types (e.g. an OVS interface and its OVS port) do not compete.
+fn check_autoconnect_priorities(config: &NetworkConfig) -> anyhow::Result<Vec<String>> {
+    let mut warnings = Vec::new();
+    let mut connections: HashMap<(String, String), Vec<(String, i32)>> = HashMap::new();
+
+    for (filename, content) in config {
+        let keyfile = Keyfile::parse(content).context(format!("Parsing {filename}"))?;
+
+        if keyfile.get("connection", "autoconnect") == Some("false") {
+            continue;
+        }
+
+        let Some(interface) = keyfile.get("connection", "interface-name") else {
+            continue;
+        };
+
+        let id = keyfile.get("connection", "id").unwrap_or(filename);
+        let priority = keyfile
+            .get("connection", "autoconnect-priority")
+            .and_then(|p| p.parse().ok())
+            .unwrap_or_default();
+
+        let connection_type = keyfile.get("connection", "type").unwrap_or_default();
+
+        connections
+            .entry((interface.to_owned(), connection_type.to_owned()))
+            .or_default()
+            .push((id.to_owned(), priority));
+    }
+
+    for ((interface, _), mut connections) in connections {
+        connections.sort();
+
+        for (i, (id, priority)) in connections.iter().enumerate() {
+            if let Some((other, _)) = connections[i + 1..].iter().find(|(_, p)| p == priority) {
+                warnings.push(format!(
+                    "Connections '{id}' and '{other}' are bound to interface \
+                    '{interface}' with the same autoconnect priority {priority}"
+                ));
+            }
+        }
+    }
+
+    warnings.sort();
+
+    Ok(warnings)
+}
+
+/// Detect connections of incompatible types bound to the same interface (e.g. a stale Ethernet
+/// connection next to a bond) since NetworkManager would then activate either one of them unpredictably.
+///
+/// OVS ports may share the name of any interface attached to the bridge, while OVS bridges
+/// and their internal interfaces may share the same name.
+fn check_connection_types(config: &NetworkConfig) -> anyhow::Result<Vec<String>> {
+    let mut connections: BTreeMap<String, BTreeMap<String, String>> = BTreeMap::new();
+
+    for (filename, content) in config {
+        let keyfile = Keyfile::parse(content).context(format!("Parsing {filename}"))?;
+
+        let Some(interface) = keyfile.get("connection", "interface-name") else {
+            continue;
+        };
+
+        let connection_type = match keyfile.get("connection", "type").unwrap_or_default() {
+            "ovs-port" => continue,
+            "ovs-bridge" | "ovs-interface" => "ovs",
+            connection_type => connection_type,
+        };
+
+        let id = keyfile.get("connection", "id").unwrap_or(filename);
+
+        connections
+            .entry(interface.to_owned())
+            .or_default()
+            .entry(connection_type.to_owned())
+            .or_insert_with(|| id.to_owned());
+    }
+
+    Ok(connections
+        .into_iter()
+        .filter(|(_, types)| types.len() > 1)
+        .map(|(interface, types)| {
+            let connections: Vec<String> = types
+                .into_iter()
+                .map(|(connection_type, id)| format!("'{id}' ({connection_type})"))
+                .collect();
+
+            format!(
+                "Connections {} of different types are bound to interface '{interface}'",
+                connections.join(", ")
+            )
+        })
+        .collect())
+}
+
+/// Remove the interfaces whose types are not selected by the options. Controllers and
+/// their ports must be either both kept or both removed.
+fn filter_interface_types(
+    network_state: &mut NetworkState,
+    options: &GenerateOptions,
+) -> anyhow::Result<()> {
+    let excluded: Vec<(String, InterfaceType)> = network_state
+        .interfaces
+        .iter()
+        .filter(|i| !options.includes_type(&i.iface_type()))
+        .map(|i| (i.name().to_owned(), i.iface_type()))
+        .collect();
+
+    if excluded.is_empty() {
+        return Ok(());
+    }
+
+    let is_excluded = |name: &str| excluded.iter().any(|(n, _)| n == name);
+
+    let mut mismatches = Vec::new();
+    for controller in network_state.interfaces.iter() {
+        let controller_excluded = is_excluded(controller.name());
+
+        for port in controller.ports().unwrap_or_default() {
+            if is_excluded(port) != controller_excluded {
+                let (kept, removed) = if controller_excluded {
+                    (port, controller.name())
+                } else {
+                    (controller.name(), port)
+                };
+                mismatches.push(format!(
+                    "'{port}' is a port of '{}' ('{kept}' is kept, '{removed}' is excluded)",
+                    controller.name()
+                ));
+            }
+        }
+    }
+
+    if !mismatches.is_empty() {
+        mismatches.sort();
+        return Err(anyhow!(
+            "Interface type filters separate controllers from their ports: {}",
+            mismatches.join("; ")
+        ));
+    }
+
+    for (name, interface_type) in excluded {
+        network_state.interfaces.remove_iface(&name, interface_type);
+    }
+
+    Ok(())
+}
+
+pub(crate) fn extract_interfaces(network_state: &NetworkState) -> Vec<Interface> {
+    let mut interfaces: Vec<Interface> = network_state
+        .interfaces
+        .iter()
+        .filter(|i| i.iface_type() != InterfaceType::Loopback)
+        .map(|i| Interface {
+            logical_name: i.name().to_owned(),
+            mac_address: i.base_iface().mac_address.clone(),
+            additional_macs: port_mac_addresses(network_state, i),
+            predictable_name: None,
+            interface_type: i.iface_type().to_string(),
+        })
+        .collect();
+
+    // Keep the host mapping stable across runs.
+    interfaces.sort_by(|a, b| a.logical_name.cmp(&b.logical_name));
+
+    interfaces
+}
+
+/// MAC addresses of the ports of a bond so that it can be identified by any of its links.
+fn port_mac_addresses(network_state: &NetworkState, interface: &nmstate::Interface) -> Vec<String> {
+    if interface.iface_type() != InterfaceType::Bond {
+        return Vec::new();
+    }
+
+    let ports = interface.ports().unwrap_or_default();
+
+    let mut mac_addresses: Vec<String> = network_state
+        .interfaces
+        .iter()
+        .filter(|i| ports.contains(&i.name()))
+        .filter_map(|i| i.base_iface().mac_address.clone())
+        .collect();
+
+    // Keep the host mapping stable across runs.
+    mac_addresses.sort();
+
+    mac_addresses
+}
+
+/// Record the names the Ethernet interfaces get under systemd's `NamePolicy=mac` so that
+/// `nmc apply` renames them deterministically instead of relying on the local NIC names only.
+///
+/// Returns warnings about the authored names which collide with the predictable name of
+/// another interface since renaming either of them would clash with the other one.
+fn assign_predictable_names(interfaces: &mut [Interface]) -> Vec<String> {
+    interfaces
+        .iter_mut()
+        .filter(|i| i.interface_type == InterfaceType::Ethernet.to_string())
+        .for_each(|i| {
+            i.predictable_name = i
+                .mac_address
+                .as_ref()
+                .map(|mac| format!("enx{}", mac.replace(':', "").to_lowercase()));
+        });
+
+    interfaces
+        .iter()
+        .filter_map(|interface| {
+            let other = interfaces.iter().find(|other| {
+                other.logical_name != interface.logical_name
+                    && other.predictable_name.as_ref() == Some(&interface.logical_name)
+            })?;
+
+            Some(format!(
+                "Interface '{}' collides with the predictable name of '{}'",
+                interface.logical_name, other.logical_name
+            ))
+        })
+        .collect()
+}
+
+pub(crate) fn validate_interfaces(interfaces: &[Interface]) -> anyhow::Result<()> {
+    let ethernet_interfaces: Vec<&Interface> = interfaces
+        .iter()
+        .filter(|i| i.interface_type == InterfaceType::Ethernet.to_string())
+        .collect();
+
+    if ethernet_interfaces.is_empty() {
+        return Err(anyhow!("No Ethernet interfaces were provided"));
+    }
+
+    let ethernet_interfaces: Vec<String> = ethernet_interfaces
+        .iter()
+        .filter(|i| i.mac_address.is_none())
+        .map(|i| i.logical_name.to_owned())
+        .collect();
+
+    if !ethernet_interfaces.is_empty() {
+        return Err(anyhow!(
+            "Detected Ethernet interfaces without a MAC address: {}",
+            ethernet_interfaces.join(", ")
+        ));
+    };
+
+    Ok(())
+}
+
+/// Ensure that every OVS bridge has its ports defined since NetworkManager
+/// does not activate OVS bridges without ports.
+fn validate_ovs_bridges(network_state: &NetworkState) -> anyhow::Result<()> {
+    let mut bridges: Vec<&str> = network_state
+        .interfaces
+        .iter()
+        .filter(|i| i.iface_type() == InterfaceType::OvsBridge && !i.is_absent())
+        .filter(|i| i.ports().unwrap_or_default().is_empty())
+        .map(|i| i.name())
+        .collect();
+
+    if bridges.is_empty() {
+        return Ok(());
+    }
+
+    bridges.sort();
+
+    Err(anyhow!(
+        "Detected OVS bridges without ports: {}",
+        bridges.join(", ")
+    ))
+}
+
+/// Ensure that VXLANs have the attributes required by NetworkManager and reject GRE tunnels
+/// which nmstate does not support and would silently configure as Ethernet interfaces instead.
+fn validate_tunnels(network_state: &NetworkState) -> anyhow::Result<()> {
+    let mut issues = Vec::new();
+
+    for interface in network_state.interfaces.iter().filter(|i| !i.is_absent()) {
+        let name = interface.name();
+
+        if let Some(kind) = unknown_interface_type(interface) {
+            if GRE_TYPES.contains(&kind.as_str()) {
+                issues.push(format!("{name}: {kind} tunnels are not supported"));
+            }
+            continue;
+        }
+
+        let nmstate::Interface::Vxlan(vxlan) = interface else {
+            continue;
+        };
+
+        let Some(config) = &vxlan.vxlan else {
+            issues.push(format!("{name}: missing VXLAN config"));
+            continue;
+        };
+
+        if config.id > MAX_VXLAN_ID {
+            issues.push(format!(
+                "{name}: VNI {} is out of range (0-{MAX_VXLAN_ID})",
+                config.id
+            ));
+        }
+
+        if config.remote.is_none() {
+            issues.push(format!("{name}: missing remote address"));
+        }
+    }
+
+    if issues.is_empty() {
+        return Ok(());
+    }
+
+    issues.sort();
+
+    Err(anyhow!(
+        "Detected invalid tunnel interfaces: {}",
+        issues.join("; ")
+    ))
+}
+
+/// Type of an interface which nmstate does not support as specified in the desired state.
+///
+/// nmstate parses such interfaces as `unknown` and only keeps the original type in a private
+/// field which is serialized after (and therefore overrides) the `unknown` one.
+fn unknown_interface_type(interface: &nmstate::Interface) -> Option<String> {
+    if !matches!(interface, nmstate::Interface::Unknown(_)) {
+        return None;
+    }
+
+    serde_json::to_value(interface)
+        .ok()?
+        .get("type")?
+        .as_str()
+        .map(str::to_owned)
+}
+
+/// Ensure that the same static IP address is not assigned to more than one interface.
+/// IPv6 link-local addresses are skipped since they are only unique per link.
+pub(crate) fn validate_addresses(network_state: &NetworkState) -> anyhow::Result<()> {
+    let mut assigned: HashMap<IpAddr, Vec<&str>> = HashMap::new();
+
+    for interface in network_state.interfaces.iter() {
+        let base = interface.base_iface();
+
+        let ipv4_addresses = base
+            .ipv4
+            .as_ref()
+            .filter(|ip| ip.enabled)
+            .and_then(|ip| ip.addresses.as_deref())
+            .unwrap_or_default();
+        let ipv6_addresses = base
+            .ipv6
+            .as_ref()
+            .filter(|ip| ip.enabled)
+            .and_then(|ip| ip.addresses.as_deref())
+            .unwrap_or_default();
+
+        for address in ipv4_addresses.iter().chain(ipv6_addresses) {
+            if let IpAddr::V6(ip) = address.ip {
+                if ip.is_unicast_link_local() {
+                    continue;
+                }
+            }
+
+            let names = assigned.entry(address.ip).or_default();
+            if !names.contains(&base.name.as_str()) {
+                names.push(&base.name);
+            }
+        }
+    }
+
+    let mut conflicts: Vec<(IpAddr, Vec<&str>)> = assigned
+        .into_iter()
+        .filter(|(_, names)| names.len() > 1)
+        .collect();
+
+    if conflicts.is_empty() {
+        return Ok(());
+    }
+
+    conflicts.sort();
+
+    let conflicts: Vec<String> = conflicts
+        .into_iter()
+        .map(|(ip, mut names)| {
+            names.sort();
+            format!("{ip} ({})", names.join(", "))
+        })
+        .collect();
+
+    Err(anyhow!(
+        "Detected IP addresses assigned to multiple interfaces: {}",
+        conflicts.join("; ")
+    ))
+}
+
+/// Ensure that the default gateways are reachable i.e. in the same subnet as one of the
+/// static addresses of their next hop interface. Interfaces without static addresses of
+/// the same family (e.g. using DHCP) and IPv6 link-local gateways are skipped.
+fn validate_gateways(network_state: &NetworkState) -> anyhow::Result<()> {
+    let mut errors = Vec::new();
+
+    for route in network_state.routes.config.as_deref().unwrap_or_default() {
+        if route.state == Some(RouteState::Absent)
+            || !matches!(route.destination.as_deref(), Some("0.0.0.0/0" | "::/0"))
+        {
+            continue;
+        }
+
+        let (Some(iface_name), Some(gateway)) = (&route.next_hop_iface, &route.next_hop_addr)
+        else {
+            continue;
+        };
+
+        let Ok(gateway) = gateway.parse::<IpAddr>() else {
+            errors.push(format!("{iface_name}: invalid gateway '{gateway}'"));
+            continue;
+        };
+
+        if let IpAddr::V6(ip) = gateway {
+            if ip.is_unicast_link_local() {
+                continue;
+            }
+        }
+
+        let Some(interface) = network_state
+            .interfaces
+            .iter()
+            .find(|i| i.name() == iface_name)
+        else {
+            continue;
+        };
+
+        let base = interface.base_iface();
+        let addresses = match gateway {
+            IpAddr::V4(_) => base.ipv4.as_ref().and_then(|ip| ip.addresses.as_deref()),
+            IpAddr::V6(_) => base.ipv6.as_ref().and_then(|ip| ip.addresses.as_deref()),
+        }
+        .unwrap_or_default();
+
+        if addresses.is_empty() {
+            continue;
+        }
+
+        if !addresses
+            .iter()
+            .any(|a| is_same_subnet(a.ip, gateway, a.prefix_length))
+        {
+            errors.push(format!(
+                "{iface_name}: gateway {gateway} is not in the subnet of any of its addresses"
+            ));
+        }
+    }
+
+    if errors.is_empty() {
+        return Ok(());
+    }
+
+    Err(anyhow!(
+        "Detected unreachable gateways: {}",
+        errors.join("; ")
+    ))
+}
+
+fn is_same_subnet(address: IpAddr, other: IpAddr, prefix_length: u8) -> bool {
+    match (address, other) {
+        (IpAddr::V4(a), IpAddr::V4(b)) => {
+            let mask = u32::MAX.checked_shl(32 - u32::from(prefix_length.min(32)));
+            let mask = mask.unwrap_or_default();
+            u32::from(a) & mask == u32::from(b) & mask
+        }
+        (IpAddr::V6(a), IpAddr::V6(b)) => {
+            let mask = u128::MAX.checked_shl(128 - u32::from(prefix_length.min(128)));
+            let mask = mask.unwrap_or_default();
+            u128::from(a) & mask == u128::from(b) & mask
+        }
+        _ => false,
+    }
+}
+
+/// Ensure that the configured DNS servers are IP addresses. IPv6 link-local
+/// servers may be suffixed with the interface they are reachable through e.g. `fe80::1%eth0`.
+fn validate_dns_servers(network_state: &NetworkState) -> anyhow::Result<()> {
+    let invalid: Vec<&str> = network_state
+        .dns
+        .as_ref()
+        .and_then(|dns| dns.config.as_ref())
+        .and_then(|config| config.server.as_deref())
+        .unwrap_or_default()
+        .iter()
+        .filter(|server| {
+            let ip = server.split_once('%').map_or(server.as_str(), |(ip, _)| ip);
+            ip.parse::<IpAddr>().is_err()
+        })
+        .map(String::as_str)
+        .collect();
+
+    if invalid.is_empty() {
+        return Ok(());
+    }
+
+    Err(anyhow!(
+        "Detected invalid DNS servers: {}",
+        invalid.join(", ")
+    ))
+}
+
+/// Detect DNS servers and search domains listed more than once, e.g. after merging the base
+/// desired state, which only clutter the resolver config.
+pub(crate) fn lint_dns_duplicates(network_state: &NetworkState) -> Vec<String> {
+    let Some(config) = network_state
+        .dns
+        .as_ref()
+        .and_then(|dns| dns.config.as_ref())
+    else {
+        return Vec::new();
+    };
+
+    let servers = duplicates(config.server.as_deref().unwrap_or_default(), |server| {
+        // Equal addresses may be written differently e.g. "2001:db8::1" and "2001:0db8::1".
+        server
+            .parse::<IpAddr>()
+            .map_or(server.to_owned(), |ip| ip.to_string())
+    });
+    let search = duplicates(config.search.as_deref().unwrap_or_default(), |domain| {
+        domain.trim_end_matches('.').to_lowercase()
+    });
+
+    let mut warnings = Vec::new();
+    if !servers.is_empty() {
+        warnings.push(format!("Duplicate DNS servers: {}", servers.join(", ")));
+    }
+    if !search.is_empty() {
+        warnings.push(format!(
+            "Duplicate DNS search domains: {}",
+            search.join(", ")
+        ));
+    }
+
+    warnings
+}
+
+/// Entries whose normalized form occurs more than once, in the order of their first occurrence.
+fn duplicates(entries: &[String], normalize: impl Fn(&str) -> String) -> Vec<&str> {
+    let mut seen = HashSet::new();
+    let mut reported = HashSet::new();
+
+    entries
+        .iter()
+        .filter(|entry| {
+            let normalized = normalize(entry);
+            !seen.insert(normalized.clone()) && reported.insert(normalized)
+        })
+        .map(String::as_str)
+        .collect()
+}
+
+fn dump_network_config(hostname: &str, config: &NetworkConfig) {
+    for (filename, content) in config {
+        println!("### {hostname}/{filename}");
+        print!("{content}");
+    }
+}
+
+/// Detect interfaces which are ports of a controller (e.g. bridge or bond) but still
+/// carry IP configuration which NetworkManager would ignore for them.
+pub(crate) fn lint_port_ip_config(network_state: &NetworkState) -> Vec<String> {
+    let mut warnings = Vec::new();
+
+    for controller in network_state.interfaces.iter() {
+        for port in controller.ports().unwrap_or_default() {
+            let Some(interface) = network_state.interfaces.iter().find(|i| i.name() == port) else {
+                continue;
+            };
+
+            let base = interface.base_iface();
+            let ipv4_enabled = base.ipv4.as_ref().is_some_and(|ip| ip.enabled);
+            let ipv6_enabled = base.ipv6.as_ref().is_some_and(|ip| ip.enabled);
+
+            if ipv4_enabled || ipv6_enabled {
+                warnings.push(format!(
+                    "Interface '{}' is a port of '{}' but has IP configuration",
+                    port,
+                    controller.name()
+                ));
+            }
+        }
+    }
+
+    warnings.sort();
+
+    warnings
+}
+
+/// Detect likely misconfigured bond options which nmstate accepts but which result in links
+/// that never aggregate or fail over, e.g. 802.3ad bonds without link monitoring.
+pub(crate) fn lint_bond_options(network_state: &NetworkState) -> Vec<String> {
+    let mut warnings = Vec::new();
+
+    for interface in network_state.interfaces.iter() {
+        let nmstate::Interface::Bond(bond) = interface else {
+            continue;
+        };
+        let Some(config) = bond.bond.as_ref() else {
+            continue;
+        };
+
+        let name = interface.name();
+        let options = config.options.clone().unwrap_or_default();
+        let ports = interface.ports().unwrap_or_default().len();
+
+        if config.mode == Some(BondMode::LACP) {
+            if options.miimon == Some(0) {
+                warnings.push(format!(
+                    "Bond '{name}' in 802.3ad mode disables link monitoring (miimon: 0), so failed links are not detected"
+                ));
+            }
+            if options.arp_interval.is_some_and(|interval| interval > 0) {
+                warnings.push(format!(
+                    "Bond '{name}' in 802.3ad mode uses ARP monitoring (arp_interval) which is not supported for it"
+                ));
+            }
+            if options.lacp_rate.is_none() {
+                warnings.push(format!(
+                    "Bond '{name}' in 802.3ad mode has no lacp_rate, so the partner only sends \
+                    LACPDUs every 30 seconds with the default 'slow' rate"
+                ));
+            }
+            if ports < 2 {
+                warnings.push(format!(
+                    "Bond '{name}' in 802.3ad mode has {ports} port(s), so there are no links to aggregate"
+                ));
+            }
+        } else {
+            let lacp_options: Vec<&str> = [
+                ("lacp_rate", options.lacp_rate.is_some()),
+                ("ad_select", options.ad_select.is_some()),
+                ("ad_actor_system", options.ad_actor_system.is_some()),
+                ("ad_actor_sys_prio", options.ad_actor_sys_prio.is_some()),
+                ("ad_user_port_key", options.ad_user_port_key.is_some()),
+            ]
+            .into_iter()
+            .filter_map(|(option, set)| set.then_some(option))
+            .collect();
+
+            if !lacp_options.is_empty() {
+                warnings.push(format!(
+                    "Bond '{name}' sets {} which only apply to 802.3ad mode",
+                    lacp_options.join(", ")
+                ));
+            }
+        }
+
+        if let Some(min_links) = options.min_links.filter(|&min| min as usize > ports) {
+            warnings.push(format!(
+                "Bond '{name}' requires {min_links} links (min_links) but has {ports} port(s), so it never comes up"
+            ));
+        }
+    }
+
+    warnings.sort();
+
+    warnings
+}
+
+/// Determine the owner of the generated files. Changing the ownership requires root privileges
+/// so the option is ignored with a warning (or fails in strict mode) when running unprivileged.
+fn output_owner(options: &GenerateOptions) -> Result<Option<(u32, u32)>, anyhow::Error> {
+    let Some(owner) = options.output_owner else {
+        return Ok(None);
+    };
+
+    // SAFETY: geteuid() is always successful and has no side effects.
+    if unsafe { libc::geteuid() } == 0 {
+        return Ok(Some(owner));
+    }
+
+    if options.strict {
+        return Err(anyhow!(
+            "Changing the owner of the output requires root privileges"
+        ));
+    }
+
+    warn!("Not changing the owner of the output since it requires root privileges");
+    Ok(None)
+}
+
+/// Check whether `nmcli` can be used for verifying the keyfiles. The verification is skipped
+/// with a warning (or fails in strict mode) if it's not installed.
+fn nmcli_available(options: &GenerateOptions) -> Result<bool, anyhow::Error> {
+    if options.output_backend != OutputBackend::NetworkManager {
+        return Err(anyhow!(
+            "Verifying with nmcli is only supported by the NetworkManager backend"
+        ));
+    }
+
+    match Command::new("nmcli").arg("--version").output() {
+        Ok(output) => {
+            debug!("Using {}", String::from_utf8_lossy(&output.stdout).trim());
+            Ok(true)
+        }
+        Err(err) if err.kind() == io::ErrorKind::NotFound => {
+            if options.strict {
+                return Err(anyhow!("Verifying keyfiles requires nmcli"));
+            }
+
+            warn!("Not verifying the keyfiles since nmcli is not available");
+            Ok(false)
+        }
+        Err(err) => Err(err).context("Running nmcli"),
+    }
+}
+
+/// Let NetworkManager parse the keyfile via `nmcli` in offline mode which neither requires
+/// a running daemon nor modifies any connections. The connection ID is "modified" to its
+/// current value since `nmcli` expects at least one property.
+fn verify_keyfile(filename: &str, content: &str) -> Result<(), anyhow::Error> {
+    let keyfile = Keyfile::parse(content).context(format!("Parsing {filename}"))?;
+    let id = keyfile
+        .get("connection", "id")
+        .ok_or_else(|| anyhow!("Missing connection.id in {filename}"))?;
+
+    let mut child = Command::new("nmcli")
+        .args(["--offline", "connection", "modify", "connection.id", id])
+        .stdin(Stdio::piped())
+        .stdout(Stdio::null())
+        .stderr(Stdio::piped())
+        .spawn()
+        .context("Spawning nmcli")?;
+
+    child
+        .stdin
+        .take()
+        .expect("stdin is piped")
+        .write_all(content.as_bytes())
+        .context("Writing to nmcli")?;
+
+    let output = child.wait_with_output().context("Waiting for nmcli")?;
+    if !output.status.success() {
+        return Err(anyhow!(
+            "NetworkManager rejected {filename}: {}",
+            String::from_utf8_lossy(&output.stderr).trim()
+        ));
+    }
+
+    Ok(())
+}
+
+fn store_network_config(
+    output_dir: &str,
+    hostname: &str,
+    config: NetworkConfig,
+    owner: Option<(u32, u32)>,
+) -> Result<(), anyhow::Error> {
+    let path = Path::new(output_dir).join(hostname);
+
+    fs::create_dir_all(&path).context("Creating output dir")?;
+
+    let mut paths = vec![path.clone()];
+
+    for (filename, content) in &config {
+        let path = path.join(filename);
+
+        fs::write(&path, content).context("Writing config file")?;
+        paths.push(path);
+    }
+
+    if let Some((uid, gid)) = owner {
+        for path in paths {
+            chown(&path, Some(uid), Some(gid))
+                .with_context(|| format!("Changing owner of {path:?}"))?;
+        }
+    }
+
+    Ok(())
+}
+
+fn store_metadata(output_dir: &str) -> Result<(), anyhow::Error> {
+    let metadata = Metadata {
+        nmstate_version: NMSTATE_VERSION.to_string(),
+        nmc_version: clap::crate_version!().to_string(),
+        generated_at: humantime::format_rfc3339_seconds(SystemTime::now()).to_string(),
+    };
+
+    fs::create_dir_all(output_dir).context("Creating output dir")?;
+
+    let metadata_file = fs::File::create(Path::new(output_dir).join(METADATA_FILE))?;
+    serde_yaml::to_writer(metadata_file, &metadata).context("Writing metadata file")
+}
+
+/// Replace the mapping file left over from a previous run, if any, with the given hosts.
+fn store_network_mapping(
+    output_dir: &str,
+    mapping_file: &str,
+    hosts: &[Host],
+) -> Result<(), anyhow::Error> {
+    write_network_mapping(&Path::new(output_dir).join(mapping_file), hosts)
+}
+
+/// Write the mapping file via a temporary one renamed into place so that
+/// a failure never leaves the output dir with a truncated or missing mapping.
+fn write_network_mapping(path: &Path, hosts: &[Host]) -> Result<(), anyhow::Error> {
+    if let Some(dir) = path.parent() {
+        fs::create_dir_all(dir).context("Creating output dir")?;
+    }
+
+    let mut temp_path = path.as_os_str().to_owned();
+    temp_path.push(".tmp");
+
+    let file = fs::File::create(&temp_path)?;
+    serde_yaml::to_writer(file, hosts).context("Writing mapping file")?;
+
+    fs::rename(&temp_path, path).with_context(|| format!("Moving {temp_path:?} to {path:?}"))
+}
+
+/// Store the mapping of the host in its own dir next to its config files.
+fn store_host_mapping(
+    output_dir: &str,
+    host: &Host,
+    owner: Option<(u32, u32)>,
+) -> Result<(), anyhow::Error> {
+    let path = Path::new(output_dir)
+        .join(&host.hostname)
+        .join(PER_HOST_MAPPING_FILE);
+    let file = fs::File::create(&path)?;
+
+    serde_yaml::to_writer(file, std::slice::from_ref(host)).context("Writing mapping file")?;
+
+    if let Some((uid, gid)) = owner {
+        chown(&path, Some(uid), Some(gid))
+            .with_context(|| format!("Changing owner of {path:?}"))?;
+    }
+
+    Ok(())
+}
+
+/// Ensure that no MAC address of a physical interface is assigned to different hosts
+/// since the host identification would be ambiguous otherwise.
+///
+/// The MAC addresses of virtual interfaces (e.g. a bridge defined in the base config)
+/// may well be shared since they don't take part in the identification.
+fn check_duplicate_mac_addresses(hosts: &[Host]) -> Result<(), anyhow::Error> {
+    let mut mac_hosts: BTreeMap<String, BTreeSet<&str>> = BTreeMap::new();
+
+    for host in hosts {
+        for interface in host.interfaces.iter().filter(|i| is_physical_interface(i)) {
+            if let Some(mac) = &interface.mac_address {
+                mac_hosts
+                    .entry(mac.to_lowercase())
+                    .or_default()
+                    .insert(&host.hostname);
+            }
+        }
+    }
+
+    let duplicates: Vec<String> = mac_hosts
+        .iter()
+        .filter(|(_, hostnames)| hostnames.len() > 1)
+        .map(|(mac, hostnames)| {
+            let hostnames: Vec<&str> = hostnames.iter().copied().collect();
+            format!("{mac} ({})", hostnames.join(", "))
+        })
+        .collect();
+
+    if duplicates.is_empty() {
+        return Ok(());
+    }
+
+    Err(ConfigError::InvalidMapping(format!(
+        "MAC addresses shared by multiple hosts: {}",
+        duplicates.join("; ")
+    ))
+    .into())
+}
+
+/// Replace the entry for the given host in the existing mapping file or insert it if not present.
+fn update_network_mapping(
+    output_dir: &str,
+    mapping_file: &str,
+    host: Host,
+) -> Result<(), anyhow::Error> {
+    let mapping_path = Path::new(output_dir).join(mapping_file);
+
+    let mut hosts: Vec<Host> = if mapping_path.exists() {
+        let contents = fs::read_to_string(&mapping_path).context("Reading mapping file")?;
+        serde_yaml::from_str(&contents).context("Parsing mapping file")?
+    } else {
+        Vec::new()
+    };
+
+    match hosts.iter_mut().find(|h| h.hostname == host.hostname) {
+        Some(existing) => *existing = host,
+        None => hosts.push(host),
+    }
+    check_duplicate_mac_addresses(&hosts)?;
+
+    write_network_mapping(&mapping_path, &hosts)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+    use std::fs;
+    use std::io::Write;
+    use std::os::unix::fs::MetadataExt;
+    use std::path::Path;
+
+    use flate2::write::GzEncoder;
+    use flate2::Compression;
+
+    use crate::apply_conf::parse_config;
+    use crate::convert::InputFormat;
+    use crate::generate_conf::{
+        assign_predictable_names, check_autoconnect_priorities, check_connection_types,
+        collect_config_files, extract_annotations, extract_hostname, extract_interfaces,
+        gen_connections, generate, generate_config, is_same_subnet, lint_bond_options,
+        lint_dns_duplicates, lint_port_ip_config, merge_config_files, merge_states,
+        nmcli_available, read_config_file, validate_addresses, validate_dns_servers,
+        validate_gateways, validate_interfaces, validate_ovs_bridges, validate_tunnels,
+        verify_keyfile, GenerateOptions, InterfaceBinding, MappingScope, OutputBackend,
+    };
+    use crate::keyfile::Keyfile;
+    use crate::types::{Host, Interface, Metadata};
+    use crate::{HOST_MAPPING_FILE, METADATA_FILE, PER_HOST_MAPPING_FILE};
+
+    #[test]
+    fn generate_successfully() -> Result<(), anyhow::Error> {
+        let config_dir = "testdata/generate";
+        let exp_output_path = Path::new("testdata/generate/expected");
+        let out_dir = "_out";
+        let output_path = Path::new("_out").join("node1");
+
+        let summary = generate(&[config_dir], out_dir, &GenerateOptions::default())?;
+        assert_eq!(summary.hosts, vec!["node1"]);
+        assert_eq!(summary.interfaces, 2);
+        assert_eq!(summary.skipped, 1); // "expected" dir
+
+        // verify contents of *.nmconnection files
+        let exp_eth0_conn = fs::read_to_string(exp_output_path.join("eth0.nmconnection"))?;
+        let exp_bridge_conn = fs::read_to_string(exp_output_path.join("bridge0.nmconnection"))?;
+        let exp_lo_conn = fs::read_to_string(exp_output_path.join("lo.nmconnection"))?;
+        let eth0_conn = fs::read_to_string(output_path.join("eth0.nmconnection"))?;
+        let bridge_conn = fs::read_to_string(output_path.join("bridge0.nmconnection"))?;
+        let lo_conn = fs::read_to_string(output_path.join("lo.nmconnection"))?;
+
+        assert_eq!(exp_eth0_conn, eth0_conn);
+        assert_eq!(exp_bridge_conn, bridge_conn);
+        assert_eq!(exp_lo_conn, lo_conn);
+
+        // verify contents of the host mapping file
+        let mut exp_hosts: Vec<Host> = serde_yaml::from_str(
+            fs::read_to_string(exp_output_path.join(HOST_MAPPING_FILE))?.as_str(),
+        )?;
+        let mut hosts: Vec<Host> = serde_yaml::from_str(
+            fs::read_to_string(Path::new(out_dir).join(HOST_MAPPING_FILE))?.as_str(),
+        )?;
+
+        assert_eq!(exp_hosts.len(), hosts.len());
+
+        exp_hosts.sort_by(|a, b| a.hostname.cmp(&b.hostname));
+        hosts.sort_by(|a, b| a.hostname.cmp(&b.hostname));
+
+        for (h1, h2) in exp_hosts.iter_mut().zip(hosts.iter_mut()) {
+            h1.interfaces
+                .sort_by(|a, b| a.logical_name.cmp(&b.logical_name));
+            h2.interfaces
+                .sort_by(|a, b| a.logical_name.cmp(&b.logical_name));
+        }
+
+        assert_eq!(exp_hosts, hosts);
+
+        // verify contents of the metadata file
+        let metadata: Metadata = serde_yaml::from_str(
+            fs::read_to_string(Path::new(out_dir).join(METADATA_FILE))?.as_str(),
+        )?;
+        assert_eq!(metadata.nmstate_version, "2.2.26");
+        assert_eq!(metadata.nmc_version, clap::crate_version!());
+
+        // cleanup
+        fs::remove_dir_all(out_dir)?;
+
+        Ok(())
+    }
+
+    #[test]
+    fn generate_twice_does_not_duplicate_hosts() -> Result<(), anyhow::Error> {
+        let config_dir = "testdata/generate";
+        let out_dir = "_out_twice";
+
+        assert!(generate(&[config_dir], out_dir, &GenerateOptions::default()).is_ok());
+        assert!(generate(&[config_dir], out_dir, &GenerateOptions::default()).is_ok());
+
+        let hosts: Vec<Host> = serde_yaml::from_str(
+            fs::read_to_string(Path::new(out_dir).join(HOST_MAPPING_FILE))?.as_str(),
+        )?;
+        let hostnames: Vec<&str> = hosts.iter().map(|h| h.hostname.as_str()).collect();
+        assert_eq!(hostnames, vec!["node1"]);
+
+        // cleanup
+        fs::remove_dir_all(out_dir)?;
+
+        Ok(())
+    }
+
+    #[test]
+    fn generate_fails_due_to_shared_mac_addresses() -> Result<(), anyhow::Error> {
+        let config_dir = "_in_shared_macs";
+        let out_dir = "_out_shared_macs";
+
+        // The mapping of a previous run is kept as long as the new one isn't complete.
+        fs::create_dir_all(out_dir)?;
+        fs::write(Path::new(out_dir).join(HOST_MAPPING_FILE), "[]\n")?;
+
+        fs::create_dir_all(config_dir)?;
+        for hostname in ["node1", "node2"] {
+            fs::copy(
+                "testdata/generate/node1.yaml",
+                Path::new(config_dir).join(format!("{hostname}.yaml")),
+            )?;
+        }
+
+        let error = generate(&[config_dir], out_dir, &GenerateOptions::default()).unwrap_err();
+        assert_eq!(
+            error.to_string(),
+            "MAC addresses shared by multiple hosts: 0e:4d:c6:b8:c4:72 (node1, node2)"
+        );
+        assert_eq!(
+            fs::read_to_string(Path::new(out_dir).join(HOST_MAPPING_FILE))?,
+            "[]\n"
+        );
+
+        // cleanup
+        fs::remove_dir_all(config_dir)?;
+        fs::remove_dir_all(out_dir)?;
+
+        Ok(())
+    }
+
+    #[test]
+    fn generate_fails_due_to_unwritable_output_dir() -> Result<(), anyhow::Error> {
+        let out_dir = "_out_unwritable";
+        fs::write(out_dir, "")?;
+
+        let error = generate(
+            &["testdata/generate"],
+            &format!("{out_dir}/network-config"),
+            &GenerateOptions::default(),
+        )
+        .unwrap_err();
+        assert_eq!(
+            error.to_string(),
+            "Checking destination _out_unwritable/network-config"
+        );
+
+        // cleanup
+        fs::remove_file(out_dir)?;
+
+        Ok(())
+    }
+
+    #[test]
+    fn generate_carries_serial_annotation() -> Result<(), anyhow::Error> {
+        let config_dir = "_in_serial_annotation";
+        let out_dir = "_out_serial_annotation";
+
+        fs::create_dir_all(config_dir)?;
+        let data = fs::read_to_string("testdata/generate/node1.yaml")?;
+        fs::write(
+            Path::new(config_dir).join("node1.yaml"),
+            format!("nmc:\n  serial: ABC1234\n{data}"),
+        )?;
+
+        generate(&[config_dir], out_dir, &GenerateOptions::default())?;
+
+        let hosts: Vec<Host> = serde_yaml::from_str(&fs::read_to_string(
+            Path::new(out_dir).join(HOST_MAPPING_FILE),
+        )?)?;
+        assert_eq!(hosts[0].serial.as_deref(), Some("ABC1234"));
+
+        // Desired states without annotations are passed on as is.
+        let (unchanged, annotations) = extract_annotations(data.clone())?;
+        assert_eq!(unchanged, data);
+        assert!(annotations.serial.is_none());
+
+        let err = extract_annotations(format!("nmc:\n  rack: 1\n{data}")).unwrap_err();
+        assert_eq!(err.to_string(), "Parsing 'nmc' annotations");
+
+        // cleanup
+        fs::remove_dir_all(config_dir)?;
+        fs::remove_dir_all(out_dir)?;
+
+        Ok(())
+    }
+
+    #[test]
+    fn generate_per_host_mappings() -> Result<(), anyhow::Error> {
+        let config_dir = "_in_per_host_mappings";
+        let out_dir = "_out_per_host_mappings";
+
+        fs::create_dir_all(config_dir)?;
+        fs::copy(
+            "testdata/generate/node1.yaml",
+            Path::new(config_dir).join("node1.yaml"),
+        )?;
+
+        let options = GenerateOptions {
+            mapping_scope: MappingScope::PerHost,
+            ..Default::default()
+        };
+        generate(&[config_dir], out_dir, &options)?;
+        assert!(!Path::new(out_dir).join(HOST_MAPPING_FILE).exists());
+
+        let hosts: Vec<Host> = serde_yaml::from_str(&fs::read_to_string(
+            Path::new(out_dir).join("node1").join(PER_HOST_MAPPING_FILE),
+        )?)?;
+        assert_eq!(hosts.len(), 1);
+        assert_eq!(hosts[0].hostname, "node1");
+
+        // The per-host mappings are read if the aggregate one is absent.
+        let parsed = parse_config(out_dir, HOST_MAPPING_FILE, true)?;
+        assert_eq!(parsed.len(), 1);
+        assert_eq!(parsed[0].hostname, "node1");
+
+        let options = GenerateOptions {
+            mapping_scope: MappingScope::Both,
+            ..Default::default()
+        };
+        generate(&[config_dir], out_dir, &options)?;
+        assert!(Path::new(out_dir).join(HOST_MAPPING_FILE).exists());
+        assert!(Path::new(out_dir)
+            .join("node1")
+            .join(PER_HOST_MAPPING_FILE)
+            .exists());
+
+        // cleanup
+        fs::remove_dir_all(config_dir)?;
+        fs::remove_dir_all(out_dir)?;
+
+        Ok(())
+    }
+
+    #[test]
+    fn generate_continues_on_error() -> Result<(), anyhow::Error> {
+        let config_dir = "_in_continue_on_error";
+        let out_dir = "_out_continue_on_error";
+
+        fs::create_dir_all(config_dir)?;
+        fs::copy(
+            "testdata/generate/node1.yaml",
+            Path::new(config_dir).join("node1.yaml"),
+        )?;
+        fs::write(
+            Path::new(config_dir).join("node2.yaml"),
+            "interfaces:\n  - name: eth0\n    type: unknown-type\n",
+        )?;
+
+        assert!(generate(&[config_dir], out_dir, &GenerateOptions::default()).is_err());
+
+        let options = GenerateOptions {
+            continue_on_error: true,
+            ..Default::default()
+        };
+        let summary = generate(&[config_dir], out_dir, &options)?;
+        assert_eq!(summary.hosts, vec!["node1"]);
+        assert_eq!(summary.failed.len(), 1);
+        assert_eq!(
+            summary.failed[0].path,
+            Path::new(config_dir).join("node2.yaml")
+        );
+        assert!(summary.failed[0]
+            .error
+            .starts_with("Invalid config for host node2"));
+
+        assert!(Path::new(out_dir).join("node1/eth0.nmconnection").exists());
+        assert!(!Path::new(out_dir).join("node2").exists());
+        let hosts: Vec<Host> = serde_yaml::from_str(&fs::read_to_string(
+            Path::new(out_dir).join(HOST_MAPPING_FILE),
+        )?)?;
+        assert_eq!(hosts.len(), 1);
+
+        // cleanup
+        fs::remove_dir_all(config_dir)?;
+        fs::remove_dir_all(out_dir)?;
+
+        Ok(())
+    }
+
+    #[test]
+    fn generate_recursively() -> Result<(), anyhow::Error> {
+        let config_dir = "_in_recursive";
+        let out_dir = "_out_recursive";
+
+        fs::create_dir_all(Path::new(config_dir).join("rack1"))?;
+        fs::copy(
+            "testdata/generate/node1.yaml",
+            Path::new(config_dir).join("rack1").join("node5.yaml"),
+        )?;
+
+        let options = GenerateOptions {
+            recursive: true,
+            ..Default::default()
+        };
+        assert_eq!(
+            generate(&[config_dir], out_dir, &options)?.hosts,
+            vec!["node5"]
+        );
+        assert!(Path::new(out_dir).join("node5/eth0.nmconnection").exists());
+
+        let options = GenerateOptions {
+            recursive: true,
+            prefix_hostnames: true,
+            ..Default::default()
+        };
+        assert_eq!(
+            generate(&[config_dir], out_dir, &options)?.hosts,
+            vec!["rack1-node5"]
+        );
+        assert!(Path::new(out_dir)
+            .join("rack1-node5/eth0.nmconnection")
+            .exists());
+
+        // the same hostname in different subdirectories is a collision unless prefixed
+        fs::create_dir_all(Path::new(config_dir).join("rack2"))?;
+        fs::write(
+            Path::new(config_dir).join("rack2").join("node5.yaml"),
+            "interfaces:\n- name: eth0\n  type: ethernet\n  state: up\n  mac-address: 00:11:22:33:44:55\n",
+        )?;
+
+        let unprefixed = GenerateOptions {
+            recursive: true,
+            ..Default::default()
+        };
+        let err = generate(&[config_dir], out_dir, &unprefixed).unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "Multiple config files produce the same hostname: 'node5' ([\"_in_recursive/rack1/node5.yaml\", \
+            \"_in_recursive/rack2/node5.yaml\"])"
+        );
+
+        assert_eq!(
+            generate(&[config_dir], out_dir, &options)?.hosts,
+            vec!["rack1-node5", "rack2-node5"]
+        );
+
+        // subdirectories are ignored unless running recursively
+        let summary = generate(&[config_dir], out_dir, &GenerateOptions::default())?;
+        assert!(summary.hosts.is_empty());
+        assert_eq!(summary.skipped, 2);
+
+        // cleanup
+        fs::remove_dir_all(config_dir)?;
+        fs::remove_dir_all(out_dir)?;
+
+        Ok(())
+    }
+
+    #[test]
+    fn merge_config_files_overrides_hosts() -> Result<(), anyhow::Error> {
+        let base_dir = "_in_merge_base";
+        let override_dir = "_in_merge_override";
+
+        fs::create_dir_all(base_dir)?;
+        fs::create_dir_all(override_dir)?;
+        fs::write(Path::new(base_dir).join("node1.yaml"), "")?;
+        fs::write(Path::new(base_dir).join("node2.yaml"), "")?;
+        fs::write(Path::new(override_dir).join("node2.yml"), "")?;
+        fs::write(Path::new(override_dir).join("node3.yaml"), "")?;
+
+        let (files, skipped) =
+            merge_config_files(&[base_dir, override_dir], &GenerateOptions::default())?;
+
+        assert_eq!(
+            files,
+            vec![
+                (Path::new(base_dir).join("node1.yaml"), "node1".to_string()),
+                (
+                    Path::new(override_dir).join("node2.yml"),
+                    "node2".to_string()
+                ),
+                (
+                    Path::new(override_dir).join("node3.yaml"),
+                    "node3".to_string()
+                ),
+            ]
+        );
+        assert_eq!(skipped, 0);
+
+        // cleanup
+        fs::remove_dir_all(base_dir)?;
+        fs::remove_dir_all(override_dir)?;
+
+        Ok(())
+    }
+
+    #[test]
+    fn merge_base_state() -> Result<(), serde_yaml::Error> {
+        let base = serde_yaml::from_str(
+            r#"
+            dns-resolver:
+              config:
+                server:
+                  - 10.0.0.53
+            routes:
+              config:
+                - destination: 10.10.0.0/16
+                  next-hop-interface: eth0.100
+            interfaces:
+              - name: eth0
+                type: ethernet
+                mtu: 9000
+                ipv4:
+                  enabled: false
+              - name: eth0.100
+                type: vlan
+                vlan:
+                  base-iface: eth0
+                  id: 100
+            "#,
+        )?;
+        let host = serde_yaml::from_str(
+            r#"
+            routes:
+              config:
+                - destination: 0.0.0.0/0
+                  next-hop-interface: eth0
+            interfaces:
+              - name: eth0
+                type: ethernet
+                mac-address: FE:C4:05:42:8B:AA
+                ipv4:
+                  enabled: true
+                  dhcp: true
+            "#,
+        )?;
+        let expected: serde_yaml::Value = serde_yaml::from_str(
+            r#"
+            dns-resolver:
+              config:
+                server:
+                  - 10.0.0.53
+            routes:
+              config:
+                - destination: 10.10.0.0/16
+                  next-hop-interface: eth0.100
+                - destination: 0.0.0.0/0
+                  next-hop-interface: eth0
+            interfaces:
+              - name: eth0
+                type: ethernet
+                mtu: 9000
+                mac-address: FE:C4:05:42:8B:AA
+                ipv4:
+                  enabled: true
+                  dhcp: true
+              - name: eth0.100
+                type: vlan
+                vlan:
+                  base-iface: eth0
+                  id: 100
+            "#,
+        )?;
+
+        assert_eq!(merge_states(base, host, true), expected);
+
+        Ok(())
+    }
+
+    #[test]
+    fn generate_merges_base_config() -> Result<(), anyhow::Error> {
+        let config_dir = "_in_base";
+        let out_dir = "_out_base";
+
+        fs::create_dir_all(config_dir)?;
+        fs::write(
+            Path::new(config_dir).join("base.yml"),
+            "interfaces:\n- name: eth0.100\n  type: vlan\n  vlan:\n    base-iface: eth0\n    id: 100\n",
+        )?;
+        fs::copy(
+            "testdata/generate/node1.yaml",
+            Path::new(config_dir).join("node1.yaml"),
+        )?;
+
+        let summary = generate(&[config_dir], out_dir, &GenerateOptions::default())?;
+        assert_eq!(summary.hosts, vec!["node1"]);
+        assert_eq!(summary.interfaces, 3);
+        assert!(Path::new(out_dir)
+            .join("node1")
+            .join("eth0.100.nmconnection")
+            .exists());
+
+        // cleanup
+        fs::remove_dir_all(config_dir)?;
+        fs::remove_dir_all(out_dir)?;
+
+        Ok(())
+    }
+
+    #[test]
+    fn generate_allows_shared_mac_addresses_of_base_interfaces() -> Result<(), anyhow::Error> {
+        let config_dir = "_in_base_mac";
+        let out_dir = "_out_base_mac";
+
+        fs::create_dir_all(config_dir)?;
+        fs::write(
+            Path::new(config_dir).join("base.yml"),
+            "interfaces:\n- name: br0\n  type: linux-bridge\n  mac-address: 02:00:00:00:00:01\n",
+        )?;
+        for (hostname, mac) in [
+            ("node1", "00:11:22:33:44:01"),
+            ("node2", "00:11:22:33:44:02"),
+        ] {
+            fs::write(
+                Path::new(config_dir).join(format!("{hostname}.yaml")),
+                format!("interfaces:\n- name: eth0\n  type: ethernet\n  mac-address: {mac}\n"),
+            )?;
+        }
+
+        let summary = generate(&[config_dir], out_dir, &GenerateOptions::default());
+
+        // cleanup
+        fs::remove_dir_all(config_dir)?;
+        fs::remove_dir_all(out_dir)?;
+
+        let mut hosts = summary?.hosts;
+        hosts.sort();
+        assert_eq!(hosts, vec!["node1", "node2"]);
+
+        Ok(())
+    }
+
+    #[test]
+    fn generate_deterministic_uuids_is_reproducible() -> Result<(), anyhow::Error> {
+        let out_dirs = ["_out_uuids1", "_out_uuids2"];
+        let options = GenerateOptions {
+            deterministic_uuids: true,
+            ..Default::default()
+        };
+
+        for out_dir in out_dirs {
+            generate(&["testdata/generate"], out_dir, &options)?;
+        }
+
+        for file in [
+            "host_config.yaml",
+            "node1/bridge0.nmconnection",
+            "node1/eth0.nmconnection",
+            "node1/lo.nmconnection",
+        ] {
+            let first = fs::read(Path::new(out_dirs[0]).join(file))?;
+            let second = fs::read(Path::new(out_dirs[1]).join(file))?;
+            assert_eq!(first, second, "{file} differs");
+        }
+
+        // cleanup
+        for out_dir in out_dirs {
+            fs::remove_dir_all(out_dir)?;
+        }
+
+        Ok(())
+    }
+
+    #[test]
+    fn generate_config_sets_deterministic_uuids() -> Result<(), anyhow::Error> {
+        let data = fs::read_to_string("testdata/cloud-init/node1.yaml")?;
+        let options = GenerateOptions {
+            input_format: InputFormat::CloudInit,
+            deterministic_uuids: true,
+            ..Default::default()
+        };
+
+        let keyfiles = |hostname: &str| -> Result<HashMap<String, Keyfile>, anyhow::Error> {
+            let (_, config) = generate_config(hostname, data.clone(), &options, None)?;
+            config
+                .into_iter()
+                .map(|(filename, content)| Ok((filename, Keyfile::parse(&content)?)))
+                .collect()
+        };
+
+        let node1 = keyfiles("node1")?;
+        let node2 = keyfiles("node2")?;
+
+        let bond_uuid = node1["bond0.nmconnection"].get("connection", "uuid");
+        assert_eq!(bond_uuid, Some("6973d075-ff00-5de7-88fd-a21dacdd1ec1"));
+        assert_eq!(
+            node1["eth0.nmconnection"].get("connection", "master"),
+            bond_uuid
+        );
+        assert_eq!(
+            node1["bond0.100.nmconnection"].get("vlan", "parent"),
+            bond_uuid
+        );
+
+        assert_ne!(
+            node2["bond0.nmconnection"].get("connection", "uuid"),
+            bond_uuid
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn collect_config_files_honors_ignore_file() -> Result<(), anyhow::Error> {
+        let config_dir = "_in_ignore";
+
+        fs::create_dir_all(Path::new(config_dir).join("templates"))?;
+        fs::create_dir_all(Path::new(config_dir).join("rack1"))?;
+        fs::write(
+            Path::new(config_dir).join(".nmc-ignore"),
+            "templates/\nREADME*\n",
+        )?;
+        fs::write(Path::new(config_dir).join("README.yaml"), "")?;
+        fs::write(Path::new(config_dir).join("node1.yaml"), "")?;
+        fs::write(Path::new(config_dir).join("templates/base.yaml"), "")?;
+        fs::write(Path::new(config_dir).join("rack1/README.yml"), "")?;
+        fs::write(Path::new(config_dir).join("rack1/node2.yaml"), "")?;
+
+        let options = GenerateOptions {
+            recursive: true,
+            ..Default::default()
+        };
+        let (files, skipped) = collect_config_files(Path::new(config_dir), &options)?;
+
+        let hostnames: Vec<&str> = files.iter().map(|(_, h)| h.as_str()).collect();
+        assert_eq!(hostnames, vec!["node1", "node2"]);
+        assert_eq!(skipped, 3);
+
+        // cleanup
+        fs::remove_dir_all(config_dir)?;
+
+        Ok(())
+    }
+
+    #[test]
+    fn collect_config_files_uses_configured_hostname() -> Result<(), anyhow::Error> {
+        let config_dir = "_in_hostname";
+
+        fs::create_dir_all(config_dir)?;
+        fs::write(
+            Path::new(config_dir).join("rack1-42.yaml"),
+            "hostname:\n  config: node1\ninterfaces: []\n",
+        )?;
+        fs::write(Path::new(config_dir).join("node2.yaml"), "interfaces: []\n")?;
+
+        let (mut files, _) =
+            collect_config_files(Path::new(config_dir), &GenerateOptions::default())?;
+        files.sort();
+
+        let hostnames: Vec<&str> = files.iter().map(|(_, h)| h.as_str()).collect();
+        assert_eq!(hostnames, vec!["node2", "node1"]);
+
+        fs::write(
+            Path::new(config_dir).join("node2.yaml"),
+            "hostname:\n  config: rack1/node2\n",
+        )?;
+
+        let error =
+            collect_config_files(Path::new(config_dir), &GenerateOptions::default()).unwrap_err();
+        assert_eq!(
+            format!("{error:#}"),
+            "Invalid config for host _in_hostname/node2.yaml: Invalid hostname 'rack1/node2'"
+        );
+
+        for hostname in [
+            "'.'",
+            "'..'",
+            "''",
+            "node2.",
+            "-node2",
+            "node_2",
+            "node2.-lab",
+        ] {
+            fs::write(
+                Path::new(config_dir).join("node2.yaml"),
+                format!("hostname:\n  config: {hostname}\n"),
+            )?;
+            assert!(
+                collect_config_files(Path::new(config_dir), &GenerateOptions::default()).is_err(),
+                "{hostname} is accepted"
+            );
+        }
+
+        fs::write(
+            Path::new(config_dir).join("node2.yaml"),
+            "hostname:\n  config: node2.rack1.example.com\n",
+        )?;
+        let (mut files, _) =
+            collect_config_files(Path::new(config_dir), &GenerateOptions::default())?;
+        files.sort();
+        let hostnames: Vec<&str> = files.iter().map(|(_, h)| h.as_str()).collect();
+        assert_eq!(hostnames, vec!["node2.rack1.example.com", "node1"]);
+
+        // cleanup
+        fs::remove_dir_all(config_dir)?;
+
+        Ok(())
+    }
+
+    #[test]
+    fn collect_config_files_fails_on_unexpected_dir_in_strict_mode() -> Result<(), anyhow::Error> {
+        let config_dir = "_in_strict";
+
+        fs::create_dir_all(Path::new(config_dir).join("rack1"))?;
+        fs::write(Path::new(config_dir).join("node1.yaml"), "")?;
+
+        let (files, skipped) =
+            collect_config_files(Path::new(config_dir), &GenerateOptions::default())?;
+        assert_eq!(files.len(), 1);
+        assert_eq!(skipped, 1);
+
+        let options = GenerateOptions {
+            strict: true,
+            ..Default::default()
+        };
+        let error = collect_config_files(Path::new(config_dir), &options).unwrap_err();
+        assert_eq!(error.to_string(), "Unexpected dir: \"_in_strict/rack1\"");
+
+        // cleanup
+        fs::remove_dir_all(config_dir)?;
+
+        Ok(())
+    }
+
+    #[test]
+    fn generate_config_fails_on_warnings_in_strict_mode() -> Result<(), anyhow::Error> {
+        let data = r#"
+        network:
+          version: 2
+          ethernets:
+            eth0:
+              match:
+                macaddress: 00:11:22:33:44:55
+              dhcp4: yes
+              wakeonlan: true
+        "#;
+        let options = GenerateOptions {
+            input_format: InputFormat::CloudInit,
+            ..Default::default()
+        };
+        assert!(generate_config("node1", data.to_string(), &options, None).is_ok());
+
+        let options = GenerateOptions {
+            strict: true,
+            ..options
+        };
+        let error = generate_config("node1", data.to_string(), &options, None).unwrap_err();
+        assert!(error
+            .to_string()
+            .contains("Dropped unsupported cloud-init keys"));
+
+        Ok(())
+    }
+
+    #[test]
+    fn generate_annotates_keyfiles() -> Result<(), anyhow::Error> {
+        let out_dir = "_out_annotate";
+        let options = GenerateOptions {
+            annotate: true,
+            ..Default::default()
+        };
+
+        generate(&["testdata/generate"], out_dir, &options)?;
+
+        let content = fs::read_to_string(Path::new(out_dir).join("node1/eth0.nmconnection"))?;
+        let header: Vec<&str> = content.lines().take(3).collect();
+        assert!(header[0].starts_with("# Generated by nmc "));
+        assert_eq!(header[1], "# Source: testdata/generate/node1.yaml");
+        assert!(header[2].starts_with("# Generated at: "));
+
+        // The header is a comment which does not affect the keyfile.
+        let keyfile = Keyfile::parse(&content)?;
+        assert_eq!(keyfile.get("connection", "id"), Some("eth0"));
+
+        // cleanup
+        fs::remove_dir_all(out_dir)?;
+
+        Ok(())
+    }
+
+    #[test]
+    fn generate_sets_output_owner() -> Result<(), anyhow::Error> {
+        let out_dir = "_out_owner";
+        let options = GenerateOptions {
+            output_owner: Some((1234, 5678)),
+            ..Default::default()
+        };
+
+        generate(&["testdata/generate"], out_dir, &options)?;
+
+        // The ownership is only changed when running as root.
+        let privileged = unsafe { libc::geteuid() } == 0;
+
+        for path in [
+            Path::new(out_dir).to_path_buf(),
+            Path::new(out_dir).join(HOST_MAPPING_FILE),
+            Path::new(out_dir).join(METADATA_FILE),
+            Path::new(out_dir).join("node1"),
+            Path::new(out_dir).join("node1/eth0.nmconnection"),
+        ] {
+            let metadata = fs::metadata(&path)?;
+            assert_eq!(metadata.uid() == 1234, privileged, "{path:?}");
+            assert_eq!(metadata.gid() == 5678, privileged, "{path:?}");
+        }
+
+        // cleanup
+        fs::remove_dir_all(out_dir)?;
+
+        Ok(())
+    }
+
+    #[test]
+    fn generate_config_binds_by_mac_address() -> Result<(), anyhow::Error> {
+        let data = fs::read_to_string("testdata/generate/node1.yaml")?;
+        let options = GenerateOptions {
+            bind: InterfaceBinding::Mac,
+            ..Default::default()
+        };
+
+        let (_, config) = generate_config("node1", data, &options, None)?;
+        let keyfiles: HashMap<String, Keyfile> = config
+            .into_iter()
+            .map(|(filename, content)| Ok((filename, Keyfile::parse(&content)?)))
+            .collect::<Result<_, anyhow::Error>>()?;
+
+        let eth0 = &keyfiles["eth0.nmconnection"];
+        assert_eq!(eth0.get("connection", "interface-name"), None);
+        assert_eq!(
+            eth0.get("ethernet", "mac-address"),
+            Some("0E:4D:C6:B8:C4:72")
+        );
+
+        let bridge0 = &keyfiles["bridge0.nmconnection"];
+        assert_eq!(bridge0.get("connection", "interface-name"), Some("bridge0"));
+        assert_eq!(bridge0.get("ethernet", "mac-address"), None);
+
+        Ok(())
+    }
+
+    #[test]
+    fn generate_config_skips_loopback_files() -> Result<(), anyhow::Error> {
+        let data = fs::read_to_string("testdata/generate/node1.yaml")?;
+        let options = GenerateOptions {
+            skip_loopback_files: true,
+            ..Default::default()
+        };
+
+        let (_, config) = generate_config("node1", data, &options, None)?;
+        let mut filenames: Vec<String> = config.into_iter().map(|(filename, _)| filename).collect();
+        filenames.sort();
+
+        assert_eq!(filenames, vec!["bridge0.nmconnection", "eth0.nmconnection"]);
+
+        Ok(())
+    }
+
+    #[test]
+    fn verify_keyfiles_with_nmcli() {
+        let options = GenerateOptions {
+            verify_nmcli: true,
+            output_backend: OutputBackend::Networkd,
+            ..Default::default()
+        };
+        assert_eq!(
+            nmcli_available(&options).unwrap_err().to_string(),
+            "Verifying with nmcli is only supported by the NetworkManager backend"
+        );
+
+        assert_eq!(
+            verify_keyfile("eth0.nmconnection", "[connection]\ntype=ethernet\n")
+                .unwrap_err()
+                .to_string(),
+            "Missing connection.id in eth0.nmconnection"
+        );
+    }
+
+    #[test]
+    fn generate_from_compressed_files() -> Result<(), anyhow::Error> {
+        let in_dir = "_in_gz";
+        let out_dir = "_out_gz";
+        fs::create_dir_all(in_dir)?;
+
+        let data = fs::read("testdata/generate/node1.yaml")?;
+        let mut encoder = GzEncoder::new(
+            fs::File::create(Path::new(in_dir).join("node1.yaml.gz"))?,
+            Compression::default(),
+        );
+        encoder.write_all(&data)?;
+        encoder.finish()?;
+
+        let summary = generate(&[in_dir], out_dir, &GenerateOptions::default())?;
+        assert_eq!(summary.hosts, vec!["node1"]);
+        assert!(Path::new(out_dir).join("node1/eth0.nmconnection").exists());
+
+        // cleanup
+        fs::remove_dir_all(in_dir)?;
+        fs::remove_dir_all(out_dir)?;
+
+        Ok(())
+    }
+
+    #[test]
+    fn read_config_file_rejects_large_files() -> Result<(), anyhow::Error> {
+        let in_dir = "_in_max_size";
+        fs::create_dir_all(in_dir)?;
+
+        let data = fs::read("testdata/generate/node1.yaml")?;
+        let path = Path::new(in_dir).join("node1.yaml");
+        fs::write(&path, &data)?;
+
+        let gz_path = Path::new(in_dir).join("node2.yaml.gz");
+        let mut encoder = GzEncoder::new(fs::File::create(&gz_path)?, Compression::default());
+        encoder.write_all(&data)?;
+        encoder.finish()?;
+
+        let size = data.len() as u64;
+        assert!(read_config_file(&path, size).is_ok());
+        assert!(read_config_file(&gz_path, size).is_ok());
+
+        assert_eq!(
+            read_config_file(&path, size - 1).unwrap_err().to_string(),
+            format!(
+                "\"_in_max_size/node1.yaml\" exceeds the maximum file size of {} bytes ({size} bytes)",
+                size - 1
+            )
+        );
+        // The compressed file itself is smaller than the limit.
+        assert_eq!(
+            read_config_file(&gz_path, size - 1).unwrap_err().to_string(),
+            format!(
+                "\"_in_max_size/node2.yaml.gz\" exceeds the maximum file size of {} bytes once decompressed",
+                size - 1
+            )
+        );
+
+        let options = GenerateOptions {
+            max_file_size: Some(size - 1),
+            ..Default::default()
+        };
+        assert!(generate(&[in_dir], "_out_max_size", &options).is_err());
+        assert!(!Path::new("_out_max_size").exists());
+
+        // cleanup
+        fs::remove_dir_all(in_dir)?;
+
+        Ok(())
+    }
+
+    #[test]
+    fn generate_dump_conf_does_not_write_files() -> Result<(), anyhow::Error> {
+        let out_dir = "_out_dump";
+        let options = GenerateOptions {
+            dump_conf: true,
+            ..Default::default()
+        };
+
+        let summary = generate(&["testdata/generate"], out_dir, &options)?;
+        assert_eq!(summary.hosts, vec!["node1"]);
+        assert!(!Path::new(out_dir).exists());
+
+        Ok(())
+    }
+
+    #[test]
+    fn generate_with_custom_mapping_file() -> Result<(), anyhow::Error> {
+        let out_dir = "_out_mapping_file";
+        let options = GenerateOptions {
+            mapping_file: Some("staging.yaml".to_string()),
+            ..Default::default()
+        };
+
+        generate(&["testdata/generate"], out_dir, &options)?;
+        assert!(!Path::new(out_dir).join(HOST_MAPPING_FILE).exists());
+
+        let hosts = parse_config(out_dir, "staging.yaml", true)?;
+        assert_eq!(hosts.len(), 1);
+        assert_eq!(hosts[0].hostname, "node1");
+
+        // regenerating replaces the entries instead of appending to them
+        generate(&["testdata/generate"], out_dir, &options)?;
+        assert_eq!(parse_config(out_dir, "staging.yaml", true)?.len(), 1);
+
+        // cleanup
+        fs::remove_dir_all(out_dir)?;
+
+        Ok(())
+    }
+
+    #[test]
+    fn generate_dry_run_does_not_touch_output_dir() -> Result<(), anyhow::Error> {
+        let out_dir = "_out_dry_run";
+        let mapping_path = Path::new(out_dir).join(HOST_MAPPING_FILE);
+
+        fs::create_dir_all(out_dir)?;
+        fs::write(&mapping_path, "[]\n")?;
+
+        let options = GenerateOptions {
+            dry_run: true,
+            ..Default::default()
+        };
+
+        let summary = generate(&["testdata/generate"], out_dir, &options)?;
+        assert_eq!(summary.hosts, vec!["node1"]);
+        assert_eq!(summary.interfaces, 2);
+
+        assert_eq!(fs::read_to_string(&mapping_path)?, "[]\n");
+        assert_eq!(fs::read_dir(out_dir)?.count(), 1);
+
+        // cleanup
+        fs::remove_dir_all(out_dir)?;
+
+        Ok(())
+    }
+
+    #[test]
+    fn generate_fails_due_to_empty_dir() {
+        fs::create_dir_all("empty").unwrap();
+
+        let error = generate(&["empty"], "_out", &GenerateOptions::default()).unwrap_err();
+        assert_eq!(error.to_string(), "Empty config directory: empty");
+
+        fs::remove_dir_all("empty").unwrap();
+    }
+
+    #[test]
+    fn generate_fails_due_to_missing_path() {
+        let error = generate(&["<missing>"], "_out", &GenerateOptions::default()).unwrap_err();
+        assert!(error.to_string().contains("No such file or directory"))
+    }
+
+    #[test]
+    fn generate_only_host_updates_mapping() -> Result<(), anyhow::Error> {
+        let config_dir = "testdata/generate";
+        let out_dir = "_out_only";
+        let mapping_path = Path::new(out_dir).join(HOST_MAPPING_FILE);
+
+        fs::create_dir_all(out_dir)?;
+        fs::write(
+            &mapping_path,
+            r#"- hostname: node2
+  interfaces:
+    - logical_name: eth0
+      mac_address: 00:11:22:33:44:55
+      interface_type: ethernet
+- hostname: node1
+  interfaces:
+    - logical_name: eth5
+      mac_address: 00:11:22:33:44:56
+      interface_type: ethernet
+"#,
+        )?;
+
+        let options = GenerateOptions {
+            only_host: Some("node1".to_string()),
+            ..Default::default()
+        };
+
+        assert!(generate(&[config_dir], out_dir, &options).is_ok());
+        assert!(Path::new(out_dir).join("node1/eth0.nmconnection").exists());
+
+        let hosts: Vec<Host> = serde_yaml::from_str(&fs::read_to_string(&mapping_path)?)?;
+        let hostnames: Vec<&str> = hosts.iter().map(|h| h.hostname.as_str()).collect();
+        assert_eq!(hostnames, vec!["node2", "node1"]);
+
+        let mut interfaces: Vec<&str> = hosts[1]
+            .interfaces
+            .iter()
+            .map(|i| i.logical_name.as_str())
+            .collect();
+        interfaces.sort();
+        assert_eq!(interfaces, vec!["bridge0", "eth0"]);
+
+        // cleanup
+        fs::remove_dir_all(out_dir)?;
+
+        Ok(())
+    }
+
+    #[test]
+    fn generate_only_host_fails_due_to_missing_host() {
+        let options = GenerateOptions {
+            only_host: Some("node5".to_string()),
+            ..Default::default()
+        };
+
+        let error = generate(&["testdata/generate"], "_out_missing", &options).unwrap_err();
+        assert_eq!(error.to_string(), "No config file found for host 'node5'");
+        assert!(!Path::new("_out_missing").exists());
+    }
+
+    #[test]
+    fn gen_connections_successfully() -> Result<(), anyhow::Error> {
+        let data = r#"---
+        interfaces:
+          - name: eth0
+            type: ethernet
+            state: up
+            ipv4:
+              enabled: true
+              dhcp: true
+        "#;
+
+        let config = gen_connections(data)?;
+        assert_eq!(config.len(), 1);
+        assert_eq!(config[0].0, "eth0.nmconnection");
+        assert!(config[0].1.contains("interface-name=eth0"));
+
+        let error = gen_connections("interfaces: []").unwrap_err();
+        assert_eq!(error.to_string(), "No connections generated");
+
+        Ok(())
+    }
+
+    #[test]
+    fn generate_config_checks_mac_addresses() {
+        let data = r#"---
+        interfaces:
+          - name: eth0
+            type: ethernet
+            state: up
+            mac-address: 02:11:22:33:44:55
+        "#;
+        let options = GenerateOptions {
+            check_mac_addresses: true,
+            ..Default::default()
+        };
+        assert!(generate_config("node1", data.to_string(), &options, None).is_ok());
+
+        let strict = GenerateOptions {
+            strict: true,
+            ..options
+        };
+        let err = generate_config("node1", data.to_string(), &strict, None).unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "eth0 has the locally-administered MAC address 02:11:22:33:44:55"
+        );
+
+        let err = generate_config(
+            "node1",
+            data.replace("02:11", "01:11"),
+            &GenerateOptions {
+                check_mac_addresses: true,
+                ..Default::default()
+            },
+            None,
+        )
+        .unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "eth0 has the multicast MAC address 01:11:22:33:44:55"
+        );
+    }
+
+    #[test]
+    fn generate_config_fails_due_to_invalid_data() {
+        let err = generate_config(
+            "node1",
+            "<invalid>".to_string(),
+            &GenerateOptions::default(),
+            None,
+        )
+        .unwrap_err();
+        assert!(err.to_string().contains("Invalid YAML string"))
+    }
+
+    #[test]
+    fn generate_config_fails_due_to_no_connections() {
+        let data = r#"---
+        interfaces:
+          - name: eth0
+            type: ethernet
+            state: ignore
+            mac-address: 00:11:22:33:44:55
+        "#;
+
+        let err = generate_config("node1", data.to_string(), &GenerateOptions::default(), None)
+            .unwrap_err();
+        assert_eq!(err.to_string(), "No connections generated for node1")
+    }
+
+    #[test]
+    fn generate_config_resolves_yaml_anchors() -> Result<(), anyhow::Error> {
+        let data = r#"
+        dns-resolver:
+          config:
+            server: &dns
+              - 192.168.100.1
+        interfaces:
+          - name: eth0
+            type: ethernet
+            state: up
+            mac-address: 00:11:22:33:44:55
+            ipv4: &ipv4
+              enabled: true
+              dhcp: true
+              auto-dns: false
+          - name: eth1
+            type: ethernet
+            state: up
+            mac-address: 00:11:22:33:44:56
+            ipv4: *ipv4
+          - name: eth2
+            type: ethernet
+            state: up
+            mac-address: 00:11:22:33:44:57
+            ipv4:
+              <<: *ipv4
+              auto-dns: true
+        "#;
+
+        let (interfaces, config) =
+            generate_config("node1", data.to_string(), &GenerateOptions::default(), None)?;
+        assert_eq!(interfaces.len(), 3);
+
+        for (filename, content) in config {
+            let keyfile = Keyfile::parse(&content)?;
+            let ignore_auto_dns = keyfile.get("ipv4", "ignore-auto-dns");
+
+            match filename.as_str() {
+                "eth0.nmconnection" | "eth1.nmconnection" => {
+                    assert_eq!(keyfile.get("ipv4", "method"), Some("auto"));
+                    assert_eq!(ignore_auto_dns, Some("true"));
+                }
+                "eth2.nmconnection" => {
+                    assert_eq!(keyfile.get("ipv4", "method"), Some("auto"));
+                    assert_ne!(ignore_auto_dns, Some("true"));
+                }
+                _ => {}
+            }
+        }
+
+        Ok(())
+    }
+
+    #[test]
+    fn generate_config_sets_autoconnect_priorities() -> Result<(), anyhow::Error> {
+        let data = fs::read_to_string("testdata/generate/node1.yaml")?;
+        let options = GenerateOptions {
+            autoconnect_priorities: HashMap::from([("eth0".to_string(), 10)]),
+            ..Default::default()
+        };
+
+        let (_, config) = generate_config("node1", data, &options, None)?;
+
+        for (filename, content) in config {
+            let keyfile = Keyfile::parse(&content)?;
+            let priority = keyfile.get("connection", "autoconnect-priority");
+
+            if filename == "eth0.nmconnection" {
+                assert_eq!(priority, Some("10"));
+            } else {
+                assert_eq!(priority, None);
+            }
+        }
+
+        Ok(())
+    }
+
+    #[test]
+    fn generate_config_prefixes_connection_ids() -> Result<(), anyhow::Error> {
+        let data = fs::read_to_string("testdata/generate/node1.yaml")?;
+        let options = GenerateOptions {
+            deterministic_uuids: true,
+            ..Default::default()
+        };
+        let (_, unprefixed) = generate_config("node1", data.clone(), &options, None)?;
+
+        let options = GenerateOptions {
+            connection_id_prefix: Some("nmc-".to_string()),
+            ..options
+        };
+        let (_, config) = generate_config("node1", data, &options, None)?;
+
+        for ((filename, content), (_, unprefixed)) in config.iter().zip(&unprefixed) {
+            let keyfile = Keyfile::parse(content)?;
+            let unprefixed = Keyfile::parse(unprefixed)?;
+
+            let id = unprefixed.get("connection", "id").unwrap_or_default();
+            assert_eq!(
+                keyfile.get("connection", "id"),
+                Some(format!("nmc-{id}").as_str()),
+                "{filename}"
+            );
+            assert_eq!(
+                keyfile.get("connection", "uuid"),
+                unprefixed.get("connection", "uuid")
+            );
+        }
+
+        Ok(())
+    }
+
+    #[test]
+    fn generate_config_normalizes_keyfiles() -> Result<(), anyhow::Error> {
+        let data = fs::read_to_string("testdata/generate/node1.yaml")?;
+        let options = GenerateOptions {
+            normalize_keyfiles: true,
+            ..Default::default()
+        };
+
+        let (_, mut config) = generate_config("node1", data.clone(), &options, None)?;
+        let (_, mut other_config) = generate_config("node1", data, &options, None)?;
+
+        config.sort();
+        other_config.sort();
+        assert_eq!(config, other_config);
+
+        for (_, content) in config {
+            let sections: Vec<&str> = content.lines().filter(|l| l.starts_with('[')).collect();
+            let mut sorted_sections = sections.clone();
+            sorted_sections.sort();
+
+            assert_eq!(sections, sorted_sections);
+        }
+
+        Ok(())
+    }
+
+    #[test]
+    fn generate_config_filters_interface_types() -> Result<(), anyhow::Error> {
+        let data = fs::read_to_string("testdata/generate/node1.yaml")?;
+        let options = GenerateOptions {
+            exclude_types: vec!["linux-bridge".to_string(), "loopback".to_string()],
+            ..Default::default()
+        };
+
+        let (interfaces, config) = generate_config("node1", data.clone(), &options, None)?;
+
+        let names: Vec<&str> = interfaces.iter().map(|i| i.logical_name.as_str()).collect();
+        assert_eq!(names, vec!["eth0"]);
+
+        let filenames: Vec<&str> = config.iter().map(|(f, _)| f.as_str()).collect();
+        assert_eq!(filenames, vec!["eth0.nmconnection"]);
+
+        let options = GenerateOptions {
+            include_types: vec!["ethernet".to_string()],
+            ..Default::default()
+        };
+
+        let (_, config) = generate_config("node1", data, &options, None)?;
+        let filenames: Vec<&str> = config.iter().map(|(f, _)| f.as_str()).collect();
+        assert_eq!(filenames, vec!["eth0.nmconnection"]);
+
+        Ok(())
+    }
+
+    #[test]
+    fn generate_config_from_cloud_init() -> Result<(), anyhow::Error> {
+        let data = fs::read_to_string("testdata/cloud-init/node1.yaml")?;
+        let options = GenerateOptions {
+            input_format: InputFormat::CloudInit,
+            ..Default::default()
+        };
+
+        let (mut interfaces, config) = generate_config("node1", data, &options, None)?;
+
+        interfaces.sort_by(|a, b| a.logical_name.cmp(&b.logical_name));
+        let names: Vec<(&str, &str)> = interfaces
+            .iter()
+            .map(|i| (i.logical_name.as_str(), i.interface_type.as_str()))
+            .collect();
+        assert_eq!(
+            names,
+            vec![
+                ("bond0", "bond"),
+                ("bond0.100", "vlan"),
+                ("eth0", "ethernet"),
+                ("eth1", "ethernet"),
+                ("eth2", "ethernet"),
+            ]
+        );
+
+        let mut filenames: Vec<&str> = config.iter().map(|(f, _)| f.as_str()).collect();
+        filenames.sort();
+        assert_eq!(
+            filenames,
+            vec![
+                "bond0.100.nmconnection",
+                "bond0.nmconnection",
+                "eth0.nmconnection",
+                "eth1.nmconnection",
+                "eth2.nmconnection",
+            ]
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn generate_config_fails_due_to_separated_ports() {
+        let data = r#"---
+        interfaces:
+          - name: eth0
+            type: ethernet
+            state: up
+            mac-address: 00:11:22:33:44:55
+          - name: br0
+            type: linux-bridge
+            state: up
+            bridge:
+              port:
+                - name: eth0
+        "#;
+
+        let options = GenerateOptions {
+            exclude_types: vec!["linux-bridge".to_string()],
+            ..Default::default()
+        };
+
+        let err = generate_config("node1", data.to_string(), &options, None).unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "Interface type filters separate controllers from their ports: \
+            'eth0' is a port of 'br0' ('eth0' is kept, 'br0' is excluded)"
+        );
+
+        let options = GenerateOptions {
+            include_types: vec!["linux-bridge".to_string()],
+            ..Default::default()
+        };
+
+        let err = generate_config("node1", data.to_string(), &options, None).unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "Interface type filters separate controllers from their ports: \
+            'eth0' is a port of 'br0' ('br0' is kept, 'eth0' is excluded)"
+        );
+    }
+
+    #[test]
+    fn check_connection_types_detects_conflicts() -> Result<(), anyhow::Error> {
+        let connection = |id: &str, interface: &str, connection_type: &str| {
+            (
+                format!("{id}.nmconnection"),
+                format!(
+                    "[connection]\nid={id}\ninterface-name={interface}\ntype={connection_type}\n"
+                ),
+            )
+        };
+        let config = vec![
+            connection("eth0", "eth0", "ethernet"),
+            connection("eth0-bond", "eth0", "bond"),
+            connection("eth1", "eth1", "ethernet"),
+            connection("eth1-port", "eth1", "ovs-port"),
+            connection("br0-br", "br0", "ovs-bridge"),
+            connection("br0-if", "br0", "ovs-interface"),
+            connection("br0-port", "br0", "ovs-port"),
+        ];
+
+        assert_eq!(
+            check_connection_types(&config)?,
+            vec![
+                "Connections 'eth0-bond' (bond), 'eth0' (ethernet) of different types \
+            are bound to interface 'eth0'"
+            ]
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn check_autoconnect_priorities_detects_conflicts() -> Result<(), anyhow::Error> {
+        let config = vec![
+            (
+                "eth0.nmconnection".to_string(),
+                "[connection]\nid=eth0\ninterface-name=eth0\n".to_string(),
+            ),
+            (
+                "eth0-backup.nmconnection".to_string(),
+                "[connection]\nid=eth0-backup\ninterface-name=eth0\n".to_string(),
+            ),
+            (
+                "eth1.nmconnection".to_string(),
+                "[connection]\nid=eth1\ninterface-name=eth1\nautoconnect-priority=5\n".to_string(),
+            ),
+            (
+                "eth1-backup.nmconnection".to_string(),
+                "[connection]\nid=eth1-backup\ninterface-name=eth1\nautoconnect-priority=1\n"
+                    .to_string(),
+            ),
+            (
+                "eth2.nmconnection".to_string(),
+                "[connection]\nid=eth2\ninterface-name=eth2\n".to_string(),
+            ),
+            (
+                "eth2-manual.nmconnection".to_string(),
+                "[connection]\nid=eth2-manual\ninterface-name=eth2\nautoconnect=false\n"
+                    .to_string(),
+            ),
+        ];
+
+        assert_eq!(
+            check_autoconnect_priorities(&config)?,
+            vec![
+                "Connections 'eth0' and 'eth0-backup' are bound to interface 'eth0' \
+                with the same autoconnect priority 0"
+            ]
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn assign_predictable_names_of_ethernet_interfaces() {
+        let mut interfaces = vec![
+            Interface {
+                logical_name: "eth0".to_string(),
+                mac_address: Some("00:11:22:AA:BB:CC".to_string()),
+                additional_macs: Vec::new(),
+                predictable_name: None,
+                interface_type: "ethernet".to_string(),
+            },
+            Interface {
+                logical_name: "eth0.100".to_string(),
+                mac_address: None,
+                additional_macs: Vec::new(),
+                predictable_name: None,
+                interface_type: "vlan".to_string(),
+            },
+            Interface {
+                logical_name: "enx001122aabbcc".to_string(),
+                mac_address: Some("00:11:22:33:44:55".to_string()),
+                additional_macs: Vec::new(),
+                predictable_name: None,
+                interface_type: "ethernet".to_string(),
+            },
+        ];
 
-        // cleanup
-        fs::remove_dir_all(out_dir)?;
+        let warnings = assign_predictable_names(&mut interfaces);
 
-        Ok(())
+        let names: Vec<Option<&str>> = interfaces
+            .iter()
+            .map(|i| i.predictable_name.as_deref())
+            .collect();
+        assert_eq!(
+            names,
+            [Some("enx001122aabbcc"), None, Some("enx001122334455")]
+        );
+        assert_eq!(
+            warnings,
+            ["Interface 'enx001122aabbcc' collides with the predictable name of 'eth0'"]
+        );
     }
 
     #[test]
-    fn generate_fails_due_to_empty_dir() {
-        fs::create_dir_all("empty").unwrap();
-
-        let error = generate("empty", "_out").unwrap_err();
-        assert_eq!(error.to_string(), "Empty config directory");
+    fn extract_interfaces_includes_bond_port_mac_addresses() -> Result<(), serde_yaml::Error> {
+        let net_state: nmstate::NetworkState = serde_yaml::from_str(
+            r#"---
+        interfaces:
+          - name: bond0
+            type: bond
+            mac-address: FE:C4:05:42:8B:AA
+            link-aggregation:
+              mode: active-backup
+              port:
+                - eth0
+                - eth1
+          - name: eth0
+            type: ethernet
+            mac-address: FE:C4:05:42:8B:AA
+          - name: eth1
+            type: ethernet
+            mac-address: FE:C4:05:42:8B:AB
+        "#,
+        )?;
 
-        fs::remove_dir_all("empty").unwrap();
-    }
+        let interfaces = extract_interfaces(&net_state);
 
-    #[test]
-    fn generate_fails_due_to_missing_path() {
-        let error = generate("<missing>", "_out").unwrap_err();
-        assert!(error.to_string().contains("No such file or directory"))
-    }
+        assert_eq!(interfaces[0].logical_name, "bond0");
+        assert_eq!(
+            interfaces[0].additional_macs,
+            vec!["FE:C4:05:42:8B:AA", "FE:C4:05:42:8B:AB"]
+        );
+        assert!(interfaces[1].additional_macs.is_empty());
+        assert!(interfaces[2].additional_macs.is_empty());
 
-    #[test]
-    fn generate_config_fails_due_to_invalid_data() {
-        let err = generate_config("<invalid>".to_string()).unwrap_err();
-        assert!(err.to_string().contains("Invalid YAML string"))
+        Ok(())
     }
 
     #[test]
@@ -248,11 +3677,15 @@ mod tests {
                 Interface {
                     logical_name: "bridge0".to_string(),
                     mac_address: Option::from("FE:C4:05:42:8B:AB".to_string()),
+                    additional_macs: Vec::new(),
+                    predictable_name: None,
                     interface_type: "linux-bridge".to_string(),
                 },
                 Interface {
                     logical_name: "eth1".to_string(),
                     mac_address: Option::from("FE:C4:05:42:8B:AA".to_string()),
+                    additional_macs: Vec::new(),
+                    predictable_name: None,
                     interface_type: "ethernet".to_string(),
                 },
             ]
@@ -267,11 +3700,15 @@ mod tests {
             Interface {
                 logical_name: "eth3.1365".to_string(),
                 mac_address: None,
+                additional_macs: Vec::new(),
+                predictable_name: None,
                 interface_type: "vlan".to_string(),
             },
             Interface {
                 logical_name: "bond0".to_string(),
                 mac_address: None,
+                additional_macs: Vec::new(),
+                predictable_name: None,
                 interface_type: "bond".to_string(),
             },
         ];
@@ -286,31 +3723,43 @@ mod tests {
             Interface {
                 logical_name: "eth0".to_string(),
                 mac_address: Option::from("00:11:22:33:44:55".to_string()),
+                additional_macs: Vec::new(),
+                predictable_name: None,
                 interface_type: "ethernet".to_string(),
             },
             Interface {
                 logical_name: "eth1".to_string(),
                 mac_address: None,
+                additional_macs: Vec::new(),
+                predictable_name: None,
                 interface_type: "ethernet".to_string(),
             },
             Interface {
                 logical_name: "eth2".to_string(),
                 mac_address: Option::from("00:11:22:33:44:56".to_string()),
+                additional_macs: Vec::new(),
+                predictable_name: None,
                 interface_type: "ethernet".to_string(),
             },
             Interface {
                 logical_name: "eth3".to_string(),
                 mac_address: None,
+                additional_macs: Vec::new(),
+                predictable_name: None,
                 interface_type: "ethernet".to_string(),
             },
             Interface {
                 logical_name: "eth3.1365".to_string(),
                 mac_address: None,
+                additional_macs: Vec::new(),
+                predictable_name: None,
                 interface_type: "vlan".to_string(),
             },
             Interface {
                 logical_name: "bond0".to_string(),
                 mac_address: Option::from("00:11:22:33:44:58".to_string()),
+                additional_macs: Vec::new(),
+                predictable_name: None,
                 interface_type: "bond".to_string(),
             },
         ];
@@ -328,16 +3777,22 @@ mod tests {
             Interface {
                 logical_name: "eth0".to_string(),
                 mac_address: Option::from("00:11:22:33:44:55".to_string()),
+                additional_macs: Vec::new(),
+                predictable_name: None,
                 interface_type: "ethernet".to_string(),
             },
             Interface {
                 logical_name: "eth0.1365".to_string(),
                 mac_address: None,
+                additional_macs: Vec::new(),
+                predictable_name: None,
                 interface_type: "vlan".to_string(),
             },
             Interface {
                 logical_name: "bond0".to_string(),
                 mac_address: None,
+                additional_macs: Vec::new(),
+                predictable_name: None,
                 interface_type: "bond".to_string(),
             },
         ];
@@ -345,6 +3800,513 @@ mod tests {
         assert!(validate_interfaces(&interfaces).is_ok())
     }
 
+    #[test]
+    fn validate_addresses_successfully() -> Result<(), serde_yaml::Error> {
+        let net_state: nmstate::NetworkState = serde_yaml::from_str(
+            r#"---
+        interfaces:
+          - name: eth0
+            type: ethernet
+            ipv4:
+              enabled: true
+              address:
+                - ip: 192.168.1.10
+                  prefix-length: 24
+            ipv6:
+              enabled: true
+              address:
+                - ip: fe80::1
+                  prefix-length: 64
+          - name: eth1
+            type: ethernet
+            ipv4:
+              enabled: true
+              address:
+                - ip: 192.168.1.11
+                  prefix-length: 24
+            ipv6:
+              enabled: true
+              address:
+                - ip: fe80::1
+                  prefix-length: 64
+        "#,
+        )?;
+
+        assert!(validate_addresses(&net_state).is_ok());
+
+        Ok(())
+    }
+
+    #[test]
+    fn validate_addresses_fails_due_to_duplicate_ipv4() -> Result<(), serde_yaml::Error> {
+        let net_state: nmstate::NetworkState = serde_yaml::from_str(
+            r#"---
+        interfaces:
+          - name: eth0
+            type: ethernet
+            ipv4:
+              enabled: true
+              address:
+                - ip: 192.168.1.10
+                  prefix-length: 24
+          - name: eth1
+            type: ethernet
+            ipv4:
+              enabled: true
+              address:
+                - ip: 192.168.1.10
+                  prefix-length: 24
+        "#,
+        )?;
+
+        let error = validate_addresses(&net_state).unwrap_err();
+        assert_eq!(
+            error.to_string(),
+            "Detected IP addresses assigned to multiple interfaces: 192.168.1.10 (eth0, eth1)"
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn validate_addresses_fails_due_to_duplicate_ipv6() -> Result<(), serde_yaml::Error> {
+        let net_state: nmstate::NetworkState = serde_yaml::from_str(
+            r#"---
+        interfaces:
+          - name: eth0
+            type: ethernet
+            ipv6:
+              enabled: true
+              address:
+                - ip: 2001:db8::1
+                  prefix-length: 64
+          - name: bond0
+            type: bond
+            ipv6:
+              enabled: true
+              address:
+                - ip: 2001:db8::1
+                  prefix-length: 64
+        "#,
+        )?;
+
+        let error = validate_addresses(&net_state).unwrap_err();
+        assert_eq!(
+            error.to_string(),
+            "Detected IP addresses assigned to multiple interfaces: 2001:db8::1 (bond0, eth0)"
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn validate_gateways_successfully() -> Result<(), serde_yaml::Error> {
+        let net_state: nmstate::NetworkState = serde_yaml::from_str(
+            r#"---
+        interfaces:
+          - name: eth0
+            type: ethernet
+            ipv4:
+              enabled: true
+              address:
+                - ip: 192.168.1.10
+                  prefix-length: 24
+          - name: eth1
+            type: ethernet
+            ipv4:
+              enabled: true
+              dhcp: true
+        routes:
+          config:
+            - destination: 0.0.0.0/0
+              next-hop-interface: eth0
+              next-hop-address: 192.168.1.1
+            - destination: ::/0
+              next-hop-interface: eth0
+              next-hop-address: fe80::1
+            - destination: 0.0.0.0/0
+              next-hop-interface: eth1
+              next-hop-address: 10.0.0.1
+            - destination: 10.10.0.0/16
+              next-hop-interface: eth0
+              next-hop-address: 10.0.0.1
+        "#,
+        )?;
+
+        assert!(validate_gateways(&net_state).is_ok());
+
+        Ok(())
+    }
+
+    #[test]
+    fn validate_gateways_fails_due_to_unreachable_gateway() -> Result<(), serde_yaml::Error> {
+        let net_state: nmstate::NetworkState = serde_yaml::from_str(
+            r#"---
+        interfaces:
+          - name: eth0
+            type: ethernet
+            ipv4:
+              enabled: true
+              address:
+                - ip: 192.168.1.10
+                  prefix-length: 24
+            ipv6:
+              enabled: true
+              address:
+                - ip: 2001:db8::10
+                  prefix-length: 64
+        routes:
+          config:
+            - destination: 0.0.0.0/0
+              next-hop-interface: eth0
+              next-hop-address: 192.168.2.1
+            - destination: ::/0
+              next-hop-interface: eth0
+              next-hop-address: 2001:db8:1::1
+        "#,
+        )?;
+
+        let error = validate_gateways(&net_state).unwrap_err();
+        assert_eq!(
+            error.to_string(),
+            "Detected unreachable gateways: \
+            eth0: gateway 192.168.2.1 is not in the subnet of any of its addresses; \
+            eth0: gateway 2001:db8:1::1 is not in the subnet of any of its addresses"
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn validate_dns_servers_fails_due_to_invalid_address() -> Result<(), serde_yaml::Error> {
+        let net_state: nmstate::NetworkState = serde_yaml::from_str(
+            r#"---
+        dns-resolver:
+          config:
+            server:
+              - 192.168.1.1
+              - fe80::1%eth0
+              - dns.example.com
+              - 192.168.1.256
+        "#,
+        )?;
+
+        let error = validate_dns_servers(&net_state).unwrap_err();
+        assert_eq!(
+            error.to_string(),
+            "Detected invalid DNS servers: dns.example.com, 192.168.1.256"
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn lint_dns_duplicates_detects_repeated_entries() -> Result<(), serde_yaml::Error> {
+        let net_state: nmstate::NetworkState = serde_yaml::from_str(
+            r#"---
+        dns-resolver:
+          config:
+            server:
+              - 192.168.1.1
+              - 2001:db8::1
+              - 192.168.1.1
+              - 2001:0db8::1
+              - 192.168.1.1
+            search:
+              - example.com
+              - example.org
+              - Example.com.
+        "#,
+        )?;
+
+        assert_eq!(
+            lint_dns_duplicates(&net_state),
+            vec![
+                "Duplicate DNS servers: 192.168.1.1, 2001:0db8::1",
+                "Duplicate DNS search domains: Example.com."
+            ]
+        );
+
+        let net_state: nmstate::NetworkState = serde_yaml::from_str(
+            r#"---
+        dns-resolver:
+          config:
+            server:
+              - 192.168.1.1
+            search:
+              - example.com
+        "#,
+        )?;
+        assert!(lint_dns_duplicates(&net_state).is_empty());
+
+        Ok(())
+    }
+
+    #[test]
+    fn same_subnet() {
+        let ip = |s: &str| s.parse().unwrap();
+
+        assert!(is_same_subnet(ip("192.168.1.10"), ip("192.168.1.1"), 24));
+        assert!(!is_same_subnet(ip("192.168.1.10"), ip("192.168.2.1"), 24));
+        assert!(is_same_subnet(ip("192.168.1.10"), ip("10.0.0.1"), 0));
+        assert!(!is_same_subnet(ip("192.168.1.10"), ip("192.168.1.11"), 32));
+        assert!(is_same_subnet(ip("2001:db8::10"), ip("2001:db8::1"), 64));
+        assert!(!is_same_subnet(ip("2001:db8::10"), ip("192.168.1.1"), 0));
+    }
+
+    #[test]
+    fn validate_ovs_bridges_fails_due_to_missing_ports() -> Result<(), serde_yaml::Error> {
+        let net_state: nmstate::NetworkState = serde_yaml::from_str(
+            r#"---
+        interfaces:
+          - name: br1
+            type: ovs-bridge
+            bridge:
+              port: []
+          - name: br0
+            type: ovs-bridge
+          - name: br2
+            type: ovs-bridge
+            bridge:
+              port:
+                - name: eth0
+        "#,
+        )?;
+
+        let error = validate_ovs_bridges(&net_state).unwrap_err();
+        assert_eq!(
+            error.to_string(),
+            "Detected OVS bridges without ports: br0, br1"
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn validate_tunnels_fails_due_to_invalid_interfaces() -> Result<(), serde_yaml::Error> {
+        let net_state: nmstate::NetworkState = serde_yaml::from_str(
+            r#"---
+        interfaces:
+          - name: vxlan0
+            type: vxlan
+            vxlan:
+              id: 20000000
+              base-iface: eth0
+              remote: 192.168.1.20
+          - name: vxlan1
+            type: vxlan
+            vxlan:
+              id: 100
+              local: 192.168.1.10
+          - name: vxlan2
+            type: vxlan
+            vxlan:
+              id: 200
+              base-iface: eth0
+              remote: 192.168.1.20
+          - name: gre1
+            type: gre
+        "#,
+        )?;
+
+        let error = validate_tunnels(&net_state).unwrap_err();
+        assert_eq!(
+            error.to_string(),
+            "Detected invalid tunnel interfaces: gre1: gre tunnels are not supported; \
+            vxlan0: VNI 20000000 is out of range (0-16777215); vxlan1: missing remote address"
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn generate_config_with_vxlan() -> Result<(), anyhow::Error> {
+        let data = r#"---
+        interfaces:
+          - name: eth0
+            type: ethernet
+            state: up
+            mac-address: 00:11:22:33:44:55
+          - name: vxlan0
+            type: vxlan
+            state: up
+            vxlan:
+              id: 100
+              base-iface: eth0
+              remote: 192.168.1.20
+        "#;
+
+        let (interfaces, config) =
+            generate_config("node1", data.to_string(), &GenerateOptions::default(), None)?;
+
+        assert_eq!(interfaces[1].logical_name, "vxlan0");
+        assert_eq!(interfaces[1].interface_type, "vxlan");
+        assert!(config
+            .iter()
+            .any(|(filename, _)| filename == "vxlan0.nmconnection"));
+
+        Ok(())
+    }
+
+    #[test]
+    fn generate_config_with_ovs_bridge() -> Result<(), anyhow::Error> {
+        let data = r#"---
+        interfaces:
+          - name: eth0
+            type: ethernet
+            state: up
+            mac-address: 00:11:22:33:44:55
+          - name: ovs0
+            type: ovs-interface
+            state: up
+          - name: br0
+            type: ovs-bridge
+            state: up
+            bridge:
+              port:
+                - name: eth0
+                - name: ovs0
+        "#;
+
+        let (interfaces, config) =
+            generate_config("node1", data.to_string(), &GenerateOptions::default(), None)?;
+
+        let interfaces: Vec<(&str, &str)> = interfaces
+            .iter()
+            .map(|i| (i.logical_name.as_str(), i.interface_type.as_str()))
+            .collect();
+        assert_eq!(
+            interfaces,
+            vec![
+                ("br0", "ovs-bridge"),
+                ("eth0", "ethernet"),
+                ("ovs0", "ovs-interface")
+            ]
+        );
+
+        let mut filenames: Vec<&str> = config.iter().map(|(f, _)| f.as_str()).collect();
+        filenames.sort();
+        assert_eq!(
+            filenames,
+            vec![
+                "br0-br.nmconnection",
+                "eth0-port.nmconnection",
+                "eth0.nmconnection",
+                "ovs0-if.nmconnection",
+                "ovs0-port.nmconnection",
+            ]
+        );
+
+        // OVS interfaces and their ports do not compete for activation
+        assert!(check_autoconnect_priorities(&config)?.is_empty());
+
+        Ok(())
+    }
+
+    #[test]
+    fn lint_port_ip_config_detects_misconfigured_ports() -> Result<(), serde_yaml::Error> {
+        let net_state: nmstate::NetworkState = serde_yaml::from_str(
+            r#"---
+        interfaces:
+          - name: eth0
+            type: ethernet
+            ipv4:
+              enabled: true
+              dhcp: true
+          - name: eth1
+            type: ethernet
+            ipv4:
+              enabled: false
+            ipv6:
+              enabled: false
+          - name: eth2
+            type: ethernet
+            ipv6:
+              enabled: true
+              autoconf: true
+          - name: eth3
+            type: ethernet
+            ipv4:
+              enabled: true
+              dhcp: true
+          - name: br0
+            type: linux-bridge
+            bridge:
+              port:
+                - name: eth0
+                - name: eth1
+          - name: bond0
+            type: bond
+            link-aggregation:
+              mode: active-backup
+              port:
+                - eth2
+        "#,
+        )?;
+
+        assert_eq!(
+            lint_port_ip_config(&net_state),
+            vec![
+                "Interface 'eth0' is a port of 'br0' but has IP configuration",
+                "Interface 'eth2' is a port of 'bond0' but has IP configuration",
+            ]
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn lint_bond_options_detects_misconfigured_bonds() -> Result<(), serde_yaml::Error> {
+        let net_state: nmstate::NetworkState = serde_yaml::from_str(
+            r#"---
+        interfaces:
+          - name: bond0
+            type: bond
+            link-aggregation:
+              mode: 802.3ad
+              options:
+                miimon: 100
+                lacp_rate: fast
+              port:
+                - eth0
+                - eth1
+          - name: bond1
+            type: bond
+            link-aggregation:
+              mode: 802.3ad
+              options:
+                miimon: 0
+                min_links: 2
+              port:
+                - eth2
+          - name: bond2
+            type: bond
+            link-aggregation:
+              mode: active-backup
+              options:
+                lacp_rate: fast
+                ad_select: bandwidth
+              port:
+                - eth3
+                - eth4
+        "#,
+        )?;
+
+        assert_eq!(
+            lint_bond_options(&net_state),
+            vec![
+                "Bond 'bond1' in 802.3ad mode disables link monitoring (miimon: 0), so failed links are not detected",
+                "Bond 'bond1' in 802.3ad mode has 1 port(s), so there are no links to aggregate",
+                "Bond 'bond1' in 802.3ad mode has no lacp_rate, so the partner only sends LACPDUs every 30 seconds \
+                with the default 'slow' rate",
+                "Bond 'bond1' requires 2 links (min_links) but has 1 port(s), so it never comes up",
+                "Bond 'bond2' sets lacp_rate, ad_select which only apply to 802.3ad mode",
+            ]
+        );
+
+        Ok(())
+    }
+
     #[test]
     fn extract_host_name() {
         assert_eq!(extract_hostname("".as_ref()), None);
@@ -365,5 +4327,25 @@ mod tests {
             extract_hostname("node1.example.com.yaml".as_ref()),
             Some("node1.example.com".as_ref())
         );
+        assert_eq!(
+            extract_hostname("node1.example.com.json".as_ref()),
+            Some("node1.example.com".as_ref())
+        );
+        assert_eq!(
+            extract_hostname("rack1/node1.json".as_ref()),
+            Some("node1".as_ref())
+        );
+        assert_eq!(
+            extract_hostname("node1.yaml.bak".as_ref()),
+            Some("node1.yaml.bak".as_ref())
+        );
+        assert_eq!(
+            extract_hostname("node1.example.com.yaml.gz".as_ref()),
+            Some("node1.example.com".as_ref())
+        );
+        assert_eq!(
+            extract_hostname("node1.yml.gz".as_ref()),
+            Some("node1".as_ref())
+        );
     }
 }
@@ -0,0 +1,195 @@
+use std::collections::BTreeMap;
+use std::fs;
+use std::path::Path;
+
+use anyhow::Context;
+use log::debug;
+use network_interface::{NetworkInterface, NetworkInterfaceConfig};
+use serde::Serialize;
+
+/// Sysfs dir listing the network interfaces. Physical ones link to their underlying `device`.
+const SYSFS_NET_DIR: &str = "/sys/class/net";
+const EMPTY_MAC_ADDRESS: &str = "00:00:00:00:00:00";
+
+/// Skeleton nmstate desired state.
+#[derive(Serialize, Debug)]
+struct Inventory {
+    interfaces: Vec<InventoryInterface>,
+}
+
+#[derive(Serialize, Debug)]
+#[serde(rename_all = "kebab-case")]
+#[cfg_attr(test, derive(PartialEq))]
+struct InventoryInterface {
+    name: String,
+    #[serde(rename = "type")]
+    interface_type: &'static str,
+    state: &'static str,
+    mac_address: String,
+    /// Diagnostic details which are not part of the desired state.
+    #[serde(skip)]
+    details: NicDetails,
+}
+
+/// Kernel driver and link speed (in Mb/s) of a NIC. The speed is unknown while the link is down.
+#[derive(Debug, Default)]
+#[cfg_attr(test, derive(PartialEq))]
+struct NicDetails {
+    driver: Option<String>,
+    speed: Option<u32>,
+}
+
+impl Inventory {
+    /// Render the desired state annotating the interfaces with their details as comments
+    /// so that the output remains a valid desired state.
+    fn to_yaml(&self) -> Result<String, anyhow::Error> {
+        let mut yaml = String::from("interfaces:\n");
+
+        for interface in &self.interfaces {
+            let details = &interface.details;
+            yaml.push_str(&format!(
+                "# driver: {}, speed: {}\n",
+                details.driver.as_deref().unwrap_or("unknown"),
+                details
+                    .speed
+                    .map_or("unknown".to_string(), |speed| format!("{speed} Mb/s"))
+            ));
+            yaml.push_str(&serde_yaml::to_string(&[interface])?);
+        }
+
+        Ok(yaml)
+    }
+}
+
+/// Print a skeleton nmstate desired state with an Ethernet interface per physical NIC of the host
+/// which serves as a starting point for its config instead of transcribing the MAC addresses by hand.
+pub(crate) fn inventory() -> Result<(), anyhow::Error> {
+    let network_interfaces = NetworkInterface::show().context("Retrieving network interfaces")?;
+    debug!("Retrieved network interfaces: {network_interfaces:?}");
+
+    let inventory = Inventory {
+        interfaces: physical_interfaces(network_interfaces, Path::new(SYSFS_NET_DIR)),
+    };
+
+    print!("{}", inventory.to_yaml()?);
+    Ok(())
+}
+
+/// Physical NICs sorted by name. The NICs are listed once per address so they are deduplicated.
+fn physical_interfaces(
+    network_interfaces: Vec<NetworkInterface>,
+    sysfs_dir: &Path,
+) -> Vec<InventoryInterface> {
+    let nics: BTreeMap<String, String> = network_interfaces
+        .into_iter()
+        .filter_map(|nic| Some((nic.mac_addr?, nic.name)))
+        .filter(|(mac, name)| {
+            mac != EMPTY_MAC_ADDRESS && sysfs_dir.join(name).join("device").exists()
+        })
+        .map(|(mac, name)| (name, mac.to_lowercase()))
+        .collect();
+
+    nics.into_iter()
+        .map(|(name, mac_address)| InventoryInterface {
+            details: nic_details(&sysfs_dir.join(&name)),
+            name,
+            interface_type: "ethernet",
+            state: "up",
+            mac_address,
+        })
+        .collect()
+}
+
+fn nic_details(nic_dir: &Path) -> NicDetails {
+    let driver = fs::read_link(nic_dir.join("device").join("driver"))
+        .ok()
+        .and_then(|path| Some(path.file_name()?.to_string_lossy().into_owned()));
+
+    // Reading the speed fails or yields -1 if the link is down.
+    let speed = fs::read_to_string(nic_dir.join("speed"))
+        .ok()
+        .and_then(|speed| speed.trim().parse().ok());
+
+    NicDetails { driver, speed }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs;
+    use std::os::unix::fs::symlink;
+    use std::path::Path;
+
+    use network_interface::NetworkInterface;
+    use nmstate::NetworkState;
+
+    use crate::inventory::{physical_interfaces, Inventory, InventoryInterface, NicDetails};
+
+    #[test]
+    fn list_physical_interfaces() -> Result<(), anyhow::Error> {
+        let sysfs_dir = Path::new("_in_sysfs");
+        for name in ["eth1", "eth0"] {
+            fs::create_dir_all(sysfs_dir.join(name).join("device"))?;
+        }
+        fs::create_dir_all(sysfs_dir.join("bond0"))?;
+        fs::create_dir_all(sysfs_dir.join("drivers").join("ixgbe"))?;
+        symlink(
+            "../../drivers/ixgbe",
+            sysfs_dir.join("eth0").join("device").join("driver"),
+        )?;
+        fs::write(sysfs_dir.join("eth0").join("speed"), "10000\n")?;
+        fs::write(sysfs_dir.join("eth1").join("speed"), "-1\n")?;
+
+        let nic = |name: &str, mac: Option<&str>| NetworkInterface {
+            name: name.to_string(),
+            mac_addr: mac.map(str::to_string),
+            addr: vec![],
+            index: 0,
+        };
+        let interfaces = vec![
+            nic("lo", Some("00:00:00:00:00:00")),
+            nic("eth1", Some("00:11:22:33:44:AA")),
+            nic("eth0", Some("00:11:22:33:44:55")),
+            nic("eth0", Some("00:11:22:33:44:55")), // IPv6 address
+            nic("bond0", Some("00:11:22:33:44:66")),
+            nic("tun0", None),
+        ];
+
+        let inventory = Inventory {
+            interfaces: physical_interfaces(interfaces, sysfs_dir),
+        };
+        assert_eq!(
+            inventory.interfaces,
+            vec![
+                InventoryInterface {
+                    name: "eth0".to_string(),
+                    interface_type: "ethernet",
+                    state: "up",
+                    mac_address: "00:11:22:33:44:55".to_string(),
+                    details: NicDetails {
+                        driver: Some("ixgbe".to_string()),
+                        speed: Some(10000),
+                    },
+                },
+                InventoryInterface {
+                    name: "eth1".to_string(),
+                    interface_type: "ethernet",
+                    state: "up",
+                    mac_address: "00:11:22:33:44:aa".to_string(),
+                    details: NicDetails::default(),
+                },
+            ]
+        );
+
+        // The skeleton is a valid desired state.
+        let yaml = inventory.to_yaml()?;
+        assert!(yaml.contains("# driver: ixgbe, speed: 10000 Mb/s\n- name: eth0\n"));
+        assert!(yaml.contains("# driver: unknown, speed: unknown\n- name: eth1\n"));
+        assert!(yaml.contains("mac-address: 00:11:22:33:44:55"));
+        assert!(NetworkState::new_from_yaml(&yaml).is_ok());
+
+        // cleanup
+        fs::remove_dir_all(sysfs_dir)?;
+
+        Ok(())
+    }
+}
@@ -0,0 +1,318 @@
+use std::fmt;
+
+use anyhow::anyhow;
+
+/// Minimal representation of a NetworkManager keyfile (*.nmconnection) preserving
+/// the order of its sections and keys.
+#[derive(Debug, Default, PartialEq)]
+pub(crate) struct Keyfile {
+    sections: Vec<Section>,
+}
+
+#[derive(Debug, PartialEq)]
+struct Section {
+    name: String,
+    entries: Vec<(String, String)>,
+}
+
+impl Keyfile {
+    /// Parse the keyfile contents. Comments and blank lines are dropped.
+    pub(crate) fn parse(contents: &str) -> Result<Self, anyhow::Error> {
+        let mut keyfile = Keyfile::default();
+
+        for (index, line) in contents.lines().enumerate() {
+            let line = line.trim();
+
+            if line.is_empty() || line.starts_with('#') || line.starts_with(';') {
+                continue;
+            }
+
+            if let Some(name) = line.strip_prefix('[').and_then(|l| l.strip_suffix(']')) {
+                keyfile.sections.push(Section {
+                    name: name.to_owned(),
+                    entries: Vec::new(),
+                });
+                continue;
+            }
+
+            let (key, value) = line
+                .split_once('=')
+                .ok_or_else(|| anyhow!("Invalid keyfile line {}: '{line}'", index + 1))?;
+
+            let section = keyfile
+                .sections
+                .last_mut()
+                .ok_or_else(|| anyhow!("Keyfile line {} is outside of a section", index + 1))?;
+
+            section
+                .entries
+                .push((key.trim().to_owned(), value.trim().to_owned()));
+        }
+
+        Ok(keyfile)
+    }
+
+    pub(crate) fn get(&self, section: &str, key: &str) -> Option<&str> {
+        self.sections
+            .iter()
+            .find(|s| s.name == section)?
+            .entries
+            .iter()
+            .find(|(k, _)| k == key)
+            .map(|(_, v)| v.as_str())
+    }
+
+    /// Set the value of the key, creating the section and/or key if necessary.
+    pub(crate) fn set(&mut self, section: &str, key: &str, value: &str) {
+        let index = match self.sections.iter().position(|s| s.name == section) {
+            Some(index) => index,
+            None => {
+                self.sections.push(Section {
+                    name: section.to_owned(),
+                    entries: Vec::new(),
+                });
+                self.sections.len() - 1
+            }
+        };
+
+        let entries = &mut self.sections[index].entries;
+        match entries.iter_mut().find(|(k, _)| k == key) {
+            Some((_, v)) => *v = value.to_owned(),
+            None => entries.push((key.to_owned(), value.to_owned())),
+        }
+    }
+
+    /// Remove the key and return its value, if present.
+    pub(crate) fn remove(&mut self, section: &str, key: &str) -> Option<String> {
+        let entries = &mut self
+            .sections
+            .iter_mut()
+            .find(|s| s.name == section)?
+            .entries;
+        let index = entries.iter().position(|(k, _)| k == key)?;
+
+        Some(entries.remove(index).1)
+    }
+
+    /// Append a new section even if one with the same name already exists
+    /// e.g. multiple `[Route]` sections in systemd-networkd files.
+    pub(crate) fn add_section(&mut self, section: &str) {
+        self.sections.push(Section {
+            name: section.to_owned(),
+            entries: Vec::new(),
+        });
+    }
+
+    /// Append the key to the last section with the given name (creating it if necessary)
+    /// even if the key is already set e.g. multiple `Address=` keys in systemd-networkd files.
+    pub(crate) fn add(&mut self, section: &str, key: &str, value: &str) {
+        if !self.sections.iter().any(|s| s.name == section) {
+            self.add_section(section);
+        }
+
+        let section = self
+            .sections
+            .iter_mut()
+            .rev()
+            .find(|s| s.name == section)
+            .expect("section exists");
+        section.entries.push((key.to_owned(), value.to_owned()));
+    }
+
+    /// Overlay the sections and keys of the `other` keyfile while keeping the ones which are
+    /// only present in this keyfile. Returns the overridden values in the format
+    /// `Vec<(section.key, old_value, new_value)>`.
+    pub(crate) fn merge(&mut self, other: &Keyfile) -> Vec<(String, String, String)> {
+        let mut conflicts = Vec::new();
+
+        for section in &other.sections {
+            for (key, value) in &section.entries {
+                match self.get(&section.name, key) {
+                    Some(existing) if existing != value => conflicts.push((
+                        format!("{}.{key}", section.name),
+                        existing.to_owned(),
+                        value.to_owned(),
+                    )),
+                    _ => {}
+                }
+
+                self.set(&section.name, key, value);
+            }
+        }
+
+        conflicts
+    }
+
+    /// Replace the value of all keys which are set to `value` e.g. references to a connection UUID.
+    pub(crate) fn replace_value(&mut self, value: &str, replacement: &str) {
+        self.sections
+            .iter_mut()
+            .flat_map(|s| s.entries.iter_mut())
+            .filter(|(_, v)| v == value)
+            .for_each(|(_, v)| *v = replacement.to_owned());
+    }
+
+    /// Sort the sections and their keys alphabetically.
+    pub(crate) fn normalize(&mut self) {
+        self.sections.sort_by(|a, b| a.name.cmp(&b.name));
+        self.sections
+            .iter_mut()
+            .for_each(|s| s.entries.sort_by(|a, b| a.0.cmp(&b.0)));
+    }
+}
+
+/// Serialize in the same format as nmstate i.e. sections separated by a blank line.
+impl fmt::Display for Keyfile {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for (index, section) in self.sections.iter().enumerate() {
+            if index > 0 {
+                writeln!(f)?;
+            }
+
+            writeln!(f, "[{}]", section.name)?;
+            for (key, value) in &section.entries {
+                writeln!(f, "{key}={value}")?;
+            }
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs;
+
+    use crate::keyfile::Keyfile;
+
+    #[test]
+    fn parse_and_serialize_keyfile() -> Result<(), anyhow::Error> {
+        let contents = fs::read_to_string("testdata/generate/expected/eth0.nmconnection")?;
+
+        let keyfile = Keyfile::parse(&contents)?;
+        assert_eq!(keyfile.get("connection", "id"), Some("eth0"));
+        assert_eq!(keyfile.get("ipv4", "method"), Some("manual"));
+        assert_eq!(keyfile.get("ipv4", "missing"), None);
+        assert_eq!(keyfile.get("missing", "id"), None);
+
+        assert_eq!(keyfile.to_string(), contents);
+
+        Ok(())
+    }
+
+    #[test]
+    fn set_keyfile_values() -> Result<(), anyhow::Error> {
+        let mut keyfile = Keyfile::parse("[connection]\nid=eth0\n")?;
+
+        keyfile.set("connection", "id", "eth1");
+        keyfile.set("connection", "autoconnect-priority", "10");
+        keyfile.set("ipv4", "method", "auto");
+
+        assert_eq!(
+            keyfile.to_string(),
+            "[connection]\nid=eth1\nautoconnect-priority=10\n\n[ipv4]\nmethod=auto\n"
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn remove_keyfile_values() -> Result<(), anyhow::Error> {
+        let mut keyfile = Keyfile::parse("[connection]\nid=eth0\ninterface-name=eth0\n")?;
+
+        assert_eq!(
+            keyfile.remove("connection", "interface-name"),
+            Some("eth0".to_string())
+        );
+        assert_eq!(keyfile.remove("connection", "interface-name"), None);
+        assert_eq!(keyfile.remove("ethernet", "mac-address"), None);
+        assert_eq!(keyfile.to_string(), "[connection]\nid=eth0\n");
+
+        Ok(())
+    }
+
+    #[test]
+    fn replace_keyfile_values() -> Result<(), anyhow::Error> {
+        let mut keyfile =
+            Keyfile::parse("[connection]\nuuid=1234\nmaster=5678\n\n[vlan]\nparent=5678\n")?;
+
+        keyfile.replace_value("5678", "abcd");
+
+        assert_eq!(
+            keyfile.to_string(),
+            "[connection]\nuuid=1234\nmaster=abcd\n\n[vlan]\nparent=abcd\n"
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn merge_keyfiles() -> Result<(), anyhow::Error> {
+        let mut existing = Keyfile::parse(
+            "[connection]\nid=eth0\nautoconnect-priority=5\n\n[ethtool]\nring-rx=4096\n",
+        )?;
+        let generated = Keyfile::parse(
+            "[connection]\nid=eth0\nautoconnect-priority=10\n\n[ipv4]\nmethod=auto\n",
+        )?;
+
+        let conflicts = existing.merge(&generated);
+
+        assert_eq!(
+            existing.to_string(),
+            "[connection]\nid=eth0\nautoconnect-priority=10\n\n[ethtool]\nring-rx=4096\n\n[ipv4]\nmethod=auto\n"
+        );
+        assert_eq!(
+            conflicts,
+            vec![(
+                "connection.autoconnect-priority".to_string(),
+                "5".to_string(),
+                "10".to_string()
+            )]
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn add_repeated_sections_and_keys() {
+        let mut keyfile = Keyfile::default();
+
+        keyfile.add("Network", "Address", "192.168.1.10/24");
+        keyfile.add("Network", "Address", "2001:db8::10/64");
+        keyfile.add_section("Route");
+        keyfile.add("Route", "Destination", "10.0.0.0/8");
+        keyfile.add_section("Route");
+        keyfile.add("Route", "Destination", "10.1.0.0/16");
+
+        assert_eq!(
+            keyfile.to_string(),
+            "[Network]\nAddress=192.168.1.10/24\nAddress=2001:db8::10/64\n\n\
+            [Route]\nDestination=10.0.0.0/8\n\n[Route]\nDestination=10.1.0.0/16\n"
+        );
+    }
+
+    #[test]
+    fn normalize_keyfile() -> Result<(), anyhow::Error> {
+        let mut keyfile = Keyfile::parse(
+            "[ipv4]\nmethod=auto\ndhcp-timeout=10\n\n[connection]\nid=eth0\nautoconnect=true\n",
+        )?;
+
+        keyfile.normalize();
+
+        assert_eq!(
+            keyfile.to_string(),
+            "[connection]\nautoconnect=true\nid=eth0\n\n[ipv4]\ndhcp-timeout=10\nmethod=auto\n"
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn parse_keyfile_fails() {
+        let error = Keyfile::parse("id=eth0\n").unwrap_err();
+        assert_eq!(error.to_string(), "Keyfile line 1 is outside of a section");
+
+        let error = Keyfile::parse("[connection]\nid\n").unwrap_err();
+        assert_eq!(error.to_string(), "Invalid keyfile line 2: 'id'");
+    }
+}
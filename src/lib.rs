@@ -0,0 +1,1471 @@
+use std::io::{IsTerminal, Write};
+use std::path::Path;
+use std::sync::mpsc;
+use std::time::{Duration, Instant};
+use std::{fs, io, thread};
+
+use anyhow::{anyhow, Context};
+use indicatif::MultiProgress;
+use indicatif_log_bridge::LogWrapper;
+use log::{error, info};
+
+use apply_conf::{
+    apply, probe_source_dir, ApplyOptions, ApplySummary, KERNEL_CMDLINE_FILE,
+    STATIC_SYSTEM_CONNECTIONS_DIR, WELL_KNOWN_SOURCE_DIRS,
+};
+use clean_conf::clean;
+use convert::InputFormat;
+use diff_conf::diff;
+use error::ConfigError;
+use export::export;
+use fetch::SourceBundle;
+use generate_conf::{
+    generate, GenerateOptions, GenerateSummary, InterfaceBinding, MappingScope, NamingScheme,
+    OutputBackend,
+};
+use inventory::inventory;
+use lint::lint;
+use matcher::MatchStrategy;
+use types::host_mapping_schema;
+
+pub use apply_conf::match_host;
+pub use generate_conf::gen_connections;
+pub use types::Host;
+
+mod apply_conf;
+mod clean_conf;
+mod convert;
+mod diff_conf;
+mod error;
+mod export;
+mod fetch;
+mod generate_conf;
+mod inventory;
+mod keyfile;
+mod lint;
+mod matcher;
+mod networkd;
+mod remote;
+mod types;
+
+const APP_NAME: &str = "nmc";
+
+const SUB_CMD_GENERATE: &str = "generate";
+const SUB_CMD_APPLY: &str = "apply";
+const SUB_CMD_DIFF: &str = "diff";
+const SUB_CMD_CLEAN: &str = "clean";
+const SUB_CMD_INVENTORY: &str = "inventory";
+const SUB_CMD_EXPORT: &str = "export";
+const SUB_CMD_LINT: &str = "lint";
+const SUB_CMD_SCHEMA: &str = "schema";
+
+/// Exit codes allowing wrapping scripts to tell the failure reasons apart
+/// e.g. in order to retry on IO errors but not on invalid configurations.
+const EXIT_FAILURE: i32 = 1;
+const EXIT_INVALID_CONFIG: i32 = 2;
+const EXIT_NO_MATCHING_HOST: i32 = 3;
+const EXIT_IO_ERROR: i32 = 4;
+
+/// File storing a mapping between host identifier (usually hostname) and its preconfigured network interfaces.
+const HOST_MAPPING_FILE: &str = "host_config.yaml";
+
+/// File storing the mapping of a single host in its config dir.
+const PER_HOST_MAPPING_FILE: &str = "mapping.yml";
+
+/// File recording the provenance of the generated configurations.
+const METADATA_FILE: &str = "metadata.yml";
+
+/// Version of the nmstate library used to generate the configurations.
+const NMSTATE_VERSION: &str = env!("NMSTATE_VERSION");
+
+/// Format of the data printed to stdout.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub(crate) enum OutputFormat {
+    Text,
+    Json,
+}
+
+impl OutputFormat {
+    fn from_arg(matches: &clap::ArgMatches) -> Self {
+        match matches.get_one::<String>("FORMAT").map(String::as_str) {
+            Some("json") => OutputFormat::Json,
+            _ => OutputFormat::Text,
+        }
+    }
+}
+
+/// Entry point of the `nmc` command line tool.
+pub fn run() {
+    let app = clap::Command::new(APP_NAME)
+        .version(clap::crate_version!())
+        .about("Command line of NM configurator")
+        .subcommand_required(true)
+        .arg(
+            clap::Arg::new("LOG-LEVEL")
+                .long("log-level")
+                .global(true)
+                .value_parser(["error", "warn", "info", "debug", "trace"])
+                .help("Sets the log level, taking precedence over the RUST_LOG environment variable"),
+        )
+        .arg(
+            clap::Arg::new("LOG-FORMAT")
+                .long("log-format")
+                .global(true)
+                .value_parser(["text", "json"])
+                .default_value("text")
+                .help("Sets the format of the log records"),
+        )
+        .arg(
+            clap::Arg::new("QUIET")
+                .long("quiet")
+                .global(true)
+                .action(clap::ArgAction::SetTrue)
+                .conflicts_with("LOG-LEVEL")
+                .help("Only log errors"),
+        )
+        .arg(
+            clap::Arg::new("STRICT")
+                .long("strict")
+                .global(true)
+                .action(clap::ArgAction::SetTrue)
+                .help("Fail instead of logging warnings e.g. about unexpected entries in the config dirs"),
+        )
+        .subcommand(
+            clap::Command::new(SUB_CMD_GENERATE)
+                .about("Generate network configuration using nmstate")
+                .arg(
+                    clap::Arg::new("CONFIG-DIR")
+                        .required(true)
+                        .long("config-dir")
+                        .action(clap::ArgAction::Append)
+                        .help("Config dir containing network configurations for different hosts in YAML format \
+                        (can be specified multiple times, later dirs override the hosts of earlier ones)"),
+                )
+                .arg(
+                    clap::Arg::new("OUTPUT-DIR")
+                        .default_value("_out")
+                        .long("output-dir")
+                        .help("Destination dir storing the output configurations"),
+                )
+                .arg(
+                    clap::Arg::new("MAPPING-FILE")
+                        .long("mapping-file")
+                        .value_name("NAME")
+                        .default_value(HOST_MAPPING_FILE)
+                        .help("Name of the host mapping file stored in the output dir"),
+                )
+                .arg(
+                    clap::Arg::new("ONLY")
+                        .long("only")
+                        .help("Only generate config for the given host and update its entry in the existing host mapping"),
+                )
+                .arg(
+                    clap::Arg::new("RECURSIVE")
+                        .long("recursive")
+                        .action(clap::ArgAction::SetTrue)
+                        .help("Descend into the subdirectories of the config dir"),
+                )
+                .arg(
+                    clap::Arg::new("PREFIX-HOSTNAMES")
+                        .long("prefix-hostnames")
+                        .action(clap::ArgAction::SetTrue)
+                        .requires("RECURSIVE")
+                        .help("Prefix hostnames with the names of the subdirectories containing their config files e.g. 'rack1/node5.yaml' -> 'rack1-node5'"),
+                )
+                .arg(
+                    clap::Arg::new("AUTOCONNECT-PRIORITY")
+                        .long("autoconnect-priority")
+                        .action(clap::ArgAction::Append)
+                        .value_parser(parse_autoconnect_priority)
+                        .help("Set the autoconnect priority of the connections bound to the given interface \
+                        in the format <INTERFACE>=<PRIORITY> (can be specified multiple times)"),
+                )
+                .arg(
+                    clap::Arg::new("CONNECTION-ID-PREFIX")
+                        .long("connection-id-prefix")
+                        .help("Prefix the IDs of the generated connections e.g. 'nmc-' in order to distinguish them \
+                        from ones created by other tools"),
+                )
+                .arg(
+                    clap::Arg::new("NORMALIZE-KEYFILES")
+                        .long("normalize-keyfiles")
+                        .action(clap::ArgAction::SetTrue)
+                        .help("Sort the sections and keys of the generated *.nmconnection files alphabetically"),
+                )
+                .arg(
+                    clap::Arg::new("INPUT-FORMAT")
+                        .long("input-format")
+                        .value_parser(["nmstate", "cloud-init", "netplan"])
+                        .default_value("nmstate")
+                        .help("Format of the network configurations in the config dir"),
+                )
+                .arg(
+                    clap::Arg::new("INCLUDE-TYPES")
+                        .long("include-types")
+                        .action(clap::ArgAction::Append)
+                        .value_delimiter(',')
+                        .help("Only generate configurations for interfaces of the given types \
+                        e.g. ethernet,bond (can be specified multiple times)"),
+                )
+                .arg(
+                    clap::Arg::new("EXCLUDE-TYPES")
+                        .long("exclude-types")
+                        .action(clap::ArgAction::Append)
+                        .value_delimiter(',')
+                        .help("Skip interfaces of the given types e.g. ovs-bridge,ovs-interface \
+                        (can be specified multiple times)"),
+                )
+                .arg(
+                    clap::Arg::new("DETERMINISTIC-UUIDS")
+                        .long("deterministic-uuids")
+                        .action(clap::ArgAction::SetTrue)
+                        .help("Derive the connection UUIDs from the hostnames and connection IDs \
+                        for reproducible builds"),
+                )
+                .arg(
+                    clap::Arg::new("ANNOTATE")
+                        .long("annotate")
+                        .action(clap::ArgAction::SetTrue)
+                        .help("Prepend a comment recording the source file, nmc version and generation timestamp \
+                        to the generated *.nmconnection files"),
+                )
+                .arg(
+                    clap::Arg::new("OUTPUT-BACKEND")
+                        .long("output-backend")
+                        .value_parser(["networkmanager", "networkd"])
+                        .default_value("networkmanager")
+                        .conflicts_with_all(["AUTOCONNECT-PRIORITY", "NORMALIZE-KEYFILES", "DETERMINISTIC-UUIDS", "BIND", "CONNECTION-ID-PREFIX", "ANNOTATE"])
+                        .help("Generate NetworkManager keyfiles or systemd-networkd *.network and *.netdev files"),
+                )
+                .arg(
+                    clap::Arg::new("BIND")
+                        .long("bind")
+                        .value_parser(["name", "mac"])
+                        .default_value("name")
+                        .help("Bind the Ethernet connections to the NICs by interface name or by MAC address, \
+                        the latter being unaffected by renamed NICs"),
+                )
+                .arg(
+                    clap::Arg::new("NAMING-SCHEME")
+                        .long("naming-scheme")
+                        .value_parser(["kernel", "mac"])
+                        .default_value("kernel")
+                        .help("Naming scheme of the NICs on the target hosts. With 'mac', the systemd predictable names \
+                        derived from the MAC addresses (e.g. 'enx001122334455') are recorded in the host mapping \
+                        and the interfaces are renamed to them on apply"),
+                )
+                .arg(
+                    clap::Arg::new("SKIP-LOOPBACK-FILES")
+                        .long("skip-loopback-files")
+                        .action(clap::ArgAction::SetTrue)
+                        .help("Do not generate connection files for the loopback interface"),
+                )
+                .arg(
+                    clap::Arg::new("CHECK-MAC-ADDRESSES")
+                        .long("check-mac-addresses")
+                        .action(clap::ArgAction::SetTrue)
+                        .help("Reject multicast MAC addresses and warn about locally-administered ones \
+                        which are unlikely to match a physical NIC"),
+                )
+                .arg(
+                    clap::Arg::new("OUTPUT-OWNER")
+                        .long("output-owner")
+                        .value_parser(parse_output_owner)
+                        .conflicts_with("DUMP-CONF")
+                        .help("Change the ownership of the generated files and dirs in the format <UID>:<GID> \
+                        (requires root privileges)"),
+                )
+                .arg(
+                    clap::Arg::new("MAX-FILE-SIZE")
+                        .long("max-file-size")
+                        .value_name("SIZE")
+                        .value_parser(parse_file_size)
+                        .default_value("10MiB")
+                        .help("Reject desired state files larger than the given size in bytes \
+                        (optionally suffixed with KiB, MiB or GiB)"),
+                )
+                .arg(
+                    clap::Arg::new("DUMP-CONF")
+                        .long("dump-conf")
+                        .action(clap::ArgAction::SetTrue)
+                        .conflicts_with("PRINT-HOSTS")
+                        .help("Print the generated configurations to stdout without storing them"),
+                )
+                .arg(
+                    clap::Arg::new("DRY-RUN")
+                        .long("dry-run")
+                        .action(clap::ArgAction::SetTrue)
+                        .conflicts_with_all(["DUMP-CONF", "PRINT-HOSTS", "OUTPUT-OWNER"])
+                        .help("Validate the desired states and print the paths of the files which would be \
+                        written to stdout without writing anything"),
+                )
+                .arg(
+                    clap::Arg::new("VERIFY-NMCLI")
+                        .long("verify-nmcli")
+                        .action(clap::ArgAction::SetTrue)
+                        .help("Check that NetworkManager accepts the generated keyfiles by parsing them \
+                        with nmcli in offline mode, skipped if nmcli is not installed"),
+                )
+                .arg(
+                    clap::Arg::new("MAPPING-SCOPE")
+                        .long("mapping-scope")
+                        .value_parser(["aggregate", "per-host", "both"])
+                        .default_value("aggregate")
+                        .help("Store a single host mapping of all hosts in the output dir, \
+                        a mapping of each host in its own dir ('mapping.yml') or both"),
+                )
+                .arg(
+                    clap::Arg::new("CONTINUE-ON-ERROR")
+                        .long("continue-on-error")
+                        .action(clap::ArgAction::SetTrue)
+                        .help("Proceed with the remaining desired state files if some of them fail, \
+                        storing the successful configurations and exiting with an error listing the failed files"),
+                )
+                .arg(
+                    clap::Arg::new("PRINT-HOSTS")
+                        .long("print-hosts")
+                        .action(clap::ArgAction::SetTrue)
+                        .help("Print the hostnames of the generated configurations to stdout \
+                        (along with the interface and skipped entry counts in JSON format)"),
+                )
+                .arg(
+                    clap::Arg::new("FORMAT")
+                        .long("format")
+                        .value_parser(["text", "json"])
+                        .default_value("text")
+                        .help("Output format of the printed hostnames"),
+                ))
+        .subcommand(
+            clap::Command::new(SUB_CMD_APPLY)
+                .about("Apply network configurations to host")
+                .arg(
+                    clap::Arg::new("CONFIG-DIR")
+                        .long("config-dir")
+                        .help("Config dir containing host mapping ('host_config.yaml') \
+                         and subdirectories containing *.nmconnection files per host. Defaults to the dir given by \
+                         the 'nmc.source=' kernel command line parameter, the first existing one of \
+                         '/run/nmc' and '/etc/nmc' or 'config'")
+                )
+                .arg(
+                    clap::Arg::new("MAPPING-FILE")
+                        .long("mapping-file")
+                        .value_name("NAME")
+                        .default_value(HOST_MAPPING_FILE)
+                        .help("Name of the host mapping file read from the config dir")
+                )
+                .arg(
+                    clap::Arg::new("DESTINATION-DIR")
+                        .long("destination-dir")
+                        .env("NM_SYSTEM_CONNECTIONS_DIR")
+                        .default_value(STATIC_SYSTEM_CONNECTIONS_DIR)
+                        .help("Destination dir storing the *.nmconnection files")
+                )
+                .arg(
+                    clap::Arg::new("VERBOSE")
+                        .long("verbose")
+                        .action(clap::ArgAction::SetTrue)
+                        .help("Enables DEBUG log level")
+                )
+                .arg(
+                    clap::Arg::new("INTERFACE")
+                        .long("interface")
+                        .action(clap::ArgAction::Append)
+                        .help("Only apply the configuration of the given interface (can be specified multiple times)")
+                )
+                .arg(
+                    clap::Arg::new("PRINT-TARGET")
+                        .long("print-target")
+                        .action(clap::ArgAction::SetTrue)
+                        .help("Print where the *.nmconnection files would be stored without applying them")
+                )
+                .arg(
+                    clap::Arg::new("EXPLAIN")
+                        .long("explain")
+                        .action(clap::ArgAction::SetTrue)
+                        .conflicts_with_all(["PRINT-TARGET", "REPORT-FORMAT", "REPORT", "ALL-MATCHES"])
+                        .help("Print a JSON record of the local MAC addresses, the ones of each host, which of them \
+                        matched and the identified host (if any) without applying anything")
+                )
+                .arg(
+                    clap::Arg::new("REPORT-FORMAT")
+                        .long("report-format")
+                        .value_parser(["text", "json"])
+                        .conflicts_with("PRINT-TARGET")
+                        .help("Emit a report of the identified host, matched MAC addresses, applied files and renamed \
+                        interfaces at the end of the run, as text to stderr or as JSON to stdout")
+                )
+                .arg(
+                    clap::Arg::new("REPORT")
+                        .long("report")
+                        .value_name("PATH")
+                        .conflicts_with("PRINT-TARGET")
+                        .help("Write the JSON report to the given file instead of stdout")
+                )
+                .arg(
+                    clap::Arg::new("REMOTE")
+                        .long("remote")
+                        .help("Apply the configuration to a remote host over SSH (e.g. 'user@host') \
+                        authenticating via the keys available to the SSH client (e.g. loaded in ssh-agent)")
+                )
+                .arg(
+                    clap::Arg::new("REMOTE-RELOAD")
+                        .long("remote-reload")
+                        .action(clap::ArgAction::SetTrue)
+                        .requires("REMOTE")
+                        .help("Reload the NetworkManager connections on the remote host after applying the configuration")
+                )
+                .arg(
+                    clap::Arg::new("MATCH")
+                        .long("match")
+                        .value_parser(["mac", "hostname", "serial"])
+                        .action(clap::ArgAction::Append)
+                        .default_value("mac")
+                        .help("Strategy identifying the host by the MAC addresses of its NICs, by its current hostname \
+                        or by its DMI product serial \
+                        (can be specified multiple times, the strategies are tried in the given order)")
+                )
+                .arg(
+                    clap::Arg::new("RETRIES")
+                        .long("retries")
+                        .value_parser(clap::value_parser!(u32))
+                        .default_value("0")
+                        .help("Number of additional attempts to identify the host if none of the NICs match")
+                )
+                .arg(
+                    clap::Arg::new("RETRY-INTERVAL")
+                        .long("retry-interval")
+                        .value_parser(clap::value_parser!(u64))
+                        .default_value("1")
+                        .help("Seconds to wait before the first retry, doubled after every subsequent attempt")
+                )
+                .arg(
+                    clap::Arg::new("TIMEOUT")
+                        .long("timeout")
+                        .value_parser(clap::value_parser!(u64).range(1..))
+                        .help("Abort the whole apply operation (including retries) after the given number of seconds")
+                )
+                .arg(
+                    clap::Arg::new("REQUIRE-INTERFACES")
+                        .long("require-interfaces")
+                        .value_parser(clap::value_parser!(usize))
+                        .help("Refuse to apply unless at least the given number of the identified host's \
+                        physical interfaces are present on the system")
+                )
+                .arg(
+                    clap::Arg::new("EXACT-MATCH")
+                        .long("exact-match")
+                        .action(clap::ArgAction::SetTrue)
+                        .help("Refuse to apply unless the MAC addresses of all physical interfaces of the \
+                        identified host are present on the system")
+                )
+                .arg(
+                    clap::Arg::new("MIN-MATCH-RATIO")
+                        .long("min-match-ratio")
+                        .value_parser(parse_match_ratio)
+                        .help("Only match hosts by their MAC addresses if at least the given share (0 < RATIO <= 1) \
+                        of their physical interfaces is present on the system e.g. 0.5")
+                )
+                .arg(
+                    clap::Arg::new("DELETE-MISSING")
+                        .long("delete-missing")
+                        .action(clap::ArgAction::SetTrue)
+                        .conflicts_with("REMOTE")
+                        .help("Remove the *.nmconnection files previously stored by NMC (listed in '.nmc-manifest' \
+                        in the destination dir or annotated by 'nmc generate --annotate') which are no longer part of the config")
+                )
+                .arg(
+                    clap::Arg::new("MERGE")
+                        .long("merge")
+                        .action(clap::ArgAction::SetTrue)
+                        .conflicts_with("REMOTE")
+                        .help("Merge the generated sections and keys into the existing *.nmconnection files \
+                        preserving any other keys instead of overwriting the files")
+                )
+                .arg(
+                    clap::Arg::new("SOURCE-URL")
+                        .long("source-url")
+                        .help("Download the config dir as a gzip-compressed tarball from the given URL \
+                        instead of reading it from --config-dir")
+                )
+                .arg(
+                    clap::Arg::new("SOURCE-AUTH")
+                        .long("source-auth")
+                        .env("NMC_SOURCE_AUTH")
+                        .hide_env_values(true)
+                        .requires("SOURCE-URL")
+                        .help("Credentials for downloading the config bundle using basic authentication \
+                        in the format <USER>:<PASSWORD>")
+                )
+                .arg(
+                    clap::Arg::new("SOURCE-SHA256")
+                        .long("source-sha256")
+                        .requires("SOURCE-URL")
+                        .help("Expected SHA-256 checksum of the downloaded config bundle")
+                )
+                .arg(
+                    clap::Arg::new("PRESERVE-SYMLINKS")
+                        .long("preserve-symlinks")
+                        .action(clap::ArgAction::SetTrue)
+                        .conflicts_with_all(["MERGE", "REMOTE"])
+                        .help("Link to the targets of symlinked connection files instead of copying them")
+                )
+                .arg(
+                    clap::Arg::new("FORCE")
+                        .long("force")
+                        .action(clap::ArgAction::SetTrue)
+                        .help("Write the connection files even if they already have the expected contents")
+                )
+                .arg(
+                    clap::Arg::new("VERIFY")
+                        .long("verify")
+                        .action(clap::ArgAction::SetTrue)
+                        .conflicts_with("REMOTE")
+                        .help("Re-read the stored connection files and fail unless they match the applied config \
+                        and are only accessible by their owner")
+                )
+                .arg(
+                    clap::Arg::new("NO-RENAME")
+                        .long("no-rename")
+                        .action(clap::ArgAction::SetTrue)
+                        .conflicts_with("RENAME-MAP")
+                        .help("Copy the connection files verbatim keeping the preconfigured interface names \
+                        instead of adjusting them to the local ones")
+                )
+                .arg(
+                    clap::Arg::new("RENAME-MAP")
+                        .long("rename-map")
+                        .value_name("FILE")
+                        .help("YAML file of explicit 'MAC address: interface name' overrides taking precedence over the names of the local NICs")
+                )
+                .arg(
+                    clap::Arg::new("SIMULATE-INTERFACES")
+                        .long("simulate-interfaces")
+                        .value_name("FILE")
+                        .conflicts_with("REMOTE")
+                        .help("YAML file of recorded NICs (name, mac_address and optional altnames) used instead of \
+                        the local ones, storing only the connection files and leaving the rest of the host untouched")
+                )
+                .arg(
+                    clap::Arg::new("ALL-MATCHES")
+                        .long("all-matches")
+                        .action(clap::ArgAction::SetTrue)
+                        .conflicts_with_all(["REMOTE", "MATCH"])
+                        .help("Apply the configs of all hosts matching the NICs by their MAC addresses instead of only \
+                        the best one, storing the ones of each host in a <DESTINATION-DIR>/<HOSTNAME> dir \
+                        without setting the hostname")
+                )
+                .arg(
+                    clap::Arg::new("STAGED")
+                        .long("staged")
+                        .action(clap::ArgAction::SetTrue)
+                        .conflicts_with("REMOTE")
+                        .help("Write the connection files to a staging dir and verify them before atomically \
+                        moving them to the destination dir")
+                )
+                .arg(
+                    clap::Arg::new("KEEP-FILENAMES")
+                        .long("keep-filenames")
+                        .action(clap::ArgAction::SetTrue)
+                        .help("Keep the preconfigured interface names as the names of the connection files \
+                        while still adjusting their contents to the local interface names")
+                )
+                .arg(
+                    clap::Arg::new("PARENTS-FIRST")
+                        .long("parents-first")
+                        .action(clap::ArgAction::SetTrue)
+                        .help("Write the connection files of the ports (e.g. Ethernet) before the ones of their controllers (e.g. bonds and bridges)")
+                )
+                .arg(
+                    clap::Arg::new("SECRETS-FILE")
+                        .long("secrets-file")
+                        .value_name("FILE")
+                        .help("YAML file of secrets substituted for the ${SECRET:<NAME>} placeholders \
+                        of the connection files (missing ones are read from NMC_SECRET_<NAME> environment variables)")
+                )
+                .arg(
+                    clap::Arg::new("VALIDATE-MAPPING")
+                        .long("validate-mapping")
+                        .action(clap::ArgAction::SetTrue)
+                        .help("Check the host mapping for blank or duplicate hostnames and missing interfaces before applying")
+                )
+                .arg(
+                    clap::Arg::new("CHECK-MAC-ADDRESSES")
+                        .long("check-mac-addresses")
+                        .action(clap::ArgAction::SetTrue)
+                        .help("Reject multicast MAC addresses and warn about locally-administered ones in the host mapping \
+                        which are unlikely to match a physical NIC")
+                )
+        )
+        .subcommand(
+            clap::Command::new(SUB_CMD_DIFF)
+                .about("Show the differences between the network configurations to be applied and the ones on the host")
+                .arg(
+                    clap::Arg::new("CONFIG-DIR")
+                        .long("config-dir")
+                        .default_value("config")
+                        .help("Config dir containing host mapping ('host_config.yaml') \
+                         and subdirectories containing *.nmconnection files per host")
+                )
+                .arg(
+                    clap::Arg::new("DESTINATION-DIR")
+                        .long("destination-dir")
+                        .env("NM_SYSTEM_CONNECTIONS_DIR")
+                        .default_value(STATIC_SYSTEM_CONNECTIONS_DIR)
+                        .help("Dir containing the currently stored *.nmconnection files")
+                )
+                .arg(
+                    clap::Arg::new("FORMAT")
+                        .long("format")
+                        .value_parser(["text", "json"])
+                        .default_value("text")
+                        .help("Output format of the differences")
+                )
+                .arg(
+                    clap::Arg::new("VERBOSE")
+                        .long("verbose")
+                        .action(clap::ArgAction::SetTrue)
+                        .help("Enables DEBUG log level")
+                )
+        )
+        .subcommand(
+            clap::Command::new(SUB_CMD_CLEAN)
+                .about("Remove the network configurations previously applied to the host")
+                .arg(
+                    clap::Arg::new("CONFIG-DIR")
+                        .long("config-dir")
+                        .default_value("config")
+                        .help("Config dir containing host mapping ('host_config.yaml') \
+                         and subdirectories containing *.nmconnection files per host")
+                )
+                .arg(
+                    clap::Arg::new("DESTINATION-DIR")
+                        .long("destination-dir")
+                        .env("NM_SYSTEM_CONNECTIONS_DIR")
+                        .default_value(STATIC_SYSTEM_CONNECTIONS_DIR)
+                        .help("Dir containing the applied *.nmconnection files")
+                )
+                .arg(
+                    clap::Arg::new("DRY-RUN")
+                        .long("dry-run")
+                        .action(clap::ArgAction::SetTrue)
+                        .help("Print the *.nmconnection files which would be removed without removing them")
+                )
+                .arg(
+                    clap::Arg::new("RELOAD")
+                        .long("reload")
+                        .action(clap::ArgAction::SetTrue)
+                        .conflicts_with("DRY-RUN")
+                        .help("Reload the NetworkManager connections after removing the files")
+                )
+                .arg(
+                    clap::Arg::new("VERBOSE")
+                        .long("verbose")
+                        .action(clap::ArgAction::SetTrue)
+                        .help("Enables DEBUG log level")
+                )
+        )
+        .subcommand(
+            clap::Command::new(SUB_CMD_INVENTORY)
+                .about("Print a skeleton nmstate desired state for the physical NICs of the host")
+                .arg(
+                    clap::Arg::new("VERBOSE")
+                        .long("verbose")
+                        .action(clap::ArgAction::SetTrue)
+                        .help("Enables DEBUG log level")
+                )
+        )
+        .subcommand(
+            clap::Command::new(SUB_CMD_EXPORT)
+                .about("Print the interfaces of all hosts in the host mapping as CSV \
+                (hostname, logical_name, mac_address, interface_type)")
+                .arg(
+                    clap::Arg::new("CONFIG-DIR")
+                        .long("config-dir")
+                        .default_value("config")
+                        .help("Config dir containing the host mapping ('host_config.yaml')")
+                )
+                .arg(
+                    clap::Arg::new("MAPPING-FILE")
+                        .long("mapping-file")
+                        .value_name("NAME")
+                        .default_value(HOST_MAPPING_FILE)
+                        .help("Name of the host mapping file in the config dir")
+                )
+                .arg(
+                    clap::Arg::new("VERBOSE")
+                        .long("verbose")
+                        .action(clap::ArgAction::SetTrue)
+                        .help("Enables DEBUG log level")
+                )
+        )
+        .subcommand(
+            clap::Command::new(SUB_CMD_LINT)
+                .about("Check the desired states in the config dir reporting the findings with their rule IDs \
+                and severities, optionally tuned in '.nmc-lint.yml'")
+                .arg(
+                    clap::Arg::new("CONFIG-DIR")
+                        .long("config-dir")
+                        .default_value("config")
+                        .help("Config dir containing network configurations for different hosts in YAML format")
+                )
+                .arg(
+                    clap::Arg::new("RECURSIVE")
+                        .long("recursive")
+                        .action(clap::ArgAction::SetTrue)
+                        .help("Descend into the subdirectories of the config dir")
+                )
+                .arg(
+                    clap::Arg::new("INPUT-FORMAT")
+                        .long("input-format")
+                        .value_parser(["nmstate", "cloud-init", "netplan"])
+                        .default_value("nmstate")
+                        .help("Format of the network configurations in the config dir")
+                )
+                .arg(
+                    clap::Arg::new("FORMAT")
+                        .long("format")
+                        .value_parser(["text", "json"])
+                        .default_value("text")
+                        .help("Output format of the findings")
+                )
+                .arg(
+                    clap::Arg::new("VERBOSE")
+                        .long("verbose")
+                        .action(clap::ArgAction::SetTrue)
+                        .help("Enables DEBUG log level")
+                )
+        )
+        .subcommand(
+            clap::Command::new(SUB_CMD_SCHEMA)
+                .about("Print the JSON Schema of the host mapping file ('host_config.yaml')")
+        );
+
+    let matches = app.get_matches();
+
+    match matches.subcommand() {
+        Some((SUB_CMD_GENERATE, cmd)) => {
+            let config_dirs: Vec<&str> = cmd
+                .get_many::<String>("CONFIG-DIR")
+                .expect("--config-dir is required")
+                .map(String::as_str)
+                .collect();
+            let output_dir = cmd
+                .get_one::<String>("OUTPUT-DIR")
+                .expect("--output-dir is required");
+            let options = GenerateOptions {
+                only_host: cmd.get_one::<String>("ONLY").cloned(),
+                recursive: cmd.get_flag("RECURSIVE"),
+                prefix_hostnames: cmd.get_flag("PREFIX-HOSTNAMES"),
+                dump_conf: cmd.get_flag("DUMP-CONF"),
+                dry_run: cmd.get_flag("DRY-RUN"),
+                autoconnect_priorities: cmd
+                    .get_many::<(String, i32)>("AUTOCONNECT-PRIORITY")
+                    .unwrap_or_default()
+                    .cloned()
+                    .collect(),
+                normalize_keyfiles: cmd.get_flag("NORMALIZE-KEYFILES"),
+                connection_id_prefix: cmd.get_one::<String>("CONNECTION-ID-PREFIX").cloned(),
+                deterministic_uuids: cmd.get_flag("DETERMINISTIC-UUIDS"),
+                annotate: cmd.get_flag("ANNOTATE"),
+                include_types: cmd
+                    .get_many::<String>("INCLUDE-TYPES")
+                    .unwrap_or_default()
+                    .cloned()
+                    .collect(),
+                exclude_types: cmd
+                    .get_many::<String>("EXCLUDE-TYPES")
+                    .unwrap_or_default()
+                    .cloned()
+                    .collect(),
+                input_format: InputFormat::from_arg(
+                    cmd.get_one::<String>("INPUT-FORMAT")
+                        .expect("--input-format has a default"),
+                ),
+                output_backend: OutputBackend::from_arg(
+                    cmd.get_one::<String>("OUTPUT-BACKEND")
+                        .expect("--output-backend has a default"),
+                ),
+                strict: cmd.get_flag("STRICT"),
+                output_owner: cmd.get_one::<(u32, u32)>("OUTPUT-OWNER").copied(),
+                verify_nmcli: cmd.get_flag("VERIFY-NMCLI"),
+                skip_loopback_files: cmd.get_flag("SKIP-LOOPBACK-FILES"),
+                check_mac_addresses: cmd.get_flag("CHECK-MAC-ADDRESSES"),
+                mapping_file: cmd.get_one::<String>("MAPPING-FILE").cloned(),
+                max_file_size: cmd.get_one::<u64>("MAX-FILE-SIZE").copied(),
+                continue_on_error: cmd.get_flag("CONTINUE-ON-ERROR"),
+                mapping_scope: MappingScope::from_arg(
+                    cmd.get_one::<String>("MAPPING-SCOPE")
+                        .expect("--mapping-scope has a default"),
+                ),
+                bind: InterfaceBinding::from_arg(
+                    cmd.get_one::<String>("BIND").expect("--bind has a default"),
+                ),
+                naming_scheme: NamingScheme::from_arg(
+                    cmd.get_one::<String>("NAMING-SCHEME")
+                        .expect("--naming-scheme has a default"),
+                ),
+                // Only draw the progress bar in interactive sessions
+                // which don't print any data to stdout.
+                progress: (!cmd.get_flag("QUIET")
+                    && !cmd.get_flag("DUMP-CONF")
+                    && !cmd.get_flag("DRY-RUN")
+                    && io::stderr().is_terminal())
+                .then(MultiProgress::new),
+            };
+            let print_hosts = cmd.get_flag("PRINT-HOSTS");
+            let format = OutputFormat::from_arg(cmd);
+
+            setup_logger(cmd, options.progress.as_ref());
+
+            match generate(&config_dirs, output_dir, &options) {
+                Ok(summary) if !summary.failed.is_empty() => {
+                    if print_hosts {
+                        print_summary(&summary, format);
+                    }
+
+                    let paths: Vec<String> = summary
+                        .failed
+                        .iter()
+                        .map(|failed| failed.path.display().to_string())
+                        .collect();
+                    error!(
+                        "Generating config failed for {} of {} files: {}",
+                        paths.len(),
+                        paths.len() + summary.hosts.len(),
+                        paths.join(", ")
+                    );
+                    std::process::exit(EXIT_INVALID_CONFIG)
+                }
+                Ok(_) if options.dry_run => info!("Successfully validated network config"),
+                Ok(summary) => {
+                    info!("Successfully generated and stored network config");
+
+                    if print_hosts {
+                        print_summary(&summary, format);
+                    }
+                }
+                Err(err) => {
+                    error!("Generating config failed: {err:#}");
+                    std::process::exit(exit_code(&err))
+                }
+            }
+        }
+        Some((SUB_CMD_APPLY, cmd)) => {
+            let destination_dir = cmd
+                .get_one::<String>("DESTINATION-DIR")
+                .expect("--destination-dir is required");
+
+            let options = ApplyOptions {
+                interface_filter: cmd
+                    .get_many::<String>("INTERFACE")
+                    .unwrap_or_default()
+                    .cloned()
+                    .collect(),
+                print_target: cmd.get_flag("PRINT-TARGET"),
+                explain: cmd.get_flag("EXPLAIN"),
+                remote: cmd.get_one::<String>("REMOTE").cloned(),
+                remote_reload: cmd.get_flag("REMOTE-RELOAD"),
+                match_strategies: cmd
+                    .get_many::<String>("MATCH")
+                    .unwrap_or_default()
+                    .map(|strategy| MatchStrategy::from_arg(strategy))
+                    .collect(),
+                retries: *cmd
+                    .get_one::<u32>("RETRIES")
+                    .expect("--retries has a default"),
+                retry_interval: Duration::from_secs(
+                    *cmd.get_one::<u64>("RETRY-INTERVAL")
+                        .expect("--retry-interval has a default"),
+                ),
+                validate_mapping: cmd.get_flag("VALIDATE-MAPPING"),
+                check_mac_addresses: cmd.get_flag("CHECK-MAC-ADDRESSES"),
+                merge: cmd.get_flag("MERGE"),
+                delete_missing: cmd.get_flag("DELETE-MISSING"),
+                required_interfaces: cmd.get_one::<usize>("REQUIRE-INTERFACES").copied(),
+                exact_match: cmd.get_flag("EXACT-MATCH"),
+                min_match_ratio: cmd.get_one::<f64>("MIN-MATCH-RATIO").copied(),
+                strict: cmd.get_flag("STRICT"),
+                source: cmd.get_one::<String>("SOURCE-URL").map(|url| SourceBundle {
+                    url: url.to_owned(),
+                    auth: cmd.get_one::<String>("SOURCE-AUTH").cloned(),
+                    sha256: cmd.get_one::<String>("SOURCE-SHA256").cloned(),
+                }),
+                rename_map: cmd.get_one::<String>("RENAME-MAP").cloned(),
+                no_rename: cmd.get_flag("NO-RENAME"),
+                preserve_symlinks: cmd.get_flag("PRESERVE-SYMLINKS"),
+                verify: cmd.get_flag("VERIFY"),
+                force: cmd.get_flag("FORCE"),
+                mapping_file: cmd.get_one::<String>("MAPPING-FILE").cloned(),
+                secrets_file: cmd.get_one::<String>("SECRETS-FILE").cloned(),
+                parents_first: cmd.get_flag("PARENTS-FIRST"),
+                staged: cmd.get_flag("STAGED"),
+                keep_filenames: cmd.get_flag("KEEP-FILENAMES"),
+                simulate_interfaces: cmd.get_one::<String>("SIMULATE-INTERFACES").cloned(),
+                all_matches: cmd.get_flag("ALL-MATCHES"),
+                deadline: cmd
+                    .get_one::<u64>("TIMEOUT")
+                    .map(|&timeout| Instant::now() + Duration::from_secs(timeout)),
+            };
+            let read_only = options.print_target || options.explain;
+            let all_matches = options.all_matches;
+            let report_format = match cmd.get_one::<String>("REPORT-FORMAT").map(String::as_str) {
+                Some("text") => Some(OutputFormat::Text),
+                // Writing the report to a file implies JSON.
+                Some("json") => Some(OutputFormat::Json),
+                _ => cmd.contains_id("REPORT").then_some(OutputFormat::Json),
+            };
+            let report_path = cmd.get_one::<String>("REPORT").cloned();
+
+            setup_logger(cmd, None);
+
+            let config_dir = match cmd.get_one::<String>("CONFIG-DIR") {
+                Some(config_dir) => config_dir.to_owned(),
+                None => probe_source_dir(Path::new(KERNEL_CMDLINE_FILE), &WELL_KNOWN_SOURCE_DIRS),
+            };
+
+            let result = match cmd.get_one::<u64>("TIMEOUT") {
+                Some(&timeout) => {
+                    let destination_dir = destination_dir.to_owned();
+
+                    run_with_timeout(Duration::from_secs(timeout), move || {
+                        apply(&config_dir, &destination_dir, &options)
+                    })
+                }
+                None => apply(&config_dir, destination_dir, &options),
+            };
+
+            match result {
+                Ok(..) if read_only => {}
+                Ok(summaries) => {
+                    info!("Successfully applied config");
+
+                    if let Some(format) = report_format {
+                        if let Err(err) = report_apply_summary(
+                            &summaries,
+                            all_matches,
+                            format,
+                            report_path.as_deref(),
+                        ) {
+                            error!("Writing report failed: {err:#}");
+                            std::process::exit(exit_code(&err))
+                        }
+                    }
+                }
+                Err(err) => {
+                    error!("Applying config failed: {err:#}");
+                    std::process::exit(exit_code(&err))
+                }
+            }
+        }
+        Some((SUB_CMD_DIFF, cmd)) => {
+            let config_dir = cmd
+                .get_one::<String>("CONFIG-DIR")
+                .expect("--config-dir is required");
+            let destination_dir = cmd
+                .get_one::<String>("DESTINATION-DIR")
+                .expect("--destination-dir is required");
+            let format = OutputFormat::from_arg(cmd);
+
+            setup_logger(cmd, None);
+
+            if let Err(err) = diff(config_dir, destination_dir, format) {
+                error!("Comparing config failed: {err:#}");
+                std::process::exit(exit_code(&err))
+            }
+        }
+        Some((SUB_CMD_CLEAN, cmd)) => {
+            let config_dir = cmd
+                .get_one::<String>("CONFIG-DIR")
+                .expect("--config-dir is required");
+            let destination_dir = cmd
+                .get_one::<String>("DESTINATION-DIR")
+                .expect("--destination-dir is required");
+            let dry_run = cmd.get_flag("DRY-RUN");
+            let reload = cmd.get_flag("RELOAD");
+
+            setup_logger(cmd, None);
+
+            match clean(config_dir, destination_dir, dry_run, reload) {
+                Ok(()) if !dry_run => info!("Successfully removed applied config"),
+                Ok(()) => {}
+                Err(err) => {
+                    error!("Removing config failed: {err:#}");
+                    std::process::exit(exit_code(&err))
+                }
+            }
+        }
+        Some((SUB_CMD_INVENTORY, cmd)) => {
+            setup_logger(cmd, None);
+
+            if let Err(err) = inventory() {
+                error!("Listing network interfaces failed: {err:#}");
+                std::process::exit(exit_code(&err))
+            }
+        }
+        Some((SUB_CMD_EXPORT, cmd)) => {
+            let config_dir = cmd
+                .get_one::<String>("CONFIG-DIR")
+                .expect("--config-dir is required");
+            let mapping_file = cmd
+                .get_one::<String>("MAPPING-FILE")
+                .expect("--mapping-file has a default");
+
+            setup_logger(cmd, None);
+
+            if let Err(err) = export(config_dir, mapping_file) {
+                error!("Exporting interfaces failed: {err:#}");
+                std::process::exit(exit_code(&err))
+            }
+        }
+        Some((SUB_CMD_LINT, cmd)) => {
+            let config_dir = cmd
+                .get_one::<String>("CONFIG-DIR")
+                .expect("--config-dir is required");
+            let options = GenerateOptions {
+                recursive: cmd.get_flag("RECURSIVE"),
+                input_format: InputFormat::from_arg(
+                    cmd.get_one::<String>("INPUT-FORMAT")
+                        .expect("--input-format has a default"),
+                ),
+                ..Default::default()
+            };
+            let format = OutputFormat::from_arg(cmd);
+
+            setup_logger(cmd, None);
+
+            if let Err(err) = lint(config_dir, &options, format) {
+                error!("Linting config failed: {err:#}");
+                std::process::exit(exit_code(&err))
+            }
+        }
+        Some((SUB_CMD_SCHEMA, _)) => {
+            println!(
+                "{}",
+                serde_json::to_string_pretty(&host_mapping_schema()).expect("serializing schema")
+            );
+        }
+        _ => unreachable!("Unrecognized subcommand"),
+    }
+}
+
+fn parse_autoconnect_priority(value: &str) -> Result<(String, i32), String> {
+    let (interface, priority) = value
+        .split_once('=')
+        .ok_or_else(|| format!("expected <INTERFACE>=<PRIORITY>, got '{value}'"))?;
+
+    let priority = priority
+        .parse()
+        .map_err(|_| format!("invalid priority '{priority}'"))?;
+
+    Ok((interface.to_owned(), priority))
+}
+
+fn parse_output_owner(value: &str) -> Result<(u32, u32), String> {
+    let (uid, gid) = value
+        .split_once(':')
+        .ok_or_else(|| format!("expected <UID>:<GID>, got '{value}'"))?;
+
+    let uid = uid.parse().map_err(|_| format!("invalid uid '{uid}'"))?;
+    let gid = gid.parse().map_err(|_| format!("invalid gid '{gid}'"))?;
+
+    Ok((uid, gid))
+}
+
+fn parse_match_ratio(value: &str) -> Result<f64, String> {
+    let ratio: f64 = value
+        .parse()
+        .map_err(|_| format!("invalid ratio '{value}'"))?;
+
+    if ratio <= 0.0 || ratio > 1.0 {
+        return Err(format!("ratio must be within (0, 1], got '{value}'"));
+    }
+
+    Ok(ratio)
+}
+
+fn parse_file_size(value: &str) -> Result<u64, String> {
+    let (number, unit) = match value.find(|c: char| !c.is_ascii_digit()) {
+        Some(index) => value.split_at(index),
+        None => (value, ""),
+    };
+
+    let multiplier: u64 = match unit.trim() {
+        "" => 1,
+        "KiB" => 1 << 10,
+        "MiB" => 1 << 20,
+        "GiB" => 1 << 30,
+        unit => return Err(format!("invalid unit '{unit}', expected KiB, MiB or GiB")),
+    };
+
+    number
+        .parse::<u64>()
+        .ok()
+        .and_then(|number| number.checked_mul(multiplier))
+        .ok_or_else(|| format!("invalid size '{value}'"))
+}
+
+/// Run the operation on a separate thread and give up waiting for it after the `timeout`.
+/// The timeout is reported as an IO error since it is usually caused by a stalled
+/// system call or remote connection and worth retrying.
+///
+/// The operation is not interrupted and keeps running until the process exits, so the callers
+/// are expected to exit right away. Operations making changes (e.g. `apply`) additionally check
+/// the deadline between their steps in order not to start any of them past the timeout.
+fn run_with_timeout<T, F>(timeout: Duration, operation: F) -> Result<T, anyhow::Error>
+where
+    T: Send + 'static,
+    F: FnOnce() -> Result<T, anyhow::Error> + Send + 'static,
+{
+    let (sender, receiver) = mpsc::channel();
+
+    thread::spawn(move || {
+        // The receiver is gone if the operation has already timed out.
+        let _ = sender.send(operation());
+    });
+
+    match receiver.recv_timeout(timeout) {
+        Ok(result) => result,
+        Err(mpsc::RecvTimeoutError::Timeout) => Err(io::Error::new(
+            io::ErrorKind::TimedOut,
+            format!("Timed out after {timeout:?}"),
+        )
+        .into()),
+        Err(mpsc::RecvTimeoutError::Disconnected) => {
+            Err(anyhow!("Operation terminated unexpectedly"))
+        }
+    }
+}
+
+fn exit_code(err: &anyhow::Error) -> i32 {
+    if let Some(err) = err.downcast_ref::<ConfigError>() {
+        return match err {
+            ConfigError::InvalidMapping(_) | ConfigError::InvalidHostConfig(_) => {
+                EXIT_INVALID_CONFIG
+            }
+            ConfigError::NoMatchingHost | ConfigError::MissingInterfaces(_) => {
+                EXIT_NO_MATCHING_HOST
+            }
+        };
+    }
+
+    if err.downcast_ref::<io::Error>().is_some() {
+        return EXIT_IO_ERROR;
+    }
+
+    EXIT_FAILURE
+}
+
+fn print_summary(summary: &GenerateSummary, format: OutputFormat) {
+    match format {
+        OutputFormat::Text => summary.hosts.iter().for_each(|h| println!("{h}")),
+        OutputFormat::Json => println!(
+            "{}",
+            serde_json::to_string(summary).expect("serializing summary")
+        ),
+    }
+}
+
+/// Emit the summary of an apply run in text format to stderr or in JSON format to stdout
+/// (or to the file at the given path). The summaries of `--all-matches` are reported as a JSON array.
+fn report_apply_summary(
+    summaries: &[ApplySummary],
+    all_matches: bool,
+    format: OutputFormat,
+    path: Option<&str>,
+) -> Result<(), anyhow::Error> {
+    let report = match (format, summaries) {
+        (OutputFormat::Text, _) => {
+            summaries
+                .iter()
+                .for_each(|summary| eprint!("{}", text_report(summary)));
+            return Ok(());
+        }
+        (OutputFormat::Json, [summary]) if !all_matches => serde_json::to_string(summary)?,
+        (OutputFormat::Json, _) => serde_json::to_string(summaries)?,
+    };
+
+    match path {
+        Some(path) => fs::write(path, report + "\n").context(format!("Writing {path}")),
+        None => {
+            println!("{report}");
+            Ok(())
+        }
+    }
+}
+
+fn text_report(summary: &ApplySummary) -> String {
+    let mut report = format!("Host: {}\n", summary.hostname);
+    report += &format!(
+        "Matched MAC addresses: {}\n",
+        summary.mac_addresses.join(", ")
+    );
+
+    report += "Files:\n";
+    for file in &summary.files {
+        report += &format!(
+            "  {} -> {}\n",
+            file.source.display(),
+            file.destination.display()
+        );
+    }
+
+    report += "Renamed interfaces:\n";
+    for rename in &summary.renames {
+        let mac_address = rename.mac_address.as_deref().unwrap_or("-");
+        report += &format!("  {} -> {} ({mac_address})\n", rename.from, rename.to);
+    }
+
+    if !summary.deleted.is_empty() {
+        report += "Deleted files:\n";
+        for file in &summary.deleted {
+            report += &format!("  {}\n", file.display());
+        }
+    }
+
+    report
+}
+
+fn setup_logger(matches: &clap::ArgMatches, progress: Option<&MultiProgress>) {
+    let verbose_arg = "VERBOSE";
+
+    let mut log_builder = env_logger::Builder::new();
+    // Keep stdout reserved for data output (e.g. `--print-hosts`).
+    log_builder.target(env_logger::Target::Stderr);
+
+    log_builder.filter(None, log::LevelFilter::Info);
+    log_builder.parse_default_env();
+
+    if matches
+        .try_get_one::<bool>(verbose_arg)
+        .is_ok_and(|arg| arg.is_some_and(|&value| value))
+    {
+        log_builder.filter(None, log::LevelFilter::Debug);
+    }
+
+    if let Some(level) = matches.get_one::<String>("LOG-LEVEL") {
+        let level = level.parse().expect("validated log level");
+        log_builder.filter(None, level);
+    }
+
+    if matches.get_flag("QUIET") {
+        log_builder.filter(None, log::LevelFilter::Error);
+    }
+
+    if matches
+        .get_one::<String>("LOG-FORMAT")
+        .is_some_and(|format| format == "json")
+    {
+        log_builder.format(format_json_record);
+    }
+
+    match progress {
+        // Suspend the progress bar while printing log records so that they don't garble it.
+        Some(progress) => LogWrapper::new(progress.clone(), log_builder.build())
+            .try_init()
+            .expect("logger is only initialized once"),
+        None => log_builder.init(),
+    }
+}
+
+/// Format log records as JSON lines including any key-value pairs attached to them
+/// e.g. `info!(host = hostname; "Identified host: {hostname}")`.
+fn format_json_record(
+    buf: &mut env_logger::fmt::Formatter,
+    record: &log::Record,
+) -> std::io::Result<()> {
+    let mut entry = serde_json::Map::new();
+    entry.insert("timestamp".into(), buf.timestamp().to_string().into());
+    entry.insert("level".into(), record.level().as_str().into());
+    entry.insert("target".into(), record.target().into());
+    entry.insert("message".into(), record.args().to_string().into());
+
+    let _ = record.key_values().visit(&mut JsonFields(&mut entry));
+
+    writeln!(buf, "{}", serde_json::Value::Object(entry))
+}
+
+struct JsonFields<'a>(&'a mut serde_json::Map<String, serde_json::Value>);
+
+impl<'kvs> log::kv::VisitSource<'kvs> for JsonFields<'_> {
+    fn visit_pair(
+        &mut self,
+        key: log::kv::Key<'kvs>,
+        value: log::kv::Value<'kvs>,
+    ) -> Result<(), log::kv::Error> {
+        self.0.insert(key.to_string(), value.to_string().into());
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::{Path, PathBuf};
+    use std::sync::mpsc;
+    use std::time::{Duration, Instant};
+    use std::{fs, io, thread};
+
+    use anyhow::{anyhow, Context};
+
+    use crate::apply_conf::{apply, AppliedFile, ApplyOptions, ApplySummary, Rename};
+    use crate::error::ConfigError;
+    use crate::{
+        exit_code, parse_file_size, report_apply_summary, run_with_timeout, text_report,
+        OutputFormat, EXIT_FAILURE, EXIT_INVALID_CONFIG, EXIT_IO_ERROR, EXIT_NO_MATCHING_HOST,
+    };
+
+    #[test]
+    fn parse_file_sizes() {
+        assert_eq!(parse_file_size("512"), Ok(512));
+        assert_eq!(parse_file_size("64KiB"), Ok(64 * 1024));
+        assert_eq!(parse_file_size("10MiB"), Ok(10 * 1024 * 1024));
+        assert_eq!(parse_file_size("1GiB"), Ok(1024 * 1024 * 1024));
+        assert!(parse_file_size("10MB").is_err());
+        assert!(parse_file_size("MiB").is_err());
+    }
+
+    #[test]
+    fn exit_codes() {
+        let err = anyhow!("No Ethernet interfaces were provided")
+            .context(ConfigError::InvalidHostConfig("node1".to_string()));
+        assert_eq!(exit_code(&err), EXIT_INVALID_CONFIG);
+
+        let err = anyhow::Error::from(ConfigError::InvalidMapping("invalid".to_string()))
+            .context("Parsing config");
+        assert_eq!(exit_code(&err), EXIT_INVALID_CONFIG);
+
+        let err = anyhow::Error::from(ConfigError::NoMatchingHost);
+        assert_eq!(exit_code(&err), EXIT_NO_MATCHING_HOST);
+
+        let err = anyhow::Error::from(ConfigError::MissingInterfaces("missing".to_string()))
+            .context("Applying");
+        assert_eq!(exit_code(&err), EXIT_NO_MATCHING_HOST);
+
+        let err = Err::<(), _>(io::Error::from(io::ErrorKind::PermissionDenied))
+            .context("Copying connection files")
+            .context("Applying")
+            .unwrap_err();
+        assert_eq!(exit_code(&err), EXIT_IO_ERROR);
+
+        assert_eq!(exit_code(&anyhow!("unexpected")), EXIT_FAILURE);
+    }
+
+    #[test]
+    fn run_operation_with_timeout() {
+        let result = run_with_timeout(Duration::from_secs(5), || Ok(1));
+        assert_eq!(result.unwrap(), 1);
+
+        let err = run_with_timeout(Duration::from_millis(10), || {
+            thread::sleep(Duration::from_secs(1));
+            Ok(())
+        })
+        .unwrap_err();
+        assert_eq!(err.to_string(), "Timed out after 10ms");
+        assert_eq!(exit_code(&err), EXIT_IO_ERROR);
+    }
+
+    #[test]
+    fn apply_with_timeout_makes_no_changes_past_deadline() -> Result<(), anyhow::Error> {
+        let destination_dir = "_out_timeout";
+        let simulated = "_in_timeout.yaml";
+        fs::write(
+            simulated,
+            "- name: ens1f0\n  mac_address: 00:11:22:33:44:55\n",
+        )?;
+
+        let timeout = Duration::from_millis(10);
+        let options = ApplyOptions {
+            simulate_interfaces: Some(simulated.to_string()),
+            deadline: Some(Instant::now() + timeout),
+            ..Default::default()
+        };
+
+        // The apply is blocked (e.g. by a stalled interface lookup) until after the timeout.
+        let (release, blocked) = mpsc::channel();
+        let (finished, applied) = mpsc::channel();
+        let err = run_with_timeout(timeout, move || {
+            blocked.recv()?;
+            let result = apply("testdata/apply/golden", destination_dir, &options);
+            finished.send(result.map_err(|err| format!("{err:#}")))?;
+            Ok(())
+        })
+        .unwrap_err();
+
+        release.send(())?;
+        let applied = applied.recv()?;
+        let stored = fs::read_dir(destination_dir).map_or(0, |entries| entries.count());
+
+        fs::remove_file(simulated)?;
+        if Path::new(destination_dir).exists() {
+            fs::remove_dir_all(destination_dir)?;
+        }
+
+        assert_eq!(err.to_string(), "Timed out after 10ms");
+        assert_eq!(
+            applied.unwrap_err(),
+            "Copying connection files: Timed out before copying the connection files"
+        );
+        assert_eq!(stored, 0);
+
+        Ok(())
+    }
+
+    #[test]
+    fn apply_report() -> Result<(), anyhow::Error> {
+        let summary = ApplySummary {
+            hostname: "node1".to_string(),
+            mac_addresses: vec!["00:11:22:33:44:56".to_string()],
+            files: vec![AppliedFile {
+                source: PathBuf::from("config/node1/eth2.nmconnection"),
+                destination: PathBuf::from(
+                    "/etc/NetworkManager/system-connections/ens1f0.nmconnection",
+                ),
+            }],
+            renames: vec![Rename {
+                from: "eth2".to_string(),
+                to: "ens1f0".to_string(),
+                mac_address: Some("00:11:22:33:44:56".to_string()),
+            }],
+            deleted: Vec::new(),
+        };
+
+        assert_eq!(
+            text_report(&summary),
+            "Host: node1\n\
+            Matched MAC addresses: 00:11:22:33:44:56\n\
+            Files:\n  config/node1/eth2.nmconnection -> /etc/NetworkManager/system-connections/ens1f0.nmconnection\n\
+            Renamed interfaces:\n  eth2 -> ens1f0 (00:11:22:33:44:56)\n"
+        );
+
+        let path = "_apply_report.json";
+        report_apply_summary(
+            std::slice::from_ref(&summary),
+            false,
+            OutputFormat::Json,
+            Some(path),
+        )?;
+
+        let report: serde_json::Value = serde_json::from_str(&fs::read_to_string(path)?)?;
+        assert_eq!(report["hostname"], "node1");
+        assert_eq!(
+            report["files"][0]["source"],
+            "config/node1/eth2.nmconnection"
+        );
+        assert_eq!(report["renames"][0]["to"], "ens1f0");
+
+        // The summaries of all matched hosts are reported as an array.
+        report_apply_summary(
+            std::slice::from_ref(&summary),
+            true,
+            OutputFormat::Json,
+            Some(path),
+        )?;
+
+        let report: serde_json::Value = serde_json::from_str(&fs::read_to_string(path)?)?;
+        assert_eq!(report[0]["hostname"], "node1");
+
+        // cleanup
+        fs::remove_file(path)?;
+
+        Ok(())
+    }
+}
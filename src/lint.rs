@@ -0,0 +1,424 @@
+use std::collections::HashMap;
+use std::fmt;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use anyhow::{anyhow, Context};
+use log::{debug, info};
+use nmstate::NetworkState;
+use serde::{Deserialize, Serialize};
+
+use crate::generate_conf::{
+    collect_config_files, extract_annotations, extract_interfaces, lint_bond_options,
+    lint_dns_duplicates, lint_port_ip_config, load_base_state, read_config_file, resolve_state,
+    validate_addresses, validate_interfaces, GenerateOptions, LINT_CONFIG_FILE,
+};
+use crate::OutputFormat;
+
+/// Rule reported for the config files which can not be parsed at all. It can't be disabled.
+const INVALID_STATE_RULE: &str = "invalid-state";
+
+/// Checks run by `nmc lint` along with their default severities.
+const RULES: [Rule; 8] = [
+    Rule {
+        id: "missing-mac",
+        default_severity: Severity::Error,
+        check: |_, state| errors(validate_interfaces(&extract_interfaces(state))),
+    },
+    Rule {
+        id: "dangling-vlan-parent",
+        default_severity: Severity::Error,
+        check: |_, state| dangling_vlan_parents(state),
+    },
+    Rule {
+        id: "empty-bond",
+        default_severity: Severity::Warning,
+        check: |_, state| empty_bonds(state),
+    },
+    Rule {
+        id: "duplicate-ip",
+        default_severity: Severity::Error,
+        check: |_, state| errors(validate_addresses(state)),
+    },
+    Rule {
+        id: "dhcp-on-port",
+        default_severity: Severity::Warning,
+        check: |_, state| lint_port_ip_config(state),
+    },
+    Rule {
+        id: "duplicate-name",
+        default_severity: Severity::Error,
+        check: |value, _| duplicate_names(value),
+    },
+    Rule {
+        id: "bond-options",
+        default_severity: Severity::Warning,
+        check: |_, state| lint_bond_options(state),
+    },
+    Rule {
+        id: "duplicate-dns",
+        default_severity: Severity::Warning,
+        check: |_, state| lint_dns_duplicates(state),
+    },
+];
+
+#[derive(Deserialize, Serialize, Clone, Copy, Debug, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub(crate) enum Severity {
+    Off,
+    Warning,
+    Error,
+}
+
+impl fmt::Display for Severity {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Severity::Off => write!(f, "off"),
+            Severity::Warning => write!(f, "warning"),
+            Severity::Error => write!(f, "error"),
+        }
+    }
+}
+
+/// Check of a desired state returning a message per finding. The state is passed both as
+/// raw YAML and parsed since nmstate silently drops e.g. interfaces defined more than once.
+struct Rule {
+    id: &'static str,
+    default_severity: Severity,
+    check: fn(&serde_yaml::Value, &NetworkState) -> Vec<String>,
+}
+
+/// Severities of the rules overridden in the lint config file of the config dir e.g.
+///
+/// ```yaml
+/// rules:
+///   empty-bond: error
+///   dhcp-on-port: off
+/// ```
+#[derive(Deserialize, Default, Debug)]
+#[serde(deny_unknown_fields)]
+struct LintConfig {
+    #[serde(default)]
+    rules: HashMap<String, Severity>,
+}
+
+impl LintConfig {
+    fn load(config_dir: &Path) -> Result<Self, anyhow::Error> {
+        let path = config_dir.join(LINT_CONFIG_FILE);
+        if !path.exists() {
+            return Ok(LintConfig::default());
+        }
+
+        let config: LintConfig = serde_yaml::from_str(&fs::read_to_string(&path)?)
+            .with_context(|| format!("Parsing {path:?}"))?;
+
+        if let Some(id) = config
+            .rules
+            .keys()
+            .find(|id| !RULES.iter().any(|rule| rule.id == id.as_str()))
+        {
+            return Err(anyhow!("Unknown lint rule '{id}' in {path:?}"));
+        }
+
+        Ok(config)
+    }
+
+    fn severity(&self, rule: &Rule) -> Severity {
+        self.rules
+            .get(rule.id)
+            .copied()
+            .unwrap_or(rule.default_severity)
+    }
+}
+
+#[derive(Serialize, Debug)]
+pub(crate) struct Finding {
+    path: PathBuf,
+    rule: &'static str,
+    severity: Severity,
+    message: String,
+}
+
+impl fmt::Display for Finding {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{}: {}[{}]: {}",
+            self.path.display(),
+            self.severity,
+            self.rule,
+            self.message
+        )
+    }
+}
+
+/// Run the lint rules over every config file of the config dir printing the findings
+/// and failing if any of them is of error severity.
+pub(crate) fn lint(
+    config_dir: &str,
+    options: &GenerateOptions,
+    format: OutputFormat,
+) -> Result<(), anyhow::Error> {
+    let findings = lint_config_dir(config_dir, options)?;
+
+    match format {
+        OutputFormat::Text => findings.iter().for_each(|f| println!("{f}")),
+        OutputFormat::Json => println!("{}", serde_json::to_string_pretty(&findings)?),
+    }
+
+    let errors = findings
+        .iter()
+        .filter(|f| f.severity == Severity::Error)
+        .count();
+    info!(
+        "Found {} finding(s), {errors} of error severity",
+        findings.len()
+    );
+
+    if errors > 0 {
+        return Err(anyhow!("{errors} error(s) found in {config_dir}"));
+    }
+
+    Ok(())
+}
+
+fn lint_config_dir(
+    config_dir: &str,
+    options: &GenerateOptions,
+) -> Result<Vec<Finding>, anyhow::Error> {
+    let config = LintConfig::load(Path::new(config_dir))?;
+    debug!("Loaded lint config: {config:?}");
+
+    let (files, _) = collect_config_files(Path::new(config_dir), options)?;
+    let base = load_base_state(&[config_dir], options.max_file_size())?;
+
+    let mut findings = Vec::new();
+
+    for (path, _) in files {
+        let (value, state) = match load_state(&path, options, base.as_ref()) {
+            Ok(loaded) => loaded,
+            Err(err) => {
+                findings.push(Finding {
+                    path,
+                    rule: INVALID_STATE_RULE,
+                    severity: Severity::Error,
+                    message: format!("{err:#}"),
+                });
+                continue;
+            }
+        };
+
+        for rule in &RULES {
+            let severity = config.severity(rule);
+            if severity == Severity::Off {
+                continue;
+            }
+
+            findings.extend(
+                (rule.check)(&value, &state)
+                    .into_iter()
+                    .map(|message| Finding {
+                        path: path.clone(),
+                        rule: rule.id,
+                        severity,
+                        message,
+                    }),
+            );
+        }
+    }
+
+    Ok(findings)
+}
+
+fn load_state(
+    path: &Path,
+    options: &GenerateOptions,
+    base: Option<&serde_yaml::Value>,
+) -> Result<(serde_yaml::Value, NetworkState), anyhow::Error> {
+    let data = read_config_file(path, options.max_file_size())?;
+    let (data, _) = extract_annotations(data)?;
+    let (data, _) = resolve_state(&data, options.input_format, base)?;
+
+    let value = serde_yaml::from_str(&data).context("Parsing desired state")?;
+    let state = NetworkState::new_from_yaml(&data)?;
+
+    Ok((value, state))
+}
+
+fn errors(result: anyhow::Result<()>) -> Vec<String> {
+    result
+        .err()
+        .map(|err| format!("{err:#}"))
+        .into_iter()
+        .collect()
+}
+
+/// VLANs on top of interfaces which are not part of the desired state
+/// e.g. because of a typo in the `base-iface` or a renamed parent.
+fn dangling_vlan_parents(network_state: &NetworkState) -> Vec<String> {
+    network_state
+        .interfaces
+        .iter()
+        .filter_map(|interface| match interface {
+            nmstate::Interface::Vlan(vlan) => Some((interface.name(), vlan.vlan.as_ref()?)),
+            _ => None,
+        })
+        .filter(|(_, config)| {
+            !network_state
+                .interfaces
+                .iter()
+                .any(|i| i.name() == config.base_iface)
+        })
+        .map(|(name, config)| {
+            format!(
+                "VLAN '{name}' is on top of the undefined interface '{}'",
+                config.base_iface
+            )
+        })
+        .collect()
+}
+
+/// Bonds without ports which NetworkManager activates but never pass any traffic.
+fn empty_bonds(network_state: &NetworkState) -> Vec<String> {
+    network_state
+        .interfaces
+        .iter()
+        .filter(|i| matches!(i, nmstate::Interface::Bond(_)) && !i.is_absent())
+        .filter(|i| i.ports().unwrap_or_default().is_empty())
+        .map(|i| format!("Bond '{}' has no ports", i.name()))
+        .collect()
+}
+
+/// Interfaces defined more than once with the same type. Different types may share
+/// a name e.g. an OVS bridge and its internal interface.
+fn duplicate_names(state: &serde_yaml::Value) -> Vec<String> {
+    let mut counts: HashMap<(&str, &str), usize> = HashMap::new();
+
+    let interfaces = state
+        .get("interfaces")
+        .and_then(|i| i.as_sequence())
+        .map(Vec::as_slice)
+        .unwrap_or_default();
+
+    for interface in interfaces {
+        let Some(name) = interface.get("name").and_then(|n| n.as_str()) else {
+            continue;
+        };
+        let interface_type = interface
+            .get("type")
+            .and_then(|t| t.as_str())
+            .unwrap_or_default();
+
+        *counts.entry((name, interface_type)).or_default() += 1;
+    }
+
+    let mut duplicates: Vec<String> = counts
+        .into_iter()
+        .filter(|(_, count)| *count > 1)
+        .map(|((name, _), count)| format!("Interface '{name}' is defined {count} times"))
+        .collect();
+    duplicates.sort();
+
+    duplicates
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs;
+    use std::path::Path;
+
+    use crate::generate_conf::GenerateOptions;
+    use crate::lint::{lint_config_dir, Severity};
+
+    #[test]
+    fn lint_config_dir_reports_findings() -> Result<(), anyhow::Error> {
+        let config_dir = "_in_lint";
+        fs::create_dir_all(config_dir)?;
+
+        fs::write(
+            Path::new(config_dir).join("node1.yaml"),
+            r#"interfaces:
+  - name: eth0
+    type: ethernet
+    mac-address: 00:11:22:33:44:55
+    ipv4:
+      enabled: true
+      address:
+        - ip: 192.168.1.10
+          prefix-length: 24
+  - name: eth1
+    type: ethernet
+  - name: eth0.100
+    type: vlan
+    vlan:
+      base-iface: eth2
+      id: 100
+  - name: bond0
+    type: bond
+    link-aggregation:
+      mode: active-backup
+      port: []
+  - name: eth3
+    type: ethernet
+    mac-address: 00:11:22:33:44:66
+    ipv4:
+      enabled: true
+      address:
+        - ip: 192.168.1.10
+          prefix-length: 24
+  - name: eth3
+    type: ethernet
+    mac-address: 00:11:22:33:44:66
+"#,
+        )?;
+        fs::write(Path::new(config_dir).join("node2.yaml"), "interfaces: [")?;
+        fs::write(
+            Path::new(config_dir).join(".nmc-lint.yml"),
+            "rules:\n  empty-bond: error\n  duplicate-ip: off\n",
+        )?;
+
+        let findings = lint_config_dir(config_dir, &GenerateOptions::default());
+
+        let unknown_rule = Path::new(config_dir).join("unknown");
+        fs::create_dir_all(&unknown_rule)?;
+        fs::write(
+            unknown_rule.join(".nmc-lint.yml"),
+            "rules:\n  missing-macs: off\n",
+        )?;
+        let unknown_rule = lint_config_dir(
+            unknown_rule.to_str().expect("valid path"),
+            &GenerateOptions::default(),
+        );
+
+        fs::remove_dir_all(config_dir)?;
+
+        let findings: Vec<(String, &str, Severity)> = findings?
+            .into_iter()
+            .map(|f| {
+                let file = f.path.file_name().unwrap().to_string_lossy().into_owned();
+                (file, f.rule, f.severity)
+            })
+            .collect();
+        assert_eq!(
+            findings,
+            [
+                ("node1.yaml".to_string(), "missing-mac", Severity::Error),
+                (
+                    "node1.yaml".to_string(),
+                    "dangling-vlan-parent",
+                    Severity::Error
+                ),
+                ("node1.yaml".to_string(), "empty-bond", Severity::Error),
+                ("node1.yaml".to_string(), "duplicate-name", Severity::Error),
+                ("node2.yaml".to_string(), "invalid-state", Severity::Error),
+            ]
+        );
+
+        assert!(unknown_rule
+            .unwrap_err()
+            .to_string()
+            .contains("Unknown lint rule 'missing-macs'"));
+
+        Ok(())
+    }
+}
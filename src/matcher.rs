@@ -0,0 +1,267 @@
+use log::debug;
+use network_interface::NetworkInterface;
+
+use crate::apply_conf::{local_mac_addresses, match_host, match_host_with_ratio};
+use crate::types::Host;
+
+/// Hostname which is set before the actual one is configured and therefore never identifies a host.
+const DEFAULT_HOSTNAME: &str = "localhost";
+
+/// Placeholders reported by firmwares which do not set a serial number.
+const PLACEHOLDER_SERIALS: [&str; 6] = [
+    "0",
+    "None",
+    "Not Specified",
+    "Default string",
+    "System Serial Number",
+    "To be filled by O.E.M.",
+];
+
+/// Strategy identifying which of the preconfigured hosts the configurations are applied to.
+pub(crate) trait HostMatcher {
+    /// Name of the strategy used in log messages.
+    fn name(&self) -> &'static str;
+
+    /// Find the host which is confidently matched by the strategy, if any.
+    fn find<'a>(
+        &self,
+        hosts: &'a [Host],
+        network_interfaces: &[NetworkInterface],
+    ) -> Option<&'a Host>;
+}
+
+/// Strategies selectable via `nmc apply --match`.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub(crate) enum MatchStrategy {
+    Mac,
+    Hostname,
+    Serial,
+}
+
+impl MatchStrategy {
+    pub(crate) fn from_arg(value: &str) -> Self {
+        match value {
+            "hostname" => MatchStrategy::Hostname,
+            "serial" => MatchStrategy::Serial,
+            _ => MatchStrategy::Mac,
+        }
+    }
+}
+
+/// Matches the MAC addresses of the preconfigured interfaces against the ones of the NICs.
+/// See [`match_host`] for the details.
+///
+/// Optionally, only hosts with at least the given share of their physical interfaces
+/// present among the NICs are considered.
+#[derive(Default)]
+pub(crate) struct MacMatcher {
+    min_match_ratio: Option<f64>,
+}
+
+impl MacMatcher {
+    pub(crate) fn with_min_match_ratio(min_match_ratio: Option<f64>) -> Self {
+        MacMatcher { min_match_ratio }
+    }
+}
+
+impl HostMatcher for MacMatcher {
+    fn name(&self) -> &'static str {
+        "MAC address"
+    }
+
+    fn find<'a>(
+        &self,
+        hosts: &'a [Host],
+        network_interfaces: &[NetworkInterface],
+    ) -> Option<&'a Host> {
+        let mac_addresses = local_mac_addresses(network_interfaces);
+
+        match self.min_match_ratio {
+            Some(min_match_ratio) => match_host_with_ratio(hosts, &mac_addresses, min_match_ratio),
+            None => match_host(hosts, &mac_addresses),
+        }
+    }
+}
+
+/// Matches the preconfigured hostnames against the current hostname of the host
+/// e.g. in case it was already set during provisioning or by a previous run.
+///
+/// The match is only considered confident if exactly one host has the hostname.
+pub(crate) struct HostnameMatcher {
+    hostname: String,
+}
+
+impl HostnameMatcher {
+    pub(crate) fn new(hostname: &str) -> Self {
+        HostnameMatcher {
+            hostname: hostname.trim().to_owned(),
+        }
+    }
+}
+
+impl HostMatcher for HostnameMatcher {
+    fn name(&self) -> &'static str {
+        "hostname"
+    }
+
+    fn find<'a>(&self, hosts: &'a [Host], _: &[NetworkInterface]) -> Option<&'a Host> {
+        if self.hostname.is_empty() || self.hostname == DEFAULT_HOSTNAME {
+            return None;
+        }
+
+        unique_match(hosts, |host| host.hostname == self.hostname)
+    }
+}
+
+/// Matches the preconfigured serial numbers against the DMI product serial of the host
+/// e.g. in case cloned VMs do not have reliably unique MAC addresses.
+///
+/// The match is only considered confident if exactly one host has the serial number.
+pub(crate) struct SerialMatcher {
+    serial: String,
+}
+
+impl SerialMatcher {
+    pub(crate) fn new(serial: &str) -> Self {
+        SerialMatcher {
+            serial: serial.trim().to_owned(),
+        }
+    }
+}
+
+impl HostMatcher for SerialMatcher {
+    fn name(&self) -> &'static str {
+        "serial number"
+    }
+
+    fn find<'a>(&self, hosts: &'a [Host], _: &[NetworkInterface]) -> Option<&'a Host> {
+        if self.serial.is_empty() || PLACEHOLDER_SERIALS.contains(&self.serial.as_str()) {
+            return None;
+        }
+
+        unique_match(hosts, |host| {
+            host.serial
+                .as_deref()
+                .is_some_and(|serial| serial.trim() == self.serial)
+        })
+    }
+}
+
+fn unique_match(hosts: &[Host], predicate: impl Fn(&Host) -> bool) -> Option<&Host> {
+    let mut matches = hosts.iter().filter(|host| predicate(host));
+    match (matches.next(), matches.next()) {
+        (Some(host), None) => Some(host),
+        _ => None,
+    }
+}
+
+/// Try the matchers in the given order returning the host found by the first one which matches.
+pub(crate) fn find_host(
+    matchers: &[Box<dyn HostMatcher>],
+    hosts: &[Host],
+    network_interfaces: &[NetworkInterface],
+) -> Option<Host> {
+    matchers.iter().find_map(|matcher| {
+        let host = matcher.find(hosts, network_interfaces)?;
+        debug!("Matched host {} by {}", host.hostname, matcher.name());
+
+        Some(host.clone())
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use network_interface::NetworkInterface;
+
+    use crate::matcher::{find_host, HostMatcher, HostnameMatcher, MacMatcher, SerialMatcher};
+    use crate::types::{Host, Interface};
+
+    fn host(hostname: &str, mac: &str) -> Host {
+        Host {
+            hostname: hostname.to_string(),
+            serial: None,
+            interfaces: vec![Interface {
+                logical_name: "eth0".to_string(),
+                mac_address: Some(mac.to_string()),
+                additional_macs: Vec::new(),
+                predictable_name: None,
+                interface_type: "ethernet".to_string(),
+            }],
+        }
+    }
+
+    #[test]
+    fn match_host_by_hostname() {
+        let hosts = vec![
+            host("node1", "00:11:22:33:44:55"),
+            host("node2", "00:11:22:33:44:56"),
+            host("node2", "00:11:22:33:44:57"),
+        ];
+
+        let found = HostnameMatcher::new("node1\n").find(&hosts, &[]);
+        assert_eq!(found.map(|h| h.hostname.as_str()), Some("node1"));
+
+        // Ambiguous and default hostnames are not a confident match.
+        assert!(HostnameMatcher::new("node2").find(&hosts, &[]).is_none());
+        assert!(HostnameMatcher::new("localhost")
+            .find(&hosts, &[])
+            .is_none());
+        assert!(HostnameMatcher::new("node3").find(&hosts, &[]).is_none());
+    }
+
+    #[test]
+    fn match_host_by_serial() {
+        let serial_host = |hostname: &str, serial: Option<&str>| Host {
+            serial: serial.map(str::to_string),
+            ..host(hostname, "00:11:22:33:44:55")
+        };
+        let hosts = vec![
+            serial_host("node1", Some("ABC123")),
+            serial_host("node2", Some("DEF456")),
+            serial_host("node3", Some("DEF456")),
+            serial_host("node4", None),
+        ];
+
+        let found = SerialMatcher::new("ABC123\n").find(&hosts, &[]);
+        assert_eq!(found.map(|h| h.hostname.as_str()), Some("node1"));
+
+        // Ambiguous and placeholder serials are not a confident match.
+        assert!(SerialMatcher::new("DEF456").find(&hosts, &[]).is_none());
+        assert!(SerialMatcher::new("Not Specified")
+            .find(&hosts, &[])
+            .is_none());
+        assert!(SerialMatcher::new("").find(&hosts, &[]).is_none());
+        assert!(SerialMatcher::new("GHI789").find(&hosts, &[]).is_none());
+    }
+
+    #[test]
+    fn find_host_tries_matchers_in_order() {
+        let hosts = vec![
+            host("node1", "00:11:22:33:44:55"),
+            host("node2", "00:11:22:33:44:56"),
+        ];
+        let interfaces = [NetworkInterface {
+            name: "eth0".to_string(),
+            mac_addr: Some("00:11:22:33:44:55".to_string()),
+            addr: vec![],
+            index: 0,
+        }];
+
+        let matchers: Vec<Box<dyn HostMatcher>> = vec![
+            Box::new(HostnameMatcher::new("node2")),
+            Box::new(MacMatcher::default()),
+        ];
+        let found = find_host(&matchers, &hosts, &interfaces);
+        assert_eq!(found.map(|h| h.hostname), Some("node2".to_string()));
+
+        let matchers: Vec<Box<dyn HostMatcher>> = vec![
+            Box::new(HostnameMatcher::new("localhost")),
+            Box::new(MacMatcher::default()),
+        ];
+        let found = find_host(&matchers, &hosts, &interfaces);
+        assert_eq!(found.map(|h| h.hostname), Some("node1".to_string()));
+
+        let matchers: Vec<Box<dyn HostMatcher>> = vec![Box::new(HostnameMatcher::new("node3"))];
+        assert!(find_host(&matchers, &hosts, &interfaces).is_none());
+    }
+}
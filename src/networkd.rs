@@ -0,0 +1,341 @@
+use std::collections::HashMap;
+
+use anyhow::anyhow;
+use nmstate::{Interface, InterfaceType, NetworkState, RouteState};
+
+use crate::keyfile::Keyfile;
+
+const NETWORK_FILE_EXT: &str = "network";
+const NETDEV_FILE_EXT: &str = "netdev";
+
+/// Translate the desired state into systemd-networkd `.netdev` and `.network` files in the
+/// following format: `Vec<(file_name, file_content)>`.
+///
+/// nmstate only generates NetworkManager configurations so only the commonly used
+/// interface types (Ethernet, bond, bridge, VLAN and dummy) and their IP settings are supported.
+pub(crate) fn gen_networkd_conf(
+    network_state: &NetworkState,
+) -> Result<Vec<(String, String)>, anyhow::Error> {
+    let interfaces: Vec<&Interface> = network_state
+        .interfaces
+        .iter()
+        .filter(|i| !i.is_absent() && !i.is_ignore())
+        .filter(|i| i.iface_type() != InterfaceType::Loopback)
+        .collect();
+
+    // Ports and VLANs are declared in the .network files of their ports and parents respectively.
+    let mut controllers: HashMap<&str, (&str, &str)> = HashMap::new();
+    let mut vlans: HashMap<&str, Vec<&str>> = HashMap::new();
+
+    for interface in &interfaces {
+        let kind = match interface.iface_type() {
+            InterfaceType::Bond => "Bond",
+            InterfaceType::LinuxBridge => "Bridge",
+            _ => "",
+        };
+
+        if !kind.is_empty() {
+            for port in interface.ports().unwrap_or_default() {
+                controllers.insert(port, (kind, interface.name()));
+            }
+        }
+
+        if let Interface::Vlan(vlan) = interface {
+            if let Some(config) = &vlan.vlan {
+                vlans
+                    .entry(config.base_iface.as_str())
+                    .or_default()
+                    .push(vlan.base.name.as_str());
+            }
+        }
+    }
+
+    let gateways = default_gateways(network_state);
+    let has_gateways = !gateways.is_empty();
+    let dns_config = network_state
+        .dns
+        .as_ref()
+        .and_then(|dns| dns.config.as_ref());
+    let dns_servers: &[String] = dns_config
+        .and_then(|config| config.server.as_deref())
+        .unwrap_or_default();
+    let dns_domains: &[String] = dns_config
+        .and_then(|config| config.search.as_deref())
+        .unwrap_or_default();
+
+    let mut config = Vec::new();
+
+    for interface in interfaces {
+        let name = interface.name();
+
+        if let Some(netdev) = netdev(interface)? {
+            config.push((format!("{name}.{NETDEV_FILE_EXT}"), netdev.to_string()));
+        }
+
+        let mut network = Keyfile::default();
+        network.add("Match", "Name", name);
+
+        if let Some(mtu) = interface.base_iface().mtu {
+            network.add("Link", "MTUBytes", &mtu.to_string());
+        }
+
+        if let Some((kind, controller)) = controllers.get(name) {
+            network.add("Network", kind, controller);
+        } else {
+            let link_gateways = gateways.get(name).map(Vec::as_slice).unwrap_or_default();
+            add_ip_config(&mut network, interface, link_gateways);
+
+            // DNS servers are configured per link in systemd-networkd so they are attached
+            // to the links with a default gateway or to all links if there are none.
+            if !link_gateways.is_empty() || (!has_gateways && has_ip_config(interface)) {
+                for server in dns_servers {
+                    network.add("Network", "DNS", server);
+                }
+                if !dns_domains.is_empty() {
+                    network.add("Network", "Domains", &dns_domains.join(" "));
+                }
+            }
+
+            add_routes(&mut network, network_state, name);
+        }
+
+        for vlan in vlans.get(name).map(Vec::as_slice).unwrap_or_default() {
+            network.add("Network", "VLAN", vlan);
+        }
+
+        config.push((format!("{name}.{NETWORK_FILE_EXT}"), network.to_string()));
+    }
+
+    config.sort();
+
+    Ok(config)
+}
+
+fn netdev(interface: &Interface) -> Result<Option<Keyfile>, anyhow::Error> {
+    let kind = match interface {
+        Interface::Ethernet(_) => return Ok(None),
+        Interface::Bond(_) => "bond",
+        Interface::LinuxBridge(_) => "bridge",
+        Interface::Vlan(_) => "vlan",
+        Interface::Dummy(_) => "dummy",
+        _ => {
+            return Err(anyhow!(
+                "Interface '{}' of type {} is not supported by the networkd backend",
+                interface.name(),
+                interface.iface_type()
+            ))
+        }
+    };
+
+    let mut netdev = Keyfile::default();
+    netdev.add("NetDev", "Name", interface.name());
+    netdev.add("NetDev", "Kind", kind);
+
+    if let Some(mtu) = interface.base_iface().mtu {
+        netdev.add("NetDev", "MTUBytes", &mtu.to_string());
+    }
+
+    match interface {
+        Interface::Bond(bond) => {
+            let mode = bond.bond.as_ref().and_then(|config| config.mode);
+            if let Some(mode) = mode.and_then(|m| serde_json::to_value(m).ok()) {
+                netdev.add("Bond", "Mode", mode.as_str().unwrap_or_default());
+            }
+        }
+        Interface::Vlan(vlan) => {
+            let config = vlan
+                .vlan
+                .as_ref()
+                .ok_or_else(|| anyhow!("VLAN '{}' is missing its config", interface.name()))?;
+            netdev.add("VLAN", "Id", &config.id.to_string());
+        }
+        _ => {}
+    }
+
+    Ok(Some(netdev))
+}
+
+fn has_ip_config(interface: &Interface) -> bool {
+    let base = interface.base_iface();
+
+    base.ipv4.as_ref().is_some_and(|ip| ip.enabled)
+        || base.ipv6.as_ref().is_some_and(|ip| ip.enabled)
+}
+
+fn add_ip_config(network: &mut Keyfile, interface: &Interface, gateways: &[&str]) {
+    let base = interface.base_iface();
+    let ipv4 = base.ipv4.as_ref().filter(|ip| ip.enabled);
+    let ipv6 = base.ipv6.as_ref().filter(|ip| ip.enabled);
+
+    let dhcp4 = ipv4.is_some_and(|ip| ip.dhcp == Some(true));
+    let dhcp6 = ipv6.is_some_and(|ip| ip.dhcp == Some(true));
+    let dhcp = match (dhcp4, dhcp6) {
+        (true, true) => "yes",
+        (true, false) => "ipv4",
+        (false, true) => "ipv6",
+        (false, false) => "no",
+    };
+    network.add("Network", "DHCP", dhcp);
+
+    match ipv6 {
+        Some(ip) => {
+            let accept_ra = if ip.autoconf == Some(true) {
+                "yes"
+            } else {
+                "no"
+            };
+            network.add("Network", "IPv6AcceptRA", accept_ra);
+        }
+        None => network.add("Network", "LinkLocalAddressing", "no"),
+    }
+
+    let addresses = ipv4
+        .and_then(|ip| ip.addresses.as_deref())
+        .unwrap_or_default()
+        .iter()
+        .chain(
+            ipv6.and_then(|ip| ip.addresses.as_deref())
+                .unwrap_or_default(),
+        );
+
+    for address in addresses {
+        network.add(
+            "Network",
+            "Address",
+            &format!("{}/{}", address.ip, address.prefix_length),
+        );
+    }
+
+    for gateway in gateways {
+        network.add("Network", "Gateway", gateway);
+    }
+}
+
+fn is_default_destination(destination: Option<&str>) -> bool {
+    matches!(destination, Some("0.0.0.0/0" | "::/0"))
+}
+
+/// Default gateways keyed by their next hop interfaces.
+fn default_gateways(network_state: &NetworkState) -> HashMap<&str, Vec<&str>> {
+    let mut gateways: HashMap<&str, Vec<&str>> = HashMap::new();
+
+    for route in network_state.routes.config.as_deref().unwrap_or_default() {
+        if route.state == Some(RouteState::Absent)
+            || !is_default_destination(route.destination.as_deref())
+        {
+            continue;
+        }
+
+        if let (Some(interface), Some(gateway)) = (&route.next_hop_iface, &route.next_hop_addr) {
+            gateways.entry(interface).or_default().push(gateway);
+        }
+    }
+
+    gateways
+}
+
+/// Add a `[Route]` section for every non-default route via the interface.
+fn add_routes(network: &mut Keyfile, network_state: &NetworkState, name: &str) {
+    let routes = network_state
+        .routes
+        .config
+        .as_deref()
+        .unwrap_or_default()
+        .iter()
+        .filter(|r| r.state != Some(RouteState::Absent))
+        .filter(|r| r.next_hop_iface.as_deref() == Some(name))
+        .filter(|r| !is_default_destination(r.destination.as_deref()));
+
+    for route in routes {
+        network.add_section("Route");
+
+        if let Some(destination) = &route.destination {
+            network.add("Route", "Destination", destination);
+        }
+        if let Some(gateway) = &route.next_hop_addr {
+            network.add("Route", "Gateway", gateway);
+        }
+        if let Some(metric) = route.metric {
+            network.add("Route", "Metric", &metric.to_string());
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+    use std::fs;
+
+    use nmstate::NetworkState;
+
+    use crate::convert::{to_nmstate, InputFormat};
+    use crate::networkd::gen_networkd_conf;
+
+    #[test]
+    fn gen_networkd_conf_from_cloud_init() -> Result<(), anyhow::Error> {
+        let data = fs::read_to_string("testdata/cloud-init/node1.yaml")?;
+        let (data, _) = to_nmstate(&data, InputFormat::CloudInit)?;
+        let network_state = NetworkState::new_from_yaml(&data)?;
+
+        let config: HashMap<String, String> =
+            gen_networkd_conf(&network_state)?.into_iter().collect();
+
+        assert_eq!(config.len(), 7);
+        assert_eq!(
+            config["bond0.netdev"],
+            "[NetDev]\nName=bond0\nKind=bond\n\n[Bond]\nMode=active-backup\n"
+        );
+        assert_eq!(
+            config["bond0.network"],
+            "[Match]\nName=bond0\n\n[Network]\nDHCP=no\nIPv6AcceptRA=no\n\
+            Address=192.168.1.10/24\nAddress=2001:db8::10/64\nGateway=192.168.1.1\n\
+            DNS=192.168.1.1\nDomains=example.com\nVLAN=bond0.100\n"
+        );
+        assert_eq!(
+            config["eth0.network"],
+            "[Match]\nName=eth0\n\n[Network]\nBond=bond0\n"
+        );
+        assert_eq!(
+            config["eth2.network"],
+            "[Match]\nName=eth2\n\n[Network]\nDHCP=ipv4\nLinkLocalAddressing=no\n"
+        );
+        assert_eq!(
+            config["bond0.100.netdev"],
+            "[NetDev]\nName=bond0.100\nKind=vlan\n\n[VLAN]\nId=100\n"
+        );
+        assert_eq!(
+            config["bond0.100.network"],
+            "[Match]\nName=bond0.100\n\n[Network]\nDHCP=no\nLinkLocalAddressing=no\n\
+            Address=10.0.100.10/24\n\n[Route]\nDestination=10.10.0.0/16\n\
+            Gateway=10.0.100.1\nMetric=100\n"
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn gen_networkd_conf_fails_for_unsupported_types() -> Result<(), anyhow::Error> {
+        let network_state = NetworkState::new_from_yaml(
+            r#"
+            interfaces:
+              - name: br0
+                type: ovs-bridge
+                state: up
+                bridge:
+                  port:
+                    - name: eth0
+              - name: eth0
+                type: ethernet
+                state: up
+            "#,
+        )?;
+
+        let error = gen_networkd_conf(&network_state).unwrap_err();
+        assert_eq!(
+            error.to_string(),
+            "Interface 'br0' of type ovs-bridge is not supported by the networkd backend"
+        );
+
+        Ok(())
+    }
+}
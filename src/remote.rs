@@ -0,0 +1,172 @@
+use std::collections::HashMap;
+use std::io::Write;
+use std::path::Path;
+use std::process::{Command, Stdio};
+
+use anyhow::{anyhow, Context};
+use log::debug;
+use network_interface::NetworkInterface;
+
+use crate::apply_conf::{
+    parse_altnames, InterfaceSource, DMI_PRODUCT_SERIAL_FILE, KERNEL_HOSTNAME_FILE,
+    LIST_ALTNAMES_CMD,
+};
+
+/// Lists the names and MAC addresses of all network interfaces in the format `<name> <mac>`.
+const LIST_INTERFACES_CMD: &str =
+    r#"for i in /sys/class/net/*; do printf '%s %s\n' "${i##*/}" "$(cat "$i/address")"; done"#;
+
+/// Remote host accessed via the system `ssh` client.
+///
+/// Authentication is left to the SSH client (e.g. keys loaded in `ssh-agent`) and
+/// interactive prompts are disabled so that a missing key fails instead of hanging.
+pub(crate) struct Remote {
+    destination: String,
+}
+
+impl Remote {
+    /// `destination` is anything accepted by `ssh` e.g. `user@host`.
+    pub(crate) fn new(destination: &str) -> Self {
+        Remote {
+            destination: destination.to_owned(),
+        }
+    }
+
+    /// Run the command on the remote host and return its stdout.
+    pub(crate) fn run(&self, command: &str, stdin: Option<&[u8]>) -> Result<String, anyhow::Error> {
+        debug!("Running '{command}' on {}", self.destination);
+
+        let mut child = Command::new("ssh")
+            .args(["-o", "BatchMode=yes", "--", &self.destination, command])
+            .stdin(Stdio::piped())
+            .stdout(Stdio::piped())
+            .stderr(Stdio::piped())
+            .spawn()
+            .context("Spawning ssh")?;
+
+        {
+            let mut child_stdin = child.stdin.take().expect("stdin is piped");
+            if let Some(input) = stdin {
+                child_stdin.write_all(input).context("Writing to ssh")?;
+            }
+        }
+
+        let output = child.wait_with_output().context("Waiting for ssh")?;
+        if !output.status.success() {
+            return Err(anyhow!(
+                "Remote command '{command}' failed: {}",
+                String::from_utf8_lossy(&output.stderr).trim()
+            ));
+        }
+
+        Ok(String::from_utf8_lossy(&output.stdout).into_owned())
+    }
+
+    /// Write the contents to the file on the remote host, creating its parent dir if necessary.
+    pub(crate) fn write_file(
+        &self,
+        path: &Path,
+        contents: &str,
+        mode: u32,
+    ) -> Result<(), anyhow::Error> {
+        let path = path
+            .to_str()
+            .ok_or_else(|| anyhow!("Invalid path: {path:?}"))?;
+        let dir = Path::new(path)
+            .parent()
+            .and_then(Path::to_str)
+            .ok_or_else(|| anyhow!("Determining parent dir of {path}"))?;
+
+        let command = format!(
+            "mkdir -p {dir} && umask 077 && cat > {path} && chmod {mode:o} {path}",
+            dir = shell_quote(dir),
+            path = shell_quote(path),
+        );
+
+        self.run(&command, Some(contents.as_bytes())).map(|_| ())
+    }
+
+    /// Retrieve the network interfaces of the remote host.
+    pub(crate) fn network_interfaces(&self) -> Result<Vec<NetworkInterface>, anyhow::Error> {
+        let output = self.run(LIST_INTERFACES_CMD, None)?;
+
+        Ok(parse_network_interfaces(&output))
+    }
+
+    /// Retrieve the current hostname of the remote host.
+    pub(crate) fn hostname(&self) -> Result<String, anyhow::Error> {
+        self.run(&format!("cat {KERNEL_HOSTNAME_FILE}"), None)
+    }
+
+    /// Retrieve the serial number of the remote host.
+    pub(crate) fn serial(&self) -> Result<String, anyhow::Error> {
+        self.run(&format!("cat {DMI_PRODUCT_SERIAL_FILE}"), None)
+    }
+
+    /// Retrieve the alternative names of the remote NICs keyed by their primary names.
+    pub(crate) fn altnames(&self) -> Result<HashMap<String, Vec<String>>, anyhow::Error> {
+        let output = self.run(LIST_ALTNAMES_CMD, None)?;
+
+        parse_altnames(&output)
+    }
+}
+
+impl InterfaceSource for Remote {
+    fn network_interfaces(&self) -> Result<Vec<NetworkInterface>, anyhow::Error> {
+        Remote::network_interfaces(self).context("Retrieving remote network interfaces")
+    }
+
+    fn altnames(&self) -> Result<HashMap<String, Vec<String>>, anyhow::Error> {
+        Remote::altnames(self)
+    }
+}
+
+fn parse_network_interfaces(output: &str) -> Vec<NetworkInterface> {
+    output
+        .lines()
+        .filter_map(|line| line.split_once(' '))
+        .map(|(name, mac)| NetworkInterface {
+            name: name.to_owned(),
+            mac_addr: Some(mac.trim().to_lowercase()).filter(|mac| !mac.is_empty()),
+            addr: vec![],
+            index: 0,
+        })
+        .collect()
+}
+
+fn shell_quote(value: &str) -> String {
+    format!("'{}'", value.replace('\'', r"'\''"))
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::remote::{parse_network_interfaces, shell_quote};
+
+    #[test]
+    fn parse_remote_network_interfaces() {
+        let output = "eth0 00:11:22:AA:BB:CC\nlo 00:00:00:00:00:00\ntun0 \n";
+
+        let interfaces: Vec<(String, Option<String>)> = parse_network_interfaces(output)
+            .into_iter()
+            .map(|i| (i.name, i.mac_addr))
+            .collect();
+
+        assert_eq!(
+            interfaces,
+            vec![
+                ("eth0".to_string(), Some("00:11:22:aa:bb:cc".to_string())),
+                ("lo".to_string(), Some("00:00:00:00:00:00".to_string())),
+                ("tun0".to_string(), None),
+            ]
+        );
+    }
+
+    #[test]
+    fn quote_shell_argument() {
+        assert_eq!(
+            shell_quote("/etc/eth0.nmconnection"),
+            "'/etc/eth0.nmconnection'"
+        );
+        assert_eq!(shell_quote("it's"), r"'it'\''s'");
+    }
+}
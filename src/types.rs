@@ -1,18 +1,86 @@
+use schemars::{JsonSchema, Schema};
 use serde::{Deserialize, Serialize};
 
-#[derive(Serialize, Deserialize, Debug)]
+/// Host and the preconfigured interfaces used to identify it.
+#[derive(Serialize, Deserialize, JsonSchema, Debug, Clone)]
 #[cfg_attr(test, derive(PartialEq))]
 pub struct Host {
+    /// Host identifier, usually the hostname.
     pub(crate) hostname: String,
     pub(crate) interfaces: Vec<Interface>,
+    /// Serial number of the machine (DMI product serial) identifying the host
+    /// in case its MAC addresses are not reliably unique e.g. for cloned VMs.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    #[serde(default)]
+    pub(crate) serial: Option<String>,
 }
 
-#[derive(Serialize, Deserialize, Debug)]
+impl Host {
+    /// Host identifier, usually the hostname.
+    pub fn hostname(&self) -> &str {
+        &self.hostname
+    }
+}
+
+/// Network interface of a host.
+#[derive(Serialize, Deserialize, JsonSchema, Debug, Clone)]
 #[cfg_attr(test, derive(PartialEq))]
 pub struct Interface {
+    /// Name of the interface in the desired state e.g. `eth0`.
     pub(crate) logical_name: String,
+    /// MAC address of the interface. Not available for virtual interfaces e.g. bonds.
     #[serde(skip_serializing_if = "Option::is_none")]
     #[serde(default)]
     pub(crate) mac_address: Option<String>,
+    /// Further MAC addresses identifying the interface e.g. the ones of the ports of a bond.
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    #[serde(default)]
+    pub(crate) additional_macs: Vec<String>,
+    /// Name the interface is expected to get under the naming scheme selected on generate
+    /// e.g. `enx001122334455`. The interface is renamed to it on apply.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    #[serde(default)]
+    pub(crate) predictable_name: Option<String>,
+    /// nmstate interface type e.g. `ethernet`.
     pub(crate) interface_type: String,
 }
+
+/// Provenance of the generated configurations.
+#[derive(Serialize, Deserialize, Debug)]
+#[cfg_attr(test, derive(PartialEq))]
+pub(crate) struct Metadata {
+    pub(crate) nmstate_version: String,
+    pub(crate) nmc_version: String,
+    /// RFC 3339 timestamp of the generation.
+    pub(crate) generated_at: String,
+}
+
+/// JSON Schema describing the host mapping file.
+pub(crate) fn host_mapping_schema() -> Schema {
+    schemars::schema_for!(Vec<Host>)
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::types::host_mapping_schema;
+
+    #[test]
+    fn generate_host_mapping_schema() {
+        let schema = serde_json::to_value(host_mapping_schema()).unwrap();
+
+        assert_eq!(schema["type"], "array");
+        assert_eq!(schema["items"]["$ref"], "#/$defs/Host");
+        assert_eq!(
+            schema["$defs"]["Host"]["required"],
+            serde_json::json!(["hostname", "interfaces"])
+        );
+        assert_eq!(
+            schema["$defs"]["Interface"]["required"],
+            serde_json::json!(["logical_name", "interface_type"])
+        );
+        assert_eq!(
+            schema["$defs"]["Interface"]["properties"]["mac_address"]["type"],
+            serde_json::json!(["string", "null"])
+        );
+    }
+}